@@ -0,0 +1,87 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use stix_patterns_parser::json::to_json;
+use stix_patterns_parser::parser::parse_pattern;
+use stix_patterns_parser::transform::{normalize, simplify};
+
+fn simple(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simple");
+    for (name, input) in [
+        ("string", "[file:name = 'a.exe']"),
+        (
+            "hash",
+            "[file:hashes.'SHA-256' = 'aec070645fe53ee3b3763059376134f058cc337247c978add178b6ccdfb0019f']",
+        ),
+        (
+            "list",
+            "[ipv4-addr:value IN ('10.0.0.1', '10.0.0.2', '10.0.0.3')]",
+        ),
+        ("exists", "[EXISTS process:pid]"),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| parse_pattern(black_box(input)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn or_chain(c: &mut Criterion) {
+    let mut group = c.benchmark_group("or_chain");
+    for n in [10, 100, 1000, 10_000] {
+        let comparisons: Vec<String> = (0..n).map(|i| format!("file:name = '{i}.exe'")).collect();
+        let input = format!("[{}]", comparisons.join(" OR "));
+        group.bench_function(n.to_string(), |b| {
+            b.iter(|| parse_pattern(black_box(&input)).unwrap());
+        });
+    }
+    group.finish();
+}
+
+/// The walks that take a loop rather than recursion down long chains.
+fn chain_walks(c: &mut Criterion) {
+    let comparisons: Vec<String> = (0..10_000).map(|i| format!("a:x = {i}")).collect();
+    let expr = parse_pattern(&format!("[{}]", comparisons.join(" AND "))).unwrap();
+    let mut group = c.benchmark_group("chain_walks");
+    group.bench_function("to_string", |b| b.iter(|| black_box(&expr).to_string()));
+    group.bench_function("to_json", |b| b.iter(|| to_json(black_box(&expr))));
+    group.bench_function("normalize", |b| {
+        b.iter(|| normalize(black_box(expr.clone())));
+    });
+    group.bench_function("simplify", |b| {
+        b.iter(|| simplify(black_box(expr.clone())));
+    });
+    group.finish();
+}
+
+fn nesting(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nesting");
+    for n in [10, 100] {
+        let input = format!("[{}a:x = 1 OR a:y = 2{}]", "(".repeat(n), ")".repeat(n));
+        group.bench_function(n.to_string(), |b| {
+            b.iter(|| parse_pattern(black_box(&input)).unwrap());
+        });
+    }
+    group.finish();
+}
+
+fn qualifiers(c: &mut Criterion) {
+    let observations: Vec<String> = (0..20)
+        .map(|i| {
+            format!(
+                "[file:size > {i}] REPEATS {} TIMES WITHIN {i}.5 SECONDS",
+                i + 1
+            )
+        })
+        .collect();
+    let input = format!(
+        "({}) START t'2023-01-01T00:00:00Z' STOP t'2023-06-01T00:00:00Z'",
+        observations.join(" FOLLOWEDBY ")
+    );
+    c.bench_function("qualifiers", |b| {
+        b.iter(|| parse_pattern(black_box(&input)).unwrap());
+    });
+}
+
+criterion_group!(benches, simple, or_chain, chain_walks, nesting, qualifiers);
+criterion_main!(benches);
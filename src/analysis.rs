@@ -0,0 +1,1025 @@
+//! Read-only queries over a parsed pattern.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+use crate::ast::{
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, ComparisonRhs,
+    CompositeComparison, CompositePattern, ObservationOp, PathComponent, PatternExpr,
+    QualifiedPattern, SourceSpan, StixValue,
+};
+use crate::parser::{self, ParseError};
+
+impl ComparisonExpr {
+    /// Levels of nesting, 1 for a single comparison.
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        match self {
+            Self::Single(_) => 1,
+            Self::Composite(c) => 1 + c.left.depth().max(c.right.depth()),
+        }
+    }
+
+    /// Leaf comparisons in document order.
+    #[must_use]
+    pub fn comparisons(&self) -> Vec<&Comparison> {
+        let mut out = Vec::new();
+        collect_comparisons(self, &mut out);
+        out
+    }
+
+    /// The object types the expression needs, as alternatives: observed
+    /// data can only match if it holds every type of one of the sets.
+    /// Ordered by size, then by name, without sets that include another.
+    ///
+    /// `AND` combines every alternative of one side with every one of the
+    /// other, so their number can grow exponentially with the input. `None`
+    /// is returned as soon as a step would combine more than
+    /// [`MAX_ALTERNATIVES`] sets; [`common_objects`](Self::common_objects)
+    /// is then still available.
+    #[must_use]
+    pub fn required_objects(&self) -> Option<Vec<BTreeSet<&str>>> {
+        match self {
+            Self::Single(c) => Some(vec![BTreeSet::from([&*c.object_path.object_type])]),
+            Self::Composite(c) => {
+                let left = c.left.required_objects()?;
+                let right = c.right.required_objects()?;
+                let sets = match c.op {
+                    BooleanOp::And => {
+                        if left.len().saturating_mul(right.len()) > MAX_ALTERNATIVES {
+                            return None;
+                        }
+                        left.iter()
+                            .flat_map(|l| right.iter().map(move |r| l | r))
+                            .collect()
+                    }
+                    BooleanOp::Or => [left, right].concat(),
+                };
+                Some(minimal_sets(sets)).filter(|sets| sets.len() <= MAX_ALTERNATIVES)
+            }
+        }
+    }
+
+    /// The object types every alternative of
+    /// [`required_objects`](Self::required_objects) holds, computed without
+    /// enumerating the alternatives.
+    #[must_use]
+    pub fn common_objects(&self) -> BTreeSet<&str> {
+        match self {
+            Self::Single(c) => BTreeSet::from([&*c.object_path.object_type]),
+            Self::Composite(c) => {
+                let (left, right) = (c.left.common_objects(), c.right.common_objects());
+                match c.op {
+                    BooleanOp::And => &left | &right,
+                    BooleanOp::Or => &left & &right,
+                }
+            }
+        }
+    }
+}
+
+/// Most alternatives [`ComparisonExpr::required_objects`] computes.
+pub const MAX_ALTERNATIVES: usize = 1024;
+
+/// `sets` sorted and without duplicates or sets including another.
+fn minimal_sets(mut sets: Vec<BTreeSet<&str>>) -> Vec<BTreeSet<&str>> {
+    sets.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    let mut out: Vec<BTreeSet<&str>> = Vec::with_capacity(sets.len());
+    for set in sets {
+        if !out.iter().any(|kept| kept.is_subset(&set)) {
+            out.push(set);
+        }
+    }
+    out
+}
+
+impl PatternExpr {
+    /// Leaf comparisons of every observation, in document order.
+    #[must_use]
+    pub fn comparisons(&self) -> Vec<&Comparison> {
+        let mut out = Vec::new();
+        for observation in self.observations() {
+            collect_comparisons(observation, &mut out);
+        }
+        out
+    }
+
+    /// The bracketed observation expressions, in document order.
+    #[must_use]
+    pub fn observations(&self) -> Vec<&ComparisonExpr> {
+        let mut out = Vec::new();
+        collect_observations(self, &mut out);
+        out
+    }
+
+    /// Number of bracketed observations, the leaves of the tree of
+    /// observation operators and qualifiers. The comparisons inside an
+    /// observation count once, however they are combined.
+    #[must_use]
+    pub fn observation_count(&self) -> usize {
+        match self {
+            Self::Comparison(_) => 1,
+            Self::Composite(c) => c.left.observation_count() + c.right.observation_count(),
+            Self::Qualified(q) => q.pattern.observation_count(),
+        }
+    }
+
+    /// The leaf comparisons of each observation, in document order, without
+    /// the operators joining them.
+    #[must_use]
+    pub fn observation_groups(&self) -> Vec<Vec<&Comparison>> {
+        self.observations()
+            .into_iter()
+            .map(ComparisonExpr::comparisons)
+            .collect()
+    }
+
+    /// [`ComparisonExpr::required_objects`] of each observation, in
+    /// document order.
+    #[must_use]
+    pub fn required_objects(&self) -> Vec<Option<Vec<BTreeSet<&str>>>> {
+        self.observations()
+            .into_iter()
+            .map(ComparisonExpr::required_objects)
+            .collect()
+    }
+
+    /// Comparisons on `object_type` whose first or last path component is
+    /// `property`. Components carrying a list index only match when
+    /// `ignore_indices` is set.
+    #[must_use]
+    pub fn find_comparisons(
+        &self,
+        object_type: &str,
+        property: &str,
+        ignore_indices: bool,
+    ) -> Vec<&Comparison> {
+        let matches = |component: Option<&PathComponent>| {
+            component.is_some_and(|c| {
+                *c.property == *property && (ignore_indices || c.indices.is_empty())
+            })
+        };
+        self.comparisons()
+            .into_iter()
+            .filter(|c| {
+                let path = &c.object_path;
+                *path.object_type == *object_type
+                    && (matches(path.property_path.first()) || matches(path.property_path.last()))
+            })
+            .collect()
+    }
+
+    /// Every constant compared against, in document order, with `IN` lists
+    /// flattened. Object paths on the right-hand side are not constants.
+    #[must_use]
+    pub fn constants(&self) -> Vec<&StixValue> {
+        self.comparisons()
+            .into_iter()
+            .flat_map(|c| match &c.constant {
+                Some(ComparisonRhs::Value(value)) => std::slice::from_ref(value),
+                Some(ComparisonRhs::List(values)) => values.as_slice(),
+                Some(ComparisonRhs::Path(_)) | None => &[],
+            })
+            .collect()
+    }
+
+    /// Whether any comparison is on an object type matching `glob`, where
+    /// `*` matches any run of characters and `?` exactly one, as in
+    /// `network-*`.
+    #[must_use]
+    pub fn matches_object_type(&self, glob: &str) -> bool {
+        self.comparisons()
+            .iter()
+            .any(|c| glob_match(glob, &c.object_path.object_type))
+    }
+
+    /// Whether any comparison of the pattern uses `op`.
+    #[must_use]
+    pub fn contains_operator(&self, op: impl Into<ComparisonOperator>) -> bool {
+        let op = op.into();
+        self.comparisons().iter().any(|c| c.op == op)
+    }
+
+    /// The distinct operators of the pattern, in order of first appearance.
+    #[must_use]
+    pub fn operators_used(&self) -> Vec<ComparisonOperator> {
+        let mut operators = Vec::new();
+        for c in self.comparisons() {
+            if !operators.contains(&c.op) {
+                operators.push(c.op);
+            }
+        }
+        operators
+    }
+
+    /// Whether this is a single bracketed observation.
+    #[must_use]
+    pub fn is_single_observation(&self) -> bool {
+        matches!(self, Self::Comparison(_))
+    }
+
+    /// Whether this combines observations with `AND`, `OR` or `FOLLOWEDBY`.
+    #[must_use]
+    pub fn is_composite(&self) -> bool {
+        matches!(self, Self::Composite(_))
+    }
+
+    /// Whether this node is qualified, not whether any node below is: see
+    /// [`has_qualifiers`](Self::has_qualifiers).
+    #[must_use]
+    pub fn is_qualified(&self) -> bool {
+        matches!(self, Self::Qualified(_))
+    }
+
+    /// Whether this is the pattern `[path op constant]`: one comparison
+    /// with a constant, optionally negated, without qualifiers.
+    #[must_use]
+    pub fn is_trivial(&self) -> bool {
+        matches!(
+            self,
+            Self::Comparison(ComparisonExpr::Single(c))
+                if matches!(c.op, ComparisonOperator::Comparison(_))
+                    && !matches!(c.constant, None | Some(ComparisonRhs::Path(_)))
+        )
+    }
+
+    /// Levels of nesting, counting every composite and qualified node and
+    /// the comparisons inside observations, so `[a:x = 1]` has depth 1.
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        match self {
+            Self::Comparison(c) => c.depth(),
+            Self::Composite(c) => 1 + c.left.depth().max(c.right.depth()),
+            Self::Qualified(q) => 1 + q.pattern.depth(),
+        }
+    }
+
+    /// Whether any node of the pattern carries a qualifier.
+    #[must_use]
+    pub fn has_qualifiers(&self) -> bool {
+        match self {
+            Self::Comparison(_) => false,
+            Self::Composite(c) => c.left.has_qualifiers() || c.right.has_qualifiers(),
+            Self::Qualified(_) => true,
+        }
+    }
+
+    /// Distinct object types, in order of first appearance.
+    #[must_use]
+    pub fn object_types(&self) -> Vec<String> {
+        let mut object_types: Vec<String> = Vec::new();
+        for c in self.comparisons() {
+            let object_type = &*c.object_path.object_type;
+            if !object_types.iter().any(|t| t == object_type) {
+                object_types.push(object_type.to_owned());
+            }
+        }
+        object_types
+    }
+
+    /// Every qualifier with the object types of the pattern it applies to,
+    /// inner qualifiers first and otherwise in document order.
+    #[must_use]
+    pub fn qualifiers(&self) -> Vec<QualifierInfo> {
+        let mut out = Vec::new();
+        collect_qualifiers(self, &mut out);
+        out
+    }
+
+    /// The first node for which `predicate` holds, visiting parents before
+    /// their operands and operands in document order.
+    pub fn find_node<'a>(
+        &'a self,
+        mut predicate: impl FnMut(NodeRef<'a>) -> bool,
+    ) -> Option<NodeRef<'a>> {
+        find_pattern_node(self, &mut predicate)
+    }
+}
+
+/// A borrowed node of any of the four node classes.
+///
+/// Nodes compare by value and spans never affect equality, so a node built
+/// or kept apart from the source can be found by comparing with it, as in
+/// `locate(input, |node| node == NodeRef::Comparison(&c))`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeRef<'a> {
+    Comparison(&'a Comparison),
+    CompositeComparison(&'a CompositeComparison),
+    CompositePattern(&'a CompositePattern),
+    QualifiedPattern(&'a QualifiedPattern),
+}
+
+impl NodeRef<'_> {
+    #[must_use]
+    pub fn span(self) -> SourceSpan {
+        match self {
+            Self::Comparison(c) => c.span,
+            Self::CompositeComparison(c) => c.span,
+            Self::CompositePattern(c) => c.span,
+            Self::QualifiedPattern(q) => q.span,
+        }
+    }
+}
+
+fn find_pattern_node<'a>(
+    expr: &'a PatternExpr,
+    predicate: &mut dyn FnMut(NodeRef<'a>) -> bool,
+) -> Option<NodeRef<'a>> {
+    let pattern_node = match expr {
+        PatternExpr::Comparison(c) => return find_comparison_node(c, predicate),
+        PatternExpr::Composite(c) => NodeRef::CompositePattern(c),
+        PatternExpr::Qualified(q) => NodeRef::QualifiedPattern(q),
+    };
+    if predicate(pattern_node) {
+        return Some(pattern_node);
+    }
+    match expr {
+        PatternExpr::Comparison(_) => None,
+        PatternExpr::Composite(c) => {
+            find_pattern_node(&c.left, predicate).or_else(|| find_pattern_node(&c.right, predicate))
+        }
+        PatternExpr::Qualified(q) => find_pattern_node(&q.pattern, predicate),
+    }
+}
+
+fn find_comparison_node<'a>(
+    expr: &'a ComparisonExpr,
+    predicate: &mut dyn FnMut(NodeRef<'a>) -> bool,
+) -> Option<NodeRef<'a>> {
+    match expr {
+        ComparisonExpr::Single(c) => Some(NodeRef::Comparison(c)).filter(|&n| predicate(n)),
+        ComparisonExpr::Composite(c) => {
+            let node = NodeRef::CompositeComparison(c);
+            if predicate(node) {
+                return Some(node);
+            }
+            find_comparison_node(&c.left, predicate)
+                .or_else(|| find_comparison_node(&c.right, predicate))
+        }
+    }
+}
+
+/// Parse `input` and return the source span of its first node for which
+/// `predicate` holds, in the order of [`PatternExpr::find_node`], for
+/// tooling that works on the AST and needs to point back at the source.
+///
+/// An observation's span excludes its brackets; qualified patterns span
+/// their qualifiers too. `None` when no node matches.
+pub fn locate(
+    input: &str,
+    predicate: impl FnMut(NodeRef<'_>) -> bool,
+) -> Result<Option<SourceSpan>, ParseError> {
+    let expr = parser::parse_pattern(input)?;
+    Ok(expr.find_node(predicate).map(NodeRef::span))
+}
+
+/// A `REPEATS`, `WITHIN` or `START ... STOP` qualifier.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Qualifier {
+    Repeats(u32),
+    Within(f64),
+    Interval {
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+    },
+}
+
+impl Qualifier {
+    /// `"repeats"`, `"within"` or `"interval"`.
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Repeats(_) => "repeats",
+            Self::Within(_) => "within",
+            Self::Interval { .. } => "interval",
+        }
+    }
+}
+
+/// A qualifier and the distinct object types of the pattern it qualifies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualifierInfo {
+    pub qualifier: Qualifier,
+    pub object_types: Vec<String>,
+}
+
+fn collect_qualifiers(expr: &PatternExpr, out: &mut Vec<QualifierInfo>) {
+    match expr {
+        PatternExpr::Comparison(_) => {}
+        PatternExpr::Composite(c) => {
+            collect_qualifiers(&c.left, out);
+            collect_qualifiers(&c.right, out);
+        }
+        PatternExpr::Qualified(q) => {
+            collect_qualifiers(&q.pattern, out);
+            let object_types = q.pattern.object_types();
+            let qualifiers = [
+                q.repeat.map(Qualifier::Repeats),
+                q.within.map(Qualifier::Within),
+                (q.start.is_some() || q.stop.is_some()).then_some(Qualifier::Interval {
+                    start: q.start,
+                    stop: q.stop,
+                }),
+            ];
+            out.extend(
+                qualifiers
+                    .into_iter()
+                    .flatten()
+                    .map(|qualifier| QualifierInfo {
+                        qualifier,
+                        object_types: object_types.clone(),
+                    }),
+            );
+        }
+    }
+}
+
+fn collect_comparisons<'a>(expr: &'a ComparisonExpr, out: &mut Vec<&'a Comparison>) {
+    match expr {
+        ComparisonExpr::Single(c) => out.push(c),
+        ComparisonExpr::Composite(c) => {
+            collect_comparisons(&c.left, out);
+            collect_comparisons(&c.right, out);
+        }
+    }
+}
+
+fn collect_observations<'a>(expr: &'a PatternExpr, out: &mut Vec<&'a ComparisonExpr>) {
+    match expr {
+        PatternExpr::Comparison(c) => out.push(c),
+        PatternExpr::Composite(c) => {
+            collect_observations(&c.left, out);
+            collect_observations(&c.right, out);
+        }
+        PatternExpr::Qualified(q) => collect_observations(&q.pattern, out),
+    }
+}
+
+/// Collapse a chain of composites sharing the same boolean operator into its
+/// operands. Operands joined by a different operator, or negated as a whole,
+/// are kept whole; a leaf comparison or negated group yields no operator and
+/// itself as the single operand.
+#[must_use]
+pub fn flatten_comparison(expr: &ComparisonExpr) -> (Option<BooleanOp>, Vec<&ComparisonExpr>) {
+    fn walk<'a>(expr: &'a ComparisonExpr, op: BooleanOp, out: &mut Vec<&'a ComparisonExpr>) {
+        match expr {
+            ComparisonExpr::Composite(c) if c.op == op && !c.negated => {
+                walk(&c.left, op, out);
+                walk(&c.right, op, out);
+            }
+            _ => out.push(expr),
+        }
+    }
+
+    match expr {
+        ComparisonExpr::Composite(c) if !c.negated => {
+            let mut operands = Vec::new();
+            walk(expr, c.op, &mut operands);
+            (Some(c.op), operands)
+        }
+        _ => (None, vec![expr]),
+    }
+}
+
+/// Pattern-level equivalent of [`flatten_comparison`] for observation
+/// operators. Qualified patterns and observations are not descended into.
+#[must_use]
+pub fn flatten_pattern(expr: &PatternExpr) -> (Option<ObservationOp>, Vec<&PatternExpr>) {
+    fn walk<'a>(expr: &'a PatternExpr, op: ObservationOp, out: &mut Vec<&'a PatternExpr>) {
+        match expr {
+            PatternExpr::Composite(c) if c.op == op => {
+                walk(&c.left, op, out);
+                walk(&c.right, op, out);
+            }
+            _ => out.push(expr),
+        }
+    }
+
+    match expr {
+        PatternExpr::Composite(c) => {
+            let mut operands = Vec::new();
+            walk(expr, c.op, &mut operands);
+            (Some(c.op), operands)
+        }
+        _ => (None, vec![expr]),
+    }
+}
+
+/// String constants an object path is compared against with `=`, `IN` or
+/// `ISSUBSET`, keyed by the path as written in STIX (e.g. `ipv4-addr:value`
+/// or `file:hashes.'SHA-256'`).
+///
+/// Values keep their document order without duplicates. Negated
+/// comparisons, and operators whose operand is a pattern rather than a
+/// value, are skipped. A comparison inside a negated group, as in
+/// `NOT (a AND b)`, counts as negated, unless it is negated itself.
+#[must_use]
+pub fn extract_iocs(expr: &PatternExpr) -> BTreeMap<String, Vec<String>> {
+    let mut iocs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    // Expressions left to visit, with whether an odd number of negated
+    // groups hold them.
+    let mut pending: Vec<(&ComparisonExpr, bool)> = expr
+        .observations()
+        .into_iter()
+        .rev()
+        .map(|observation| (observation, false))
+        .collect();
+    while let Some((expr, negated)) = pending.pop() {
+        let c = match expr {
+            ComparisonExpr::Single(c) => c,
+            ComparisonExpr::Composite(c) => {
+                let negated = negated != c.negated;
+                pending.extend([(&*c.right, negated), (&*c.left, negated)]);
+                continue;
+            }
+        };
+        let ComparisonOperator::Comparison(
+            ComparisonOp::Eq | ComparisonOp::In | ComparisonOp::IsSubset,
+        ) = c.op
+        else {
+            continue;
+        };
+        if c.negated != negated {
+            continue;
+        }
+        let values: Vec<&StixValue> = match &c.constant {
+            Some(ComparisonRhs::Value(v)) => vec![v],
+            Some(ComparisonRhs::List(values)) => values.iter().collect(),
+            _ => continue,
+        };
+        for value in values {
+            if let StixValue::String(s) = value {
+                let entry = iocs.entry(c.object_path.to_string()).or_default();
+                if !entry.contains(s) {
+                    entry.push(s.clone());
+                }
+            }
+        }
+    }
+    iocs
+}
+
+/// Lightweight metadata about a pattern, computed without converting the
+/// AST to Python objects.
+#[cfg_attr(feature = "python", pyclass(frozen, module = "stix_patterns_parser"))]
+#[derive(Debug, Clone)]
+pub struct PatternSummary {
+    /// Distinct object types, in order of first appearance.
+    pub object_types: Vec<String>,
+    /// Distinct operators, in order of first appearance.
+    pub operators_used: Vec<ComparisonOperator>,
+    pub has_qualifiers: bool,
+    pub observation_count: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PatternSummary {
+    #[getter]
+    fn object_types(&self) -> Vec<String> {
+        self.object_types.clone()
+    }
+
+    #[getter]
+    fn has_qualifiers(&self) -> bool {
+        self.has_qualifiers
+    }
+
+    #[getter]
+    fn observation_count(&self) -> usize {
+        self.observation_count
+    }
+
+    #[getter]
+    fn operators_used(&self, py: Python<'_>) -> PyResult<Vec<Py<PyAny>>> {
+        self.operators_used
+            .iter()
+            .map(|op| op.to_pyobject(py))
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PatternSummary(object_types={:?}, has_qualifiers={}, observation_count={}, ...)",
+            self.object_types, self.has_qualifiers, self.observation_count
+        )
+    }
+}
+
+/// Summarize the object types, operators and structure of a pattern.
+#[must_use]
+pub fn summarize(expr: &PatternExpr) -> PatternSummary {
+    PatternSummary {
+        object_types: expr.object_types(),
+        operators_used: expr.operators_used(),
+        has_qualifiers: expr.has_qualifiers(),
+        observation_count: expr.observation_count(),
+    }
+}
+
+fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    // Position of the last `*` and of the text it currently absorbs up to.
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, t));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_g, star_t)) => {
+                    star = Some((star_g, star_t + 1));
+                    g = star_g + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{ComparisonOp, ObjectPath, UnaryOp};
+    use crate::parser::{ParseOptions, parse_pattern, parse_pattern_with_options};
+
+    #[test]
+    fn test_summarize() {
+        let expr = parse_pattern(
+            "[file:name = 'a' AND EXISTS file:size] FOLLOWEDBY ([url:value = 'b'] OR [file:name = 'c']) WITHIN 5 SECONDS",
+        )
+        .unwrap();
+        let summary = summarize(&expr);
+        assert_eq!(summary.object_types, ["file", "url"]);
+        assert_eq!(
+            summary.operators_used,
+            [
+                ComparisonOperator::from(ComparisonOp::Eq),
+                ComparisonOperator::from(UnaryOp::Exists)
+            ]
+        );
+        assert!(summary.has_qualifiers);
+        assert_eq!(summary.observation_count, 3);
+    }
+
+    #[test]
+    fn test_operators_used() {
+        let expr = parse_pattern(
+            "([a:x = 1 AND (a:y LIKE 'b' OR EXISTS a:z)] FOLLOWEDBY [a:x = 2]) \
+             REPEATS 2 TIMES OR [b:w NOT IN (1, 2)]",
+        )
+        .unwrap();
+        assert_eq!(
+            expr.operators_used(),
+            [
+                ComparisonOperator::from(ComparisonOp::Eq),
+                ComparisonOperator::from(ComparisonOp::Like),
+                ComparisonOperator::from(UnaryOp::Exists),
+                ComparisonOperator::from(ComparisonOp::In),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observation_groups() {
+        let expr = parse_pattern(
+            "([a:x = 1 AND (a:y = 2 OR a:z = 3)] FOLLOWEDBY [b:x = 4]) WITHIN 5 SECONDS \
+             OR [c:x = 5]",
+        )
+        .unwrap();
+        let groups: Vec<Vec<String>> = expr
+            .observation_groups()
+            .iter()
+            .map(|group| group.iter().map(ToString::to_string).collect())
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                vec!["a:x = 1", "a:y = 2", "a:z = 3"],
+                vec!["b:x = 4"],
+                vec!["c:x = 5"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_required_objects() {
+        let required = |input: &str| -> Vec<Vec<String>> {
+            let expr = parse_pattern(input).unwrap();
+            expr.required_objects()
+                .into_iter()
+                .map(|alternatives| {
+                    alternatives
+                        .unwrap()
+                        .iter()
+                        .map(|set| set.iter().copied().collect::<Vec<_>>().join("+"))
+                        .collect()
+                })
+                .collect()
+        };
+        assert_eq!(
+            required("[file:name = 'a' AND network-traffic:dst_port = 80]"),
+            [["file+network-traffic"]]
+        );
+        assert_eq!(
+            required("[url:value = 'a' OR file:name = 'b' OR file:size = 1]"),
+            [["file", "url"]]
+        );
+        assert_eq!(
+            required(
+                "[(file:name = 'a' OR url:value = 'b') AND (file:size = 1 OR process:pid = 2)]"
+            ),
+            [["file", "process+url"]]
+        );
+        assert_eq!(
+            required("[a:x = 1 AND (a:y = 2 OR b:z = 3)] FOLLOWEDBY [c:x = 4]"),
+            [vec!["a"], vec!["c"]]
+        );
+
+        // 2^24 alternatives, all minimal.
+        let groups: Vec<_> = (0..24)
+            .map(|i| format!("(a{i}:x = 1 OR b{i}:x = 1)"))
+            .collect();
+        let input = format!("[{} AND c:x = 1]", groups.join(" AND "));
+        let expr = parse_pattern(&input).unwrap();
+        assert_eq!(expr.required_objects(), [None]);
+        let ComparisonExpr::Composite(c) = expr.observations()[0] else {
+            panic!("expected a composite");
+        };
+        assert_eq!(c.left.common_objects(), BTreeSet::new());
+        assert_eq!(
+            expr.observations()[0].common_objects(),
+            BTreeSet::from(["c"])
+        );
+
+        // Alternatives that collapse stay within the limit.
+        let groups: Vec<_> = (0..24).map(|_| "(a:x = 1 OR a:y = 1)").collect();
+        let expr = parse_pattern(&format!("[{}]", groups.join(" AND "))).unwrap();
+        assert_eq!(expr.required_objects(), [Some(vec![BTreeSet::from(["a"])])]);
+    }
+
+    #[test]
+    fn test_qualifiers() {
+        let expr = parse_pattern(
+            "([file:name = 'a'] REPEATS 2 TIMES WITHIN 5 SECONDS AND [url:value = 'b']) \
+             START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'",
+        )
+        .unwrap();
+        let qualifiers = expr.qualifiers();
+        assert_eq!(
+            qualifiers
+                .iter()
+                .map(|q| (q.qualifier.kind(), q.object_types.join(",")))
+                .collect::<Vec<_>>(),
+            [
+                ("repeats", "file".to_owned()),
+                ("within", "file".to_owned()),
+                ("interval", "file,url".to_owned()),
+            ]
+        );
+        assert_eq!(qualifiers[0].qualifier, Qualifier::Repeats(2));
+        assert_eq!(qualifiers[1].qualifier, Qualifier::Within(5.0));
+        assert!(matches!(
+            qualifiers[2].qualifier,
+            Qualifier::Interval { start: Some(start), stop: Some(stop) } if start < stop
+        ));
+        assert!(parse_pattern("[a:x = 1]").unwrap().qualifiers().is_empty());
+    }
+
+    #[test]
+    fn test_depth() {
+        let depth = |input: &str| parse_pattern(input).unwrap().depth();
+        assert_eq!(depth("[a:x = 1]"), 1);
+        assert_eq!(depth("[a:x = 1 AND (a:y = 2 OR a:z = 3)]"), 3);
+        assert_eq!(depth("[a:x = 1] AND [a:y = 2]"), 2);
+        assert_eq!(
+            depth("([a:x = 1] AND [a:y = 2 OR a:z = 3]) WITHIN 5 SECONDS"),
+            4
+        );
+        assert_eq!(depth("[a:x = 1] REPEATS 2 TIMES WITHIN 5 SECONDS"), 3);
+    }
+
+    #[test]
+    fn test_locate() {
+        let input = "[a:x = 1 AND a:y = 2] OR ([b:z = 'q'] WITHIN 5 SECONDS)";
+        let bounds =
+            |span: Option<SourceSpan>| span.map(|s| (s.start().unwrap(), s.end().unwrap()));
+        let on_path = |object_type: &'static str, property: &'static str| {
+            move |node: NodeRef<'_>| {
+                matches!(node, NodeRef::Comparison(c)
+                    if *c.object_path.object_type == *object_type
+                        && *c.object_path.property_path[0].property == *property)
+            }
+        };
+        assert_eq!(
+            bounds(locate(input, on_path("a", "y")).unwrap()),
+            Some((13, 20))
+        );
+        let composite = |node: NodeRef<'_>| matches!(node, NodeRef::CompositeComparison(_));
+        assert_eq!(bounds(locate(input, composite).unwrap()), Some((1, 20)));
+        let qualified = |node: NodeRef<'_>| matches!(node, NodeRef::QualifiedPattern(_));
+        assert_eq!(bounds(locate(input, qualified).unwrap()), Some((26, 54)));
+
+        let built = Comparison::new(
+            ObjectPath::new("b", vec![PathComponent::new("z", None)]),
+            ComparisonOp::Eq,
+            Some(StixValue::from("q").into()),
+            false,
+        );
+        let same = |node: NodeRef<'_>| node == NodeRef::Comparison(&built);
+        assert_eq!(bounds(locate(input, same).unwrap()), Some((27, 36)));
+
+        assert_eq!(locate(input, on_path("c", "x")).unwrap(), None);
+        assert!(locate("[a:x =", |_| true).is_err());
+    }
+
+    #[test]
+    fn test_observation_count() {
+        let count = |input: &str| parse_pattern(input).unwrap().observation_count();
+        assert_eq!(count("[a:x = 1 AND (a:y = 2 OR a:z = 3)]"), 1);
+        assert_eq!(count("[a:x = 1] AND [a:y = 2 OR a:z = 3]"), 2);
+        assert_eq!(
+            count("([a:x = 1] FOLLOWEDBY [a:y = 2]) REPEATS 2 TIMES OR [a:z = 3] WITHIN 5 SECONDS"),
+            3
+        );
+    }
+
+    #[test]
+    fn test_classification() {
+        let classify = |input: &str| {
+            let expr = parse_pattern(input).unwrap();
+            (
+                expr.is_single_observation(),
+                expr.is_composite(),
+                expr.is_qualified(),
+            )
+        };
+        assert_eq!(classify("[a:x = 1 AND a:y = 2]"), (true, false, false));
+        assert_eq!(classify("[a:x = 1] OR [a:y = 2]"), (false, true, false));
+        assert_eq!(
+            classify("([a:x = 1] OR [a:y = 2]) WITHIN 5 SECONDS"),
+            (false, false, true)
+        );
+
+        let is_trivial = |input: &str| parse_pattern(input).unwrap().is_trivial();
+        assert!(is_trivial("[a:x = 1]"));
+        assert!(is_trivial("[a:x NOT IN (1, 2)]"));
+        assert!(!is_trivial("[EXISTS a:x]"));
+        assert!(!is_trivial("[a:x = 1 AND a:y = 2]"));
+        assert!(!is_trivial("[a:x = 1] REPEATS 2 TIMES"));
+        assert!(!is_trivial("[a:x = 1] OR [a:y = 2]"));
+    }
+
+    #[test]
+    fn test_matches_object_type() {
+        let expr = parse_pattern("[file:name = 'a'] OR [network-traffic:dst_port = 80]").unwrap();
+        for glob in ["network-*", "*traffic", "file", "f??e", "*", "n*-t*c"] {
+            assert!(expr.matches_object_type(glob), "{glob}");
+        }
+        for glob in ["network", "process*", "?", "file?", ""] {
+            assert!(!expr.matches_object_type(glob), "{glob}");
+        }
+    }
+
+    #[test]
+    fn test_constants() {
+        let expr = parse_pattern(
+            "[file:name = 'a' AND EXISTS file:x] FOLLOWEDBY \
+             [file:size IN (1, 2) OR file:magic = h'ff'] REPEATS 2 TIMES",
+        )
+        .unwrap();
+        assert_eq!(
+            expr.constants(),
+            [
+                &StixValue::from("a"),
+                &StixValue::Int(1),
+                &StixValue::Int(2),
+                &StixValue::Hex("ff".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flatten_comparison() {
+        let PatternExpr::Comparison(expr) = parse_pattern(
+            "[a:x = 1 AND a:y = 2 AND (a:z = 3 AND a:w = 4) AND (a:v = 5 OR a:u = 6)]",
+        )
+        .unwrap() else {
+            unreachable!()
+        };
+        let (op, operands) = flatten_comparison(&expr);
+        assert_eq!(op, Some(BooleanOp::And));
+        let operands: Vec<_> = operands.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            operands,
+            [
+                "a:x = 1",
+                "a:y = 2",
+                "a:z = 3",
+                "a:w = 4",
+                "a:v = 5 OR a:u = 6"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flatten_pattern() {
+        let expr = parse_pattern("[a:x = 1] OR [a:y = 2] OR ([a:z = 3] AND [a:w = 4])").unwrap();
+        let (op, operands) = flatten_pattern(&expr);
+        assert_eq!(op, Some(ObservationOp::Or));
+        assert_eq!(operands.len(), 3);
+
+        let leaf = parse_pattern("[a:x = 1] WITHIN 5 SECONDS").unwrap();
+        assert_eq!(flatten_pattern(&leaf).0, None);
+    }
+
+    #[test]
+    fn test_find_comparisons() {
+        let expr = parse_pattern(
+            "[file:hashes.'SHA-256' = 'a' AND file:name = 'b'] OR [file:hashes.'SHA-256' = 'c' \
+             AND url:hashes.'SHA-256' = 'd' AND file:extensions[0].'SHA-256' = 'e']",
+        )
+        .unwrap();
+        let found: Vec<_> = expr
+            .find_comparisons("file", "SHA-256", false)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "file:hashes.'SHA-256' = 'a'",
+                "file:hashes.'SHA-256' = 'c'",
+                "file:extensions[0].'SHA-256' = 'e'"
+            ]
+        );
+        assert_eq!(expr.find_comparisons("file", "hashes", false).len(), 2);
+        assert!(
+            expr.find_comparisons("file", "extensions", false)
+                .is_empty()
+        );
+        assert_eq!(expr.find_comparisons("file", "extensions", true).len(), 1);
+    }
+
+    #[test]
+    fn test_extract_iocs() {
+        let expr = parse_pattern(
+            "[ipv4-addr:value = '10.0.0.1' OR ipv4-addr:value ISSUBSET '10.1.0.0/16'] \
+             AND [domain-name:value IN ('a.com', 'b.com', 'a.com') AND domain-name:value != 'c.com'] \
+             FOLLOWEDBY [file:hashes.'SHA-256' = 'ab' AND file:name LIKE '%.exe' AND file:size = 3]",
+        )
+        .unwrap();
+        let iocs = extract_iocs(&expr);
+        assert_eq!(
+            iocs.into_iter().collect::<Vec<_>>(),
+            [
+                (
+                    "domain-name:value".to_owned(),
+                    vec!["a.com".to_owned(), "b.com".to_owned()]
+                ),
+                ("file:hashes.'SHA-256'".to_owned(), vec!["ab".to_owned()]),
+                (
+                    "ipv4-addr:value".to_owned(),
+                    vec!["10.0.0.1".to_owned(), "10.1.0.0/16".to_owned()]
+                ),
+            ]
+        );
+
+        let options = ParseOptions {
+            allow_grouped_negation: true,
+            ..Default::default()
+        };
+        let expr = parse_pattern_with_options(
+            "[NOT (file:name = 'a' AND file:name NOT = 'b') AND file:name = 'c']",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_iocs(&expr).into_iter().collect::<Vec<_>>(),
+            [("file:name".to_owned(), vec!["b".to_owned(), "c".to_owned()])]
+        );
+    }
+
+    #[test]
+    fn test_contains_operator() {
+        let expr =
+            parse_pattern("[a:x = 1] FOLLOWEDBY ([a:y MATCHES 'b' OR EXISTS a:z] REPEATS 2 TIMES)")
+                .unwrap();
+        assert!(expr.contains_operator(ComparisonOp::Matches));
+        assert!(expr.contains_operator(UnaryOp::Exists));
+        assert!(!expr.contains_operator(ComparisonOp::Like));
+    }
+}
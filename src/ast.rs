@@ -1,37 +1,61 @@
-use chrono::{DateTime, Datelike, Timelike, Utc};
+use std::cmp::Ordering;
+use std::sync::{Arc, LazyLock};
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "python")]
+use chrono::{Datelike, NaiveDate, SecondsFormat, Timelike};
+#[cfg(feature = "python")]
+use pyo3::exceptions::{PyTypeError, PyValueError};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
-use pyo3::types::PyDateTime;
+#[cfg(feature = "python")]
+use pyo3::types::{
+    PyBool, PyDateAccess, PyDateTime, PyFloat, PyInt, PyList, PyString, PyTimeAccess, PyTuple,
+    PyType, PyTzInfo, PyTzInfoAccess,
+};
 
-#[pyclass(frozen, eq, eq_int)]
+#[cfg_attr(
+    feature = "python",
+    pyclass(
+        frozen,
+        eq,
+        eq_int,
+        hash,
+        rename_all = "UPPERCASE",
+        module = "stix_patterns_parser"
+    )
+)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ComparisonOp {
-    #[pyo3(name = "EQ")]
     Eq,
-    #[pyo3(name = "NEQ")]
     Neq,
-    #[pyo3(name = "GT")]
     Gt,
-    #[pyo3(name = "LT")]
     Lt,
-    #[pyo3(name = "GE")]
     Ge,
-    #[pyo3(name = "LE")]
     Le,
-    #[pyo3(name = "IN")]
     In,
-    #[pyo3(name = "LIKE")]
     Like,
-    #[pyo3(name = "MATCHES")]
     Matches,
-    #[pyo3(name = "ISSUBSET")]
     IsSubset,
-    #[pyo3(name = "ISSUPERSET")]
     IsSuperset,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl ComparisonOp {
-    fn __repr__(&self) -> &'static str {
+    fn __copy__(&self) -> Self {
+        *self
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        *self
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<EnumReduce<'py>> {
+        reduce_enum_member::<Self>(py, self.__repr__())
+    }
+
+    pub(crate) fn __repr__(&self) -> &'static str {
         match self {
             Self::Eq => "ComparisonOp.EQ",
             Self::Neq => "ComparisonOp.NEQ",
@@ -47,8 +71,41 @@ impl ComparisonOp {
         }
     }
 
-    #[getter]
-    fn value(&self) -> &'static str {
+    #[getter(value)]
+    fn py_value(&self) -> &'static str {
+        self.value()
+    }
+
+    #[getter(code)]
+    fn py_code(&self) -> u8 {
+        self.code()
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_code")]
+    fn py_from_code(code: u8) -> PyResult<Self> {
+        Self::from_code(code).ok_or_else(|| unknown_code::<Self>(code))
+    }
+}
+
+impl ComparisonOp {
+    /// Every operator, in the order of their [`code`](Self::code)s.
+    pub const ALL: [Self; 11] = [
+        Self::Eq,
+        Self::Neq,
+        Self::Gt,
+        Self::Lt,
+        Self::Ge,
+        Self::Le,
+        Self::In,
+        Self::Like,
+        Self::Matches,
+        Self::IsSubset,
+        Self::IsSuperset,
+    ];
+
+    #[must_use]
+    pub fn value(&self) -> &'static str {
         match self {
             Self::Eq => "=",
             Self::Neq => "!=",
@@ -63,39 +120,214 @@ impl ComparisonOp {
             Self::IsSuperset => "ISSUPERSET",
         }
     }
+
+    /// A stable number identifying the operator, for compact encodings.
+    ///
+    /// Codes are never reassigned; new operators get new codes.
+    #[must_use]
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Eq => 0,
+            Self::Neq => 1,
+            Self::Gt => 2,
+            Self::Lt => 3,
+            Self::Ge => 4,
+            Self::Le => 5,
+            Self::In => 6,
+            Self::Like => 7,
+            Self::Matches => 8,
+            Self::IsSubset => 9,
+            Self::IsSuperset => 10,
+        }
+    }
+
+    /// The operator numbered `code`, the inverse of [`code`](Self::code).
+    #[must_use]
+    pub fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            0 => Self::Eq,
+            1 => Self::Neq,
+            2 => Self::Gt,
+            3 => Self::Lt,
+            4 => Self::Ge,
+            5 => Self::Le,
+            6 => Self::In,
+            7 => Self::Like,
+            8 => Self::Matches,
+            9 => Self::IsSubset,
+            10 => Self::IsSuperset,
+            _ => return None,
+        })
+    }
+
+    /// The operator spelled `value`, the inverse of [`value`](Self::value).
+    #[must_use]
+    pub fn from_value(value: &str) -> Option<Self> {
+        Some(match value {
+            "=" => Self::Eq,
+            "!=" => Self::Neq,
+            ">" => Self::Gt,
+            "<" => Self::Lt,
+            ">=" => Self::Ge,
+            "<=" => Self::Le,
+            "IN" => Self::In,
+            "LIKE" => Self::Like,
+            "MATCHES" => Self::Matches,
+            "ISSUBSET" => Self::IsSubset,
+            "ISSUPERSET" => Self::IsSuperset,
+            _ => return None,
+        })
+    }
+
+    /// The operator equivalent to this one under `NOT`, if there is one.
+    ///
+    /// `IN`, `LIKE`, `MATCHES`, `ISSUBSET` and `ISSUPERSET` have no
+    /// complementary operator.
+    #[must_use]
+    pub fn complement(self) -> Option<Self> {
+        match self {
+            Self::Eq => Some(Self::Neq),
+            Self::Neq => Some(Self::Eq),
+            Self::Gt => Some(Self::Le),
+            Self::Le => Some(Self::Gt),
+            Self::Lt => Some(Self::Ge),
+            Self::Ge => Some(Self::Lt),
+            Self::In | Self::Like | Self::Matches | Self::IsSubset | Self::IsSuperset => None,
+        }
+    }
+
+    /// Whether the operator takes a list on the right, as `IN` does, rather
+    /// than a single value.
+    #[must_use]
+    pub fn takes_list(self) -> bool {
+        self == Self::In
+    }
+
+    /// The operator giving the same result with its operands swapped, so
+    /// that `v > p` is `p < v`, if there is one.
+    ///
+    /// `IN`, `LIKE`, `MATCHES`, `ISSUBSET` and `ISSUPERSET` take an object
+    /// path on the left only.
+    #[must_use]
+    pub fn swapped(self) -> Option<Self> {
+        match self {
+            Self::Eq | Self::Neq => Some(self),
+            Self::Gt => Some(Self::Lt),
+            Self::Lt => Some(Self::Gt),
+            Self::Ge => Some(Self::Le),
+            Self::Le => Some(Self::Ge),
+            Self::In | Self::Like | Self::Matches | Self::IsSubset | Self::IsSuperset => None,
+        }
+    }
 }
 
-#[pyclass(frozen, eq, eq_int)]
+#[cfg_attr(
+    feature = "python",
+    pyclass(
+        frozen,
+        eq,
+        eq_int,
+        hash,
+        rename_all = "UPPERCASE",
+        module = "stix_patterns_parser"
+    )
+)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UnaryOp {
-    #[pyo3(name = "EXISTS")]
     Exists,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl UnaryOp {
-    fn __repr__(&self) -> &'static str {
+    fn __copy__(&self) -> Self {
+        *self
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        *self
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<EnumReduce<'py>> {
+        reduce_enum_member::<Self>(py, self.__repr__())
+    }
+
+    pub(crate) fn __repr__(&self) -> &'static str {
         "UnaryOp.EXISTS"
     }
 
-    #[getter]
-    fn value(&self) -> &'static str {
+    #[getter(value)]
+    fn py_value(&self) -> &'static str {
+        self.value()
+    }
+
+    #[getter(code)]
+    fn py_code(&self) -> u8 {
+        self.code()
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_code")]
+    fn py_from_code(code: u8) -> PyResult<Self> {
+        Self::from_code(code).ok_or_else(|| unknown_code::<Self>(code))
+    }
+}
+
+impl UnaryOp {
+    /// Every operator, in the order of their [`code`](Self::code)s.
+    pub const ALL: [Self; 1] = [Self::Exists];
+
+    #[must_use]
+    pub fn value(&self) -> &'static str {
         "EXISTS"
     }
+
+    /// A stable number identifying the operator, for compact encodings.
+    #[must_use]
+    pub fn code(&self) -> u8 {
+        0
+    }
+
+    /// The operator numbered `code`, the inverse of [`code`](Self::code).
+    #[must_use]
+    pub fn from_code(code: u8) -> Option<Self> {
+        (code == 0).then_some(Self::Exists)
+    }
 }
 
-#[pyclass(frozen, eq, eq_int)]
+#[cfg_attr(
+    feature = "python",
+    pyclass(
+        frozen,
+        eq,
+        eq_int,
+        hash,
+        rename_all = "UPPERCASE",
+        module = "stix_patterns_parser"
+    )
+)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum BooleanOp {
     #[default]
-    #[pyo3(name = "AND")]
     And,
-    #[pyo3(name = "OR")]
     Or,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl BooleanOp {
+    fn __copy__(&self) -> Self {
+        *self
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        *self
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<EnumReduce<'py>> {
+        reduce_enum_member::<Self>(py, self.__repr__())
+    }
+
     fn __repr__(&self) -> &'static str {
         match self {
             Self::And => "BooleanOp.AND",
@@ -103,28 +335,105 @@ impl BooleanOp {
         }
     }
 
-    #[getter]
-    fn value(&self) -> &'static str {
+    #[getter(value)]
+    fn py_value(&self) -> &'static str {
+        self.value()
+    }
+
+    #[getter(code)]
+    fn py_code(&self) -> u8 {
+        self.code()
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_code")]
+    fn py_from_code(code: u8) -> PyResult<Self> {
+        Self::from_code(code).ok_or_else(|| unknown_code::<Self>(code))
+    }
+}
+
+impl BooleanOp {
+    #[must_use]
+    pub fn value(&self) -> &'static str {
         match self {
             Self::And => "AND",
             Self::Or => "OR",
         }
     }
+
+    /// A stable number identifying the operator, for compact encodings.
+    #[must_use]
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::And => 0,
+            Self::Or => 1,
+        }
+    }
+
+    /// The operator numbered `code`, the inverse of [`code`](Self::code).
+    #[must_use]
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::And),
+            1 => Some(Self::Or),
+            _ => None,
+        }
+    }
+
+    /// The operator spelled `value`, the inverse of [`value`](Self::value).
+    #[must_use]
+    pub fn from_value(value: &str) -> Option<Self> {
+        match value {
+            "AND" => Some(Self::And),
+            "OR" => Some(Self::Or),
+            _ => None,
+        }
+    }
+
+    /// How tightly the operator binds: `AND` before `OR` (STIX 2.1
+    /// Specification Section 9.6). Both are left-associative.
+    #[must_use]
+    pub fn precedence(self) -> u8 {
+        match self {
+            Self::And => 2,
+            Self::Or => 1,
+        }
+    }
 }
 
-#[pyclass(frozen, eq, eq_int)]
+#[cfg_attr(
+    feature = "python",
+    pyclass(
+        frozen,
+        eq,
+        eq_int,
+        hash,
+        rename_all = "UPPERCASE",
+        module = "stix_patterns_parser"
+    )
+)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ObservationOp {
-    #[pyo3(name = "AND")]
     And,
-    #[pyo3(name = "OR")]
     Or,
-    #[pyo3(name = "FOLLOWEDBY")]
     FollowedBy,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl ObservationOp {
+    fn __copy__(&self) -> Self {
+        *self
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        *self
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<EnumReduce<'py>> {
+        reduce_enum_member::<Self>(py, self.__repr__())
+    }
+
     fn __repr__(&self) -> &'static str {
         match self {
             Self::And => "ObservationOp.AND",
@@ -133,14 +442,289 @@ impl ObservationOp {
         }
     }
 
-    #[getter]
-    fn value(&self) -> &'static str {
+    #[getter(value)]
+    fn py_value(&self) -> &'static str {
+        self.value()
+    }
+
+    #[getter(code)]
+    fn py_code(&self) -> u8 {
+        self.code()
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_code")]
+    fn py_from_code(code: u8) -> PyResult<Self> {
+        Self::from_code(code).ok_or_else(|| unknown_code::<Self>(code))
+    }
+}
+
+impl ObservationOp {
+    #[must_use]
+    pub fn value(&self) -> &'static str {
         match self {
             Self::And => "AND",
             Self::Or => "OR",
             Self::FollowedBy => "FOLLOWEDBY",
         }
     }
+
+    /// A stable number identifying the operator, for compact encodings.
+    #[must_use]
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::And => 0,
+            Self::Or => 1,
+            Self::FollowedBy => 2,
+        }
+    }
+
+    /// The operator numbered `code`, the inverse of [`code`](Self::code).
+    #[must_use]
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::And),
+            1 => Some(Self::Or),
+            2 => Some(Self::FollowedBy),
+            _ => None,
+        }
+    }
+
+    /// The operator spelled `value`, the inverse of [`value`](Self::value).
+    #[must_use]
+    pub fn from_value(value: &str) -> Option<Self> {
+        match value {
+            "AND" => Some(Self::And),
+            "OR" => Some(Self::Or),
+            "FOLLOWEDBY" => Some(Self::FollowedBy),
+            _ => None,
+        }
+    }
+
+    /// How tightly the operator binds, higher first: `AND`, then `OR`, then
+    /// `FOLLOWEDBY` (STIX 2.1 Specification Section 9.5). All three are
+    /// left-associative.
+    #[must_use]
+    pub fn precedence(self) -> u8 {
+        match self {
+            Self::And => 3,
+            Self::Or => 2,
+            Self::FollowedBy => 1,
+        }
+    }
+}
+
+/// How a path component's property was written.
+#[cfg_attr(
+    feature = "python",
+    pyclass(
+        frozen,
+        eq,
+        eq_int,
+        hash,
+        rename_all = "UPPERCASE",
+        module = "stix_patterns_parser"
+    )
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PropertyKind {
+    /// A bare identifier such as `hashes` or `MD5`.
+    #[default]
+    Plain,
+    /// A quoted dictionary key such as `'SHA-256'`.
+    Quoted,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PropertyKind {
+    fn __copy__(&self) -> Self {
+        *self
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        *self
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<EnumReduce<'py>> {
+        reduce_enum_member::<Self>(py, self.__repr__())
+    }
+
+    fn __repr__(&self) -> &'static str {
+        match self {
+            Self::Plain => "PropertyKind.PLAIN",
+            Self::Quoted => "PropertyKind.QUOTED",
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+/// Return type of `__reduce__`: the class to call and its constructor arguments.
+type Reduce<'py, Args> = PyResult<(Bound<'py, PyType>, Args)>;
+
+#[cfg(feature = "python")]
+type EnumReduce<'py> = (Bound<'py, PyAny>, (Bound<'py, PyType>, &'static str));
+
+#[cfg(feature = "python")]
+fn unknown_code<T: pyo3::PyTypeInfo>(code: u8) -> PyErr {
+    pyo3::exceptions::PyValueError::new_err(format!("Unknown {} code {code}", T::NAME))
+}
+
+#[cfg(feature = "python")]
+/// Pickle enum members as `getattr(cls, name)` so they unpickle to the
+/// canonical member. `repr` is the member's `Class.NAME` representation.
+fn reduce_enum_member<'py, T: pyo3::PyTypeInfo>(
+    py: Python<'py>,
+    repr: &'static str,
+) -> PyResult<EnumReduce<'py>> {
+    let name = repr.rsplit('.').next().unwrap_or(repr);
+    let getattr = py.import("builtins")?.getattr("getattr")?;
+    Ok((getattr, (py.get_type::<T>(), name)))
+}
+
+/// Byte range of a node in the parsed input, unknown for nodes built
+/// programmatically.
+///
+/// Spans are metadata: all spans compare equal and hash alike, so nodes
+/// that only differ in where they were written are equal.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SourceSpan {
+    bounds: Option<(usize, usize)>,
+}
+
+impl SourceSpan {
+    #[must_use]
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            bounds: Some((start, end)),
+        }
+    }
+
+    #[must_use]
+    pub fn start(&self) -> Option<usize> {
+        self.bounds.map(|(start, _)| start)
+    }
+
+    #[must_use]
+    pub fn end(&self) -> Option<usize> {
+        self.bounds.map(|(_, end)| end)
+    }
+}
+
+impl PartialEq for SourceSpan {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SourceSpan {}
+
+impl std::hash::Hash for SourceSpan {
+    fn hash<H: std::hash::Hasher>(&self, _state: &mut H) {}
+}
+
+impl From<pest::Span<'_>> for SourceSpan {
+    fn from(span: pest::Span<'_>) -> Self {
+        Self::new(span.start(), span.end())
+    }
+}
+
+/// A value as written in the source, such as `1.50` for a float that
+/// displays as `1.5`.
+///
+/// The text is kept along with the value it was parsed into and only
+/// applies while the node still holds that value, so rewrites that replace
+/// it fall back to the canonical form. Like [`SourceSpan`], it never
+/// affects equality.
+#[derive(Debug, Clone)]
+pub struct RawText<T> {
+    source: Option<Arc<(String, T)>>,
+}
+
+/// The source text of a comparison's constant.
+pub type RawConstant = RawText<ComparisonRhs>;
+
+impl<T: PartialEq> RawText<T> {
+    #[must_use]
+    pub fn new(text: impl Into<String>, value: T) -> Self {
+        Self {
+            source: Some(Arc::new((text.into(), value))),
+        }
+    }
+
+    /// The source text, if it was written for `value`.
+    #[must_use]
+    pub fn text_for(&self, value: &T) -> Option<&str> {
+        self.source
+            .as_deref()
+            .filter(|(_, parsed)| parsed == value)
+            .map(|(text, _)| text.as_str())
+    }
+}
+
+impl<T> Default for RawText<T> {
+    fn default() -> Self {
+        Self { source: None }
+    }
+}
+
+impl<T> PartialEq for RawText<T> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// A Python object built from a frozen node the first time it is asked
+/// for. Clones start empty, as they may be changed before reaching Python.
+/// Like [`SourceSpan`], it never affects equality.
+#[cfg(feature = "python")]
+pub(crate) struct PyCache<T>(pyo3::sync::PyOnceLock<Py<T>>);
+
+#[cfg(feature = "python")]
+impl<T> PyCache<T> {
+    fn get_or_try_init<'py>(
+        &self,
+        py: Python<'py>,
+        f: impl FnOnce() -> PyResult<Py<T>>,
+    ) -> PyResult<&Bound<'py, T>> {
+        Ok(self.0.get_or_try_init(py, f)?.bind(py))
+    }
+}
+
+#[cfg(feature = "python")]
+impl<T> Default for PyCache<T> {
+    fn default() -> Self {
+        Self(pyo3::sync::PyOnceLock::new())
+    }
+}
+
+#[cfg(feature = "python")]
+impl<T> Clone for PyCache<T> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "python")]
+impl<T> std::fmt::Debug for PyCache<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PyCache")
+    }
+}
+
+#[cfg(feature = "python")]
+impl<T> PartialEq for PyCache<T> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "python")]
+impl<T> Eq for PyCache<T> {}
+
+#[cfg(feature = "python")]
+impl<T> std::hash::Hash for PyCache<T> {
+    fn hash<H: std::hash::Hasher>(&self, _state: &mut H) {}
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -149,57 +733,226 @@ pub enum ListIndex {
     Star,
 }
 
-#[pyclass(frozen)]
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, eq, hash, module = "stix_patterns_parser")
+)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PathComponent {
-    #[pyo3(get)]
-    pub property: String,
-    index: Option<ListIndex>,
+    pub property: Arc<str>,
+    /// List indices in order, as in `b[0][*]`.
+    pub indices: Vec<ListIndex>,
+    pub kind: PropertyKind,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl PathComponent {
+    #[new]
+    #[pyo3(signature = (property, index=None, kind=PropertyKind::Plain))]
+    pub(crate) fn py_new(
+        property: String,
+        index: Option<&Bound<'_, PyAny>>,
+        kind: PropertyKind,
+    ) -> PyResult<Self> {
+        let extract = |i: &Bound<'_, PyAny>| {
+            if let Ok(i) = i.extract::<u32>() {
+                Ok(ListIndex::Index(i))
+            } else if i.extract::<String>().is_ok_and(|s| s == "*") {
+                Ok(ListIndex::Star)
+            } else {
+                Err(PyValueError::new_err(
+                    "index must be a non-negative int, '*', a list of them or None",
+                ))
+            }
+        };
+        let indices = match index {
+            None => Vec::new(),
+            Some(i) if i.is_none() => Vec::new(),
+            Some(i) if i.is_instance_of::<PyList>() => i
+                .try_iter()?
+                .map(|i| extract(&i?))
+                .collect::<PyResult<_>>()?,
+            Some(i) => vec![extract(i)?],
+        };
+        Ok(Self {
+            property: property.into(),
+            indices,
+            kind,
+        })
+    }
+
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> Reduce<'py, (String, Option<Py<PyAny>>, PropertyKind)> {
+        let this = slf.get();
+        Ok((
+            slf.get_type(),
+            (this.property.to_string(), this.index(slf.py())?, this.kind),
+        ))
+    }
+
+    fn __copy__(&self) -> Self {
+        self.clone()
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.clone()
+    }
+
+    #[getter(is_reference)]
+    fn py_is_reference(&self) -> bool {
+        self.is_reference()
+    }
+
+    #[getter]
+    fn property(&self) -> &str {
+        &self.property
+    }
+
+    /// The list index, a list of them when chained, or None.
     #[getter]
-    fn index(&self, py: Python<'_>) -> Option<Py<PyAny>> {
-        self.index.as_ref().map(|idx| match idx {
+    pub(crate) fn index(&self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        let to_py = |idx: &ListIndex| match idx {
             ListIndex::Index(i) => (*i).into_pyobject(py).unwrap().into_any().unbind(),
             ListIndex::Star => "*".into_pyobject(py).unwrap().into_any().unbind(),
+        };
+        Ok(match self.indices.as_slice() {
+            [] => None,
+            [idx] => Some(to_py(idx)),
+            indices => Some(
+                PyList::new(py, indices.iter().map(to_py))?
+                    .into_any()
+                    .unbind(),
+            ),
         })
     }
 
+    #[getter]
+    fn kind(&self) -> PropertyKind {
+        self.kind
+    }
+
     fn __repr__(&self) -> String {
-        match &self.index {
-            Some(ListIndex::Index(i)) => {
-                format!("PathComponent(property={:?}, index={})", self.property, i)
-            }
-            Some(ListIndex::Star) => {
-                format!("PathComponent(property={:?}, index='*')", self.property)
-            }
-            None => format!("PathComponent(property={:?}, index=None)", self.property),
-        }
+        let repr = |idx: &ListIndex| match idx {
+            ListIndex::Index(i) => i.to_string(),
+            ListIndex::Star => "'*'".to_owned(),
+        };
+        let index = match self.indices.as_slice() {
+            [] => "None".to_owned(),
+            [idx] => repr(idx),
+            indices => format!(
+                "[{}]",
+                indices.iter().map(repr).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        format!(
+            "PathComponent(property={:?}, index={index}, kind={})",
+            self.property,
+            self.kind.__repr__()
+        )
     }
 }
 
 impl PathComponent {
     #[must_use]
-    pub fn new(property: String, index: Option<ListIndex>) -> Self {
-        Self { property, index }
+    pub fn new(property: impl Into<Arc<str>>, index: Option<ListIndex>) -> Self {
+        Self {
+            property: property.into(),
+            indices: index.into_iter().collect(),
+            kind: PropertyKind::Plain,
+        }
+    }
+
+    /// Replace the list indices, e.g. for chained indices like `b[0][*]`.
+    #[must_use]
+    pub fn with_indices(self, indices: Vec<ListIndex>) -> Self {
+        Self { indices, ..self }
+    }
+
+    /// A component whose property is a quoted dictionary key.
+    #[must_use]
+    pub fn new_quoted(property: impl Into<Arc<str>>, index: Option<ListIndex>) -> Self {
+        Self {
+            kind: PropertyKind::Quoted,
+            ..Self::new(property, index)
+        }
+    }
+
+    /// Whether the property holds an object reference (`_ref`) or a list of
+    /// them (`_refs`), which later components dereference.
+    #[must_use]
+    pub fn is_reference(&self) -> bool {
+        self.property.ends_with("_ref") || self.property.ends_with("_refs")
     }
 }
 
-#[pyclass(frozen)]
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, eq, hash, module = "stix_patterns_parser")
+)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ObjectPath {
-    #[pyo3(get)]
-    pub object_type: String,
+    pub object_type: Arc<str>,
     pub property_path: Vec<PathComponent>,
+    pub span: SourceSpan,
+    /// The Python objects of `property_path`, built on first access.
+    #[cfg(feature = "python")]
+    py_property_path: PyCache<PyTuple>,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl ObjectPath {
+    #[new]
+    fn py_new(object_type: String, property_path: Vec<PathComponent>) -> Self {
+        Self::new(object_type, property_path)
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> Reduce<'py, (String, Vec<PathComponent>)> {
+        let this = slf.get();
+        Ok((
+            slf.get_type(),
+            (this.object_type.to_string(), this.property_path.clone()),
+        ))
+    }
+
     #[getter]
-    fn property_path(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
-        Ok(self.property_path.clone().into_pyobject(py)?.unbind())
+    fn start_offset(&self) -> Option<usize> {
+        self.span.start()
+    }
+
+    #[getter]
+    fn end_offset(&self) -> Option<usize> {
+        self.span.end()
+    }
+
+    fn __copy__(&self) -> Self {
+        self.clone()
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.clone()
+    }
+
+    #[getter]
+    fn object_type(&self) -> &str {
+        &self.object_type
+    }
+
+    #[getter(is_custom)]
+    fn py_is_custom(&self) -> bool {
+        self.is_custom()
+    }
+
+    /// A new list of the same components on each access, so changing it
+    /// changes nothing else.
+    #[getter]
+    fn property_path<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let components = self.py_property_path.get_or_try_init(py, || {
+            Ok(PyTuple::new(py, self.property_path.iter().cloned())?.unbind())
+        })?;
+        PyList::new(py, components)
     }
 
     fn __repr__(&self) -> String {
@@ -209,15 +962,37 @@ impl ObjectPath {
 
 impl ObjectPath {
     #[must_use]
-    pub fn new(object_type: String, property_path: Vec<PathComponent>) -> Self {
+    pub fn new(object_type: impl Into<Arc<str>>, property_path: Vec<PathComponent>) -> Self {
         Self {
-            object_type,
+            object_type: object_type.into(),
             property_path,
+            span: SourceSpan::default(),
+            #[cfg(feature = "python")]
+            py_property_path: PyCache::default(),
         }
     }
+
+    /// Attach the node's position in the parsed input.
+    #[must_use]
+    pub fn with_span(self, span: SourceSpan) -> Self {
+        Self { span, ..self }
+    }
+
+    /// Whether the object type is a custom (`x-` prefixed) one.
+    #[must_use]
+    pub fn is_custom(&self) -> bool {
+        self.object_type.starts_with("x-")
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// A constant on the right-hand side of a comparison.
+///
+/// Values are totally ordered, first by variant in declaration order
+/// (string, int, float, bool, timestamp, hex, binary, null) and then by value.
+/// Floats compare with [`f64::total_cmp`] except that every NaN sorts after
+/// all other floats and equal to any other NaN, so `-0.0 < 0.0`. Equality
+/// agrees with this order.
+#[derive(Debug, Clone)]
 pub enum StixValue {
     String(String),
     Int(i64),
@@ -226,9 +1001,41 @@ pub enum StixValue {
     Timestamp(DateTime<Utc>),
     Hex(String),
     Binary(String),
+    /// The non-standard `null` constant, accepted with
+    /// [`ParseOptions::allow_null`](crate::parser::ParseOptions).
+    Null,
 }
 
 impl StixValue {
+    fn rank(&self) -> u8 {
+        match self {
+            Self::String(_) => 0,
+            Self::Int(_) => 1,
+            Self::Float(_) => 2,
+            Self::Bool(_) => 3,
+            Self::Timestamp(_) => 4,
+            Self::Hex(_) => 5,
+            Self::Binary(_) => 6,
+            Self::Null => 7,
+        }
+    }
+
+    /// Name of the constant's type, as used in error messages.
+    #[must_use]
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::Int(_) => "int",
+            Self::Float(_) => "float",
+            Self::Bool(_) => "bool",
+            Self::Timestamp(_) => "timestamp",
+            Self::Hex(_) => "hex",
+            Self::Binary(_) => "binary",
+            Self::Null => "null",
+        }
+    }
+
+    #[cfg(feature = "python")]
     pub fn to_pyobject(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
         match self {
             Self::String(s) | Self::Hex(s) | Self::Binary(s) => {
@@ -238,16 +1045,139 @@ impl StixValue {
             Self::Float(f) => Ok((*f).into_pyobject(py)?.into_any().unbind()),
             Self::Bool(b) => Ok(b.into_pyobject(py)?.to_owned().into_any().unbind()),
             Self::Timestamp(dt) => Ok(datetime_to_pyobject(dt, py)?.into_any()),
+            Self::Null => Ok(py.None()),
         }
     }
+
+    #[cfg(feature = "python")]
+    /// Build a constant from a Python value. `type_name` selects the variant
+    /// by its [`type_name`](Self::type_name); without it the variant is
+    /// inferred from the Python type, so hex and binary constants need it.
+    pub fn from_pyobject(obj: &Bound<'_, PyAny>, type_name: Option<&str>) -> PyResult<Self> {
+        Ok(match type_name {
+            Some("string") => Self::String(obj.extract()?),
+            Some("int") => Self::Int(obj.extract()?),
+            Some("float") => Self::Float(obj.extract()?),
+            Some("bool") => Self::Bool(obj.extract()?),
+            Some("timestamp") => Self::Timestamp(datetime_from_pyobject(obj)?),
+            Some("hex") => Self::Hex(obj.extract()?),
+            Some("binary") => Self::Binary(obj.extract()?),
+            Some("null") => Self::Null,
+            Some(other) => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown constant type {other:?}"
+                )));
+            }
+            None if obj.is_none() => Self::Null,
+            None if obj.is_instance_of::<PyBool>() => Self::Bool(obj.extract()?),
+            None if obj.is_instance_of::<PyInt>() => Self::Int(obj.extract()?),
+            None if obj.is_instance_of::<PyFloat>() => Self::Float(obj.extract()?),
+            None if obj.is_instance_of::<PyString>() => Self::String(obj.extract()?),
+            None if obj.is_instance_of::<PyDateTime>() => {
+                Self::Timestamp(datetime_from_pyobject(obj)?)
+            }
+            None => {
+                return Err(PyTypeError::new_err(format!(
+                    "unsupported constant type {}",
+                    obj.get_type().name()?
+                )));
+            }
+        })
+    }
+
+    #[cfg(feature = "python")]
+    /// Python value used when pickling. Timestamps are passed as RFC 3339
+    /// strings so that sub-microsecond precision survives.
+    fn to_reduce_arg(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        match self {
+            Self::Timestamp(dt) => Ok(dt
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                .into_pyobject(py)?
+                .into_any()
+                .unbind()),
+            _ => self.to_pyobject(py),
+        }
+    }
+}
+
+impl Ord for StixValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::String(a), Self::String(b))
+            | (Self::Hex(a), Self::Hex(b))
+            | (Self::Binary(a), Self::Binary(b)) => a.cmp(b),
+            (Self::Int(a), Self::Int(b)) => a.cmp(b),
+            (Self::Float(a), Self::Float(b)) => match (a.is_nan(), b.is_nan()) {
+                (false, false) => a.total_cmp(b),
+                (nan_a, nan_b) => nan_a.cmp(&nan_b),
+            },
+            (Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+            (Self::Timestamp(a), Self::Timestamp(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for StixValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for StixValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for StixValue {}
+
+impl From<&str> for StixValue {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_owned())
+    }
+}
+
+impl From<String> for StixValue {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<i64> for StixValue {
+    fn from(i: i64) -> Self {
+        Self::Int(i)
+    }
+}
+
+impl From<f64> for StixValue {
+    fn from(f: f64) -> Self {
+        Self::Float(f)
+    }
+}
+
+impl From<bool> for StixValue {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<DateTime<Utc>> for StixValue {
+    fn from(dt: DateTime<Utc>) -> Self {
+        Self::Timestamp(dt)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ComparisonRhs {
     Value(StixValue),
     List(Vec<StixValue>),
+    /// Another object path, as in `a:x = b:y`. Not part of STIX 2.1; only
+    /// produced with [`ParseOptions::allow_path_rhs`](crate::parser::ParseOptions).
+    Path(ObjectPath),
 }
 
+#[cfg(feature = "python")]
 impl ComparisonRhs {
     pub fn to_pyobject(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
         match self {
@@ -257,6 +1187,71 @@ impl ComparisonRhs {
                     values.iter().map(|v| v.to_pyobject(py)).collect();
                 Ok(py_values?.into_pyobject(py)?.into_any().unbind())
             }
+            Self::Path(path) => Ok(path.clone().into_pyobject(py)?.into_any().unbind()),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+impl ComparisonRhs {
+    /// Build a constant from a Python value or list of values. `type_name`
+    /// is either one type name applied to every value or one per element.
+    pub fn from_pyobject(
+        obj: &Bound<'_, PyAny>,
+        type_name: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        if let Ok(path) = obj.extract::<ObjectPath>() {
+            return Ok(Self::Path(path));
+        }
+        let type_name = type_name.filter(|t| !t.is_none());
+        if !(obj.is_instance_of::<PyList>() || obj.is_instance_of::<PyTuple>()) {
+            let type_name = type_name.map(|t| t.extract::<String>()).transpose()?;
+            return StixValue::from_pyobject(obj, type_name.as_deref()).map(Self::Value);
+        }
+
+        let values: Vec<Bound<'_, PyAny>> = obj.extract()?;
+        let type_names: Vec<Option<String>> = match type_name {
+            None => vec![None; values.len()],
+            Some(t) if t.is_instance_of::<PyString>() => vec![Some(t.extract()?); values.len()],
+            Some(t) => {
+                let names: Vec<String> = t.extract()?;
+                if names.len() != values.len() {
+                    return Err(PyValueError::new_err(
+                        "constant_type must have one entry per list element",
+                    ));
+                }
+                names.into_iter().map(Some).collect()
+            }
+        };
+        values
+            .iter()
+            .zip(&type_names)
+            .map(|(v, t)| StixValue::from_pyobject(v, t.as_deref()))
+            .collect::<PyResult<_>>()
+            .map(Self::List)
+    }
+
+    /// The `(constant, constant_type)` pair passed to the `Comparison`
+    /// constructor when pickling.
+    pub(crate) fn to_reduce_args(&self, py: Python<'_>) -> PyResult<(Py<PyAny>, Py<PyAny>)> {
+        match self {
+            Self::Value(v) => Ok((
+                v.to_reduce_arg(py)?,
+                v.type_name().into_pyobject(py)?.into_any().unbind(),
+            )),
+            Self::List(values) => {
+                let py_values: PyResult<Vec<_>> =
+                    values.iter().map(|v| v.to_reduce_arg(py)).collect();
+                let type_names: Vec<_> = values.iter().map(StixValue::type_name).collect();
+                Ok((
+                    py_values?.into_pyobject(py)?.into_any().unbind(),
+                    type_names.into_pyobject(py)?.into_any().unbind(),
+                ))
+            }
+            Self::Path(path) => Ok((
+                path.clone().into_pyobject(py)?.into_any().unbind(),
+                py.None(),
+            )),
         }
     }
 }
@@ -267,6 +1262,12 @@ impl From<StixValue> for ComparisonRhs {
     }
 }
 
+impl From<ObjectPath> for ComparisonRhs {
+    fn from(path: ObjectPath) -> Self {
+        Self::Path(path)
+    }
+}
+
 impl From<Vec<StixValue>> for ComparisonRhs {
     fn from(values: Vec<StixValue>) -> Self {
         Self::List(values)
@@ -274,44 +1275,181 @@ impl From<Vec<StixValue>> for ComparisonRhs {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "python", derive(FromPyObject))]
 pub enum ComparisonOperator {
     Comparison(ComparisonOp),
     Unary(UnaryOp),
 }
 
-impl ComparisonOperator {
-    fn to_pyobject(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
-        match self {
-            Self::Comparison(op) => Ok((*op).into_pyobject(py)?.into_any().unbind()),
-            Self::Unary(op) => Ok((*op).into_pyobject(py)?.into_any().unbind()),
-        }
+#[cfg(feature = "python")]
+impl ComparisonOperator {
+    pub fn to_pyobject(self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        match self {
+            Self::Comparison(op) => Ok(op.into_pyobject(py)?.into_any().unbind()),
+            Self::Unary(op) => Ok(op.into_pyobject(py)?.into_any().unbind()),
+        }
+    }
+}
+
+impl From<ComparisonOp> for ComparisonOperator {
+    fn from(op: ComparisonOp) -> Self {
+        Self::Comparison(op)
+    }
+}
+
+impl From<UnaryOp> for ComparisonOperator {
+    fn from(op: UnaryOp) -> Self {
+        Self::Unary(op)
+    }
+}
+
+#[cfg(feature = "python")]
+/// Base class of the four pattern node classes, so that `parse` and the
+/// other functions returning nodes have a common type. It cannot be
+/// instantiated from Python.
+#[cfg_attr(
+    feature = "python",
+    pyclass(subclass, frozen, module = "stix_patterns_parser")
+)]
+pub struct PatternNode;
+
+#[cfg(feature = "python")]
+/// Wrap a node in a Python object of its class, under [`PatternNode`].
+pub(crate) fn node_into_pyobject<T>(py: Python<'_>, node: T) -> PyResult<Py<PyAny>>
+where
+    T: pyo3::PyClass<BaseType = PatternNode>,
+{
+    Ok(Py::new(py, PyClassInitializer::from(PatternNode).add_subclass(node))?.into_any())
+}
+
+#[cfg_attr(feature = "python", pyclass(frozen, eq, extends = PatternNode, module = "stix_patterns_parser"))]
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub object_path: ObjectPath,
+    pub op: ComparisonOperator,
+    pub constant: Option<ComparisonRhs>,
+    pub negated: bool,
+    pub span: SourceSpan,
+    pub raw: RawConstant,
+    /// Whether the list constant was written with integer ranges, as in
+    /// `IN (1..3)`, and holds their expansion. Only set by parsing with
+    /// `ParseOptions::allow_ranges`. Like the span, it never affects
+    /// equality.
+    pub has_ranges: bool,
+}
+
+impl PartialEq for Comparison {
+    fn eq(&self, other: &Self) -> bool {
+        self.object_path == other.object_path
+            && self.op == other.op
+            && self.constant == other.constant
+            && self.negated == other.negated
+    }
+}
+
+#[cfg(feature = "python")]
+/// `(object_path, op, constant, negated, constant_type)`
+type ComparisonArgs = (
+    ObjectPath,
+    Py<PyAny>,
+    Option<Py<PyAny>>,
+    bool,
+    Option<Py<PyAny>>,
+);
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Comparison {
+    #[new]
+    #[pyo3(signature = (object_path, op, constant=None, negated=false, constant_type=None))]
+    pub(crate) fn py_new(
+        object_path: ObjectPath,
+        op: ComparisonOperator,
+        constant: Option<&Bound<'_, PyAny>>,
+        negated: bool,
+        constant_type: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<(Self, PatternNode)> {
+        // A `None` constant means there is none, unless it is typed `null`.
+        let constant = match (constant, constant_type) {
+            (Some(c), t) => Some(ComparisonRhs::from_pyobject(c, t)?),
+            (None, Some(t)) => Some(ComparisonRhs::from_pyobject(
+                &t.py().None().into_bound(t.py()),
+                Some(t),
+            )?),
+            (None, None) => None,
+        };
+        match (op, &constant) {
+            (ComparisonOperator::Comparison(_), None) => {
+                return Err(PyValueError::new_err(
+                    "comparison operators need a constant",
+                ));
+            }
+            (ComparisonOperator::Unary(_), Some(_)) => {
+                return Err(PyValueError::new_err("unary operators take no constant"));
+            }
+            (ComparisonOperator::Unary(_), None) if negated => {
+                return Err(PyValueError::new_err("unary operators cannot be negated"));
+            }
+            _ => {}
+        }
+        Ok((Self::new(object_path, op, constant, negated), PatternNode))
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> Reduce<'py, ComparisonArgs> {
+        let py = slf.py();
+        let this = slf.get();
+        let (constant, constant_type) = match &this.constant {
+            Some(rhs) => {
+                let (constant, constant_type) = rhs.to_reduce_args(py)?;
+                (Some(constant), Some(constant_type))
+            }
+            None => (None, None),
+        };
+        Ok((
+            slf.get_type(),
+            (
+                this.object_path.clone(),
+                this.op.to_pyobject(py)?,
+                constant,
+                this.negated,
+                constant_type,
+            ),
+        ))
+    }
+
+    #[getter]
+    fn start_offset(&self) -> Option<usize> {
+        self.span.start()
+    }
+
+    #[getter]
+    fn end_offset(&self) -> Option<usize> {
+        self.span.end()
     }
-}
 
-impl From<ComparisonOp> for ComparisonOperator {
-    fn from(op: ComparisonOp) -> Self {
-        Self::Comparison(op)
+    #[getter]
+    fn is_single_observation(&self) -> bool {
+        true
     }
-}
 
-impl From<UnaryOp> for ComparisonOperator {
-    fn from(op: UnaryOp) -> Self {
-        Self::Unary(op)
+    #[getter]
+    fn is_composite(&self) -> bool {
+        false
     }
-}
 
-#[pyclass(frozen)]
-#[derive(Debug, Clone)]
-pub struct Comparison {
-    object_path: ObjectPath,
-    op: ComparisonOperator,
-    constant: Option<ComparisonRhs>,
-    #[pyo3(get)]
-    pub negated: bool,
-}
+    #[getter]
+    fn is_qualified(&self) -> bool {
+        false
+    }
+
+    fn __copy__<'py>(slf: &Bound<'py, Self>) -> Bound<'py, Self> {
+        slf.clone()
+    }
+
+    fn __deepcopy__<'py>(slf: &Bound<'py, Self>, _memo: &Bound<'_, PyAny>) -> Bound<'py, Self> {
+        slf.clone()
+    }
 
-#[pymethods]
-impl Comparison {
     #[getter]
     fn object_path(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
         Ok(self
@@ -335,6 +1473,38 @@ impl Comparison {
             .transpose()
     }
 
+    /// Type name of the constant, or one per list element, as accepted by the
+    /// constructor. `None` without a constant or for an object path.
+    #[getter]
+    fn constant_type(&self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        Ok(match &self.constant {
+            Some(ComparisonRhs::Value(v)) => {
+                Some(v.type_name().into_pyobject(py)?.into_any().unbind())
+            }
+            Some(ComparisonRhs::List(values)) => {
+                let type_names: Vec<_> = values.iter().map(StixValue::type_name).collect();
+                Some(type_names.into_pyobject(py)?.into_any().unbind())
+            }
+            Some(ComparisonRhs::Path(_)) | None => None,
+        })
+    }
+
+    /// The constant exactly as written in the parsed source, if any.
+    #[getter]
+    fn raw(&self) -> Option<&str> {
+        self.constant.as_ref().and_then(|c| self.raw.text_for(c))
+    }
+
+    #[getter]
+    fn has_ranges(&self) -> bool {
+        self.has_ranges
+    }
+
+    #[getter]
+    fn negated(&self) -> bool {
+        self.negated
+    }
+
     fn __repr__(&self, py: Python<'_>) -> String {
         let op_repr = self
             .op(py)
@@ -357,21 +1527,123 @@ impl Comparison {
             op: op.into(),
             constant: rhs,
             negated,
+            span: SourceSpan::default(),
+            raw: RawConstant::default(),
+            has_ranges: false,
+        }
+    }
+
+    /// Attach the node's position in the parsed input.
+    #[must_use]
+    pub fn with_span(self, span: SourceSpan) -> Self {
+        Self { span, ..self }
+    }
+
+    /// Record the source text of the current constant, which `Display` then
+    /// writes instead of the canonical form.
+    #[must_use]
+    pub fn with_raw(self, text: impl Into<String>) -> Self {
+        match &self.constant {
+            Some(c) => Self {
+                raw: RawConstant::new(text, c.clone()),
+                ..self
+            },
+            None => self,
         }
     }
 }
 
-#[pyclass(frozen)]
+/// Two comparison expressions joined by `AND` or `OR`.
+///
+/// Operands are shared through `Arc`, so cloning a node, as every Python
+/// getter does, copies no subtree.
+#[cfg_attr(feature = "python", pyclass(frozen, eq, extends = PatternNode, module = "stix_patterns_parser"))]
 #[derive(Debug, Clone)]
 pub struct CompositeComparison {
-    left: Box<ComparisonExpr>,
-    #[pyo3(get)]
+    pub left: Arc<ComparisonExpr>,
     pub op: BooleanOp,
-    right: Box<ComparisonExpr>,
+    pub right: Arc<ComparisonExpr>,
+    /// Whether the source wrapped this expression in parentheses. Like the
+    /// span, it never affects equality.
+    pub parenthesized: bool,
+    /// Whether the whole expression is negated, as in `NOT (a AND b)`.
+    /// Standard STIX only negates single comparisons, so this is only set
+    /// by parsing with `ParseOptions::allow_grouped_negation` or by
+    /// building the node.
+    pub negated: bool,
+    pub span: SourceSpan,
 }
 
+#[cfg(feature = "python")]
+/// `(left, op, right, parenthesized, negated)`
+type CompositeComparisonArgs = (Py<PyAny>, BooleanOp, Py<PyAny>, bool, bool);
+
+#[cfg(feature = "python")]
 #[pymethods]
 impl CompositeComparison {
+    #[new]
+    #[pyo3(signature = (left, op, right, parenthesized=false, negated=false))]
+    fn py_new(
+        left: ComparisonExpr,
+        op: BooleanOp,
+        right: ComparisonExpr,
+        parenthesized: bool,
+        negated: bool,
+    ) -> (Self, PatternNode) {
+        let mut node = Self::new(left, op, right);
+        node.parenthesized = parenthesized;
+        node.negated = negated;
+        (node, PatternNode)
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> Reduce<'py, CompositeComparisonArgs> {
+        let py = slf.py();
+        let this = slf.get();
+        Ok((
+            slf.get_type(),
+            (
+                this.left.to_pyobject(py)?,
+                this.op,
+                this.right.to_pyobject(py)?,
+                this.parenthesized,
+                this.negated,
+            ),
+        ))
+    }
+
+    #[getter]
+    fn start_offset(&self) -> Option<usize> {
+        self.span.start()
+    }
+
+    #[getter]
+    fn end_offset(&self) -> Option<usize> {
+        self.span.end()
+    }
+
+    #[getter]
+    fn is_single_observation(&self) -> bool {
+        true
+    }
+
+    #[getter]
+    fn is_composite(&self) -> bool {
+        false
+    }
+
+    #[getter]
+    fn is_qualified(&self) -> bool {
+        false
+    }
+
+    fn __copy__<'py>(slf: &Bound<'py, Self>) -> Bound<'py, Self> {
+        slf.clone()
+    }
+
+    fn __deepcopy__<'py>(slf: &Bound<'py, Self>, _memo: &Bound<'_, PyAny>) -> Bound<'py, Self> {
+        slf.clone()
+    }
+
     #[getter]
     fn left(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
         self.left.to_pyobject(py)
@@ -382,6 +1654,21 @@ impl CompositeComparison {
         self.right.to_pyobject(py)
     }
 
+    #[getter]
+    fn op(&self) -> BooleanOp {
+        self.op
+    }
+
+    #[getter]
+    fn parenthesized(&self) -> bool {
+        self.parenthesized
+    }
+
+    #[getter]
+    fn negated(&self) -> bool {
+        self.negated
+    }
+
     fn __repr__(&self) -> String {
         format!("CompositeComparison(op={:?}, ...)", self.op)
     }
@@ -391,24 +1678,160 @@ impl CompositeComparison {
     #[must_use]
     pub fn new(left: ComparisonExpr, op: BooleanOp, right: ComparisonExpr) -> Self {
         Self {
-            left: Box::new(left),
+            left: Arc::new(left),
             op,
-            right: Box::new(right),
+            right: Arc::new(right),
+            parenthesized: false,
+            negated: false,
+            span: SourceSpan::default(),
+        }
+    }
+
+    /// Attach the node's position in the parsed input.
+    #[must_use]
+    pub fn with_span(mut self, span: SourceSpan) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// The operands, moved out of their `Arc`s when this node is their only
+    /// owner.
+    #[must_use]
+    pub fn into_operands(mut self) -> (ComparisonExpr, ComparisonExpr) {
+        self.take_operands()
+    }
+
+    /// Replace both operands with `f` applied to them, keeping the operator,
+    /// grouping, negation and span.
+    #[must_use]
+    pub fn map_operands(mut self, mut f: impl FnMut(ComparisonExpr) -> ComparisonExpr) -> Self {
+        let (left, right) = self.take_operands();
+        self.left = Arc::new(f(left));
+        self.right = Arc::new(f(right));
+        self
+    }
+
+    /// Like [`map_operands`](Self::map_operands) applied down the chain of
+    /// composites on the left, where parsing nests long chains, in a loop
+    /// rather than one stack frame per level. `f` gets the right operand of
+    /// each and the first left operand that is a comparison or a negated
+    /// group, in document order.
+    #[must_use]
+    pub(crate) fn map_chain(mut self, mut f: impl FnMut(ComparisonExpr) -> ComparisonExpr) -> Self {
+        let (mut left, right) = self.take_operands();
+        let mut chain = vec![(self, right)];
+        let first = loop {
+            match left {
+                ComparisonExpr::Composite(mut c) if !c.negated => {
+                    let (l, r) = c.take_operands();
+                    chain.push((c, r));
+                    left = l;
+                }
+                other => break other,
+            }
+        };
+        let mut left = f(first);
+        loop {
+            let (mut c, right) = chain.pop().expect("the chain holds this node");
+            c.left = Arc::new(left);
+            c.right = Arc::new(f(right));
+            if chain.is_empty() {
+                return c;
+            }
+            left = c.into();
+        }
+    }
+
+    /// The composites down the left side, starting with this one, and the
+    /// comparison on the left of the last.
+    pub(crate) fn left_spine(&self) -> (Vec<&Self>, &Comparison) {
+        let mut spine = vec![self];
+        loop {
+            match spine
+                .last()
+                .expect("the spine holds this node")
+                .left
+                .as_ref()
+            {
+                ComparisonExpr::Composite(c) => spine.push(c),
+                ComparisonExpr::Single(c) => return (spine, c),
+            }
+        }
+    }
+
+    fn take_operands(&mut self) -> (ComparisonExpr, ComparisonExpr) {
+        static EMPTY: LazyLock<Arc<ComparisonExpr>> = LazyLock::new(|| {
+            let path = ObjectPath::new("", Vec::new());
+            Arc::new(Comparison::new(path, UnaryOp::Exists, None, false).into())
+        });
+        let left = std::mem::replace(&mut self.left, Arc::clone(&EMPTY));
+        let right = std::mem::replace(&mut self.right, Arc::clone(&EMPTY));
+        (Arc::unwrap_or_clone(left), Arc::unwrap_or_clone(right))
+    }
+}
+
+impl Drop for CompositeComparison {
+    /// Parsing folds a chain such as `a AND b AND c` from the left, so long
+    /// chains nest deeply on that side. Left operands owned by this node
+    /// alone are dropped in a loop rather than one stack frame per level.
+    fn drop(&mut self) {
+        let mut next = detach_left_comparison(self);
+        while let Some(mut c) = next {
+            next = detach_left_comparison(&mut c);
         }
     }
 }
 
-#[derive(Debug, Clone)]
+impl PartialEq for CompositeComparison {
+    /// Compared down the left side in a loop, like [`Drop`].
+    fn eq(&self, other: &Self) -> bool {
+        let (a, a_first) = self.left_spine();
+        let (b, b_first) = other.left_spine();
+        a.len() == b.len()
+            && a_first == b_first
+            && a.iter()
+                .zip(&b)
+                .all(|(a, b)| a.op == b.op && a.negated == b.negated && a.right == b.right)
+    }
+}
+
+/// Take the left operand of `c` if it is a composite owned by `c` alone,
+/// leaving a second reference to the right operand in its place.
+fn detach_left_comparison(c: &mut CompositeComparison) -> Option<CompositeComparison> {
+    if !matches!(
+        Arc::get_mut(&mut c.left),
+        Some(ComparisonExpr::Composite(_))
+    ) {
+        return None;
+    }
+    let left = std::mem::replace(&mut c.left, Arc::clone(&c.right));
+    match Arc::into_inner(left) {
+        Some(ComparisonExpr::Composite(left)) => Some(left),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "python", derive(FromPyObject))]
 pub enum ComparisonExpr {
     Single(Comparison),
     Composite(CompositeComparison),
 }
 
 impl ComparisonExpr {
+    #[must_use]
+    pub fn span(&self) -> SourceSpan {
+        match self {
+            Self::Single(c) => c.span,
+            Self::Composite(c) => c.span,
+        }
+    }
+
+    #[cfg(feature = "python")]
     pub fn to_pyobject(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
         match self {
-            Self::Single(c) => Ok(c.clone().into_pyobject(py)?.into_any().unbind()),
-            Self::Composite(c) => Ok(c.clone().into_pyobject(py)?.into_any().unbind()),
+            Self::Single(c) => node_into_pyobject(py, c.clone()),
+            Self::Composite(c) => node_into_pyobject(py, c.clone()),
         }
     }
 }
@@ -425,17 +1848,85 @@ impl From<CompositeComparison> for ComparisonExpr {
     }
 }
 
-#[pyclass(frozen)]
+/// Two pattern expressions joined by an observation operator. Operands are
+/// shared like those of [`CompositeComparison`].
+#[cfg_attr(feature = "python", pyclass(frozen, eq, extends = PatternNode, module = "stix_patterns_parser"))]
 #[derive(Debug, Clone)]
 pub struct CompositePattern {
-    left: Box<PatternExpr>,
-    #[pyo3(get)]
+    pub left: Arc<PatternExpr>,
     pub op: ObservationOp,
-    right: Box<PatternExpr>,
+    pub right: Arc<PatternExpr>,
+    /// Whether the source wrapped this expression in parentheses. Like the
+    /// span, it never affects equality.
+    pub parenthesized: bool,
+    pub span: SourceSpan,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl CompositePattern {
+    #[new]
+    #[pyo3(signature = (left, op, right, parenthesized=false))]
+    fn py_new(
+        left: PatternExpr,
+        op: ObservationOp,
+        right: PatternExpr,
+        parenthesized: bool,
+    ) -> (Self, PatternNode) {
+        let mut node = Self::new(left, op, right);
+        node.parenthesized = parenthesized;
+        (node, PatternNode)
+    }
+
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> Reduce<'py, (Py<PyAny>, ObservationOp, Py<PyAny>, bool)> {
+        let py = slf.py();
+        let this = slf.get();
+        Ok((
+            slf.get_type(),
+            (
+                this.left.to_pyobject(py)?,
+                this.op,
+                this.right.to_pyobject(py)?,
+                this.parenthesized,
+            ),
+        ))
+    }
+
+    #[getter]
+    fn start_offset(&self) -> Option<usize> {
+        self.span.start()
+    }
+
+    #[getter]
+    fn end_offset(&self) -> Option<usize> {
+        self.span.end()
+    }
+
+    #[getter]
+    fn is_single_observation(&self) -> bool {
+        false
+    }
+
+    #[getter]
+    fn is_composite(&self) -> bool {
+        true
+    }
+
+    #[getter]
+    fn is_qualified(&self) -> bool {
+        false
+    }
+
+    fn __copy__<'py>(slf: &Bound<'py, Self>) -> Bound<'py, Self> {
+        slf.clone()
+    }
+
+    fn __deepcopy__<'py>(slf: &Bound<'py, Self>, _memo: &Bound<'_, PyAny>) -> Bound<'py, Self> {
+        slf.clone()
+    }
+
     #[getter]
     fn left(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
         self.left.to_pyobject(py)
@@ -446,6 +1937,16 @@ impl CompositePattern {
         self.right.to_pyobject(py)
     }
 
+    #[getter]
+    fn op(&self) -> ObservationOp {
+        self.op
+    }
+
+    #[getter]
+    fn parenthesized(&self) -> bool {
+        self.parenthesized
+    }
+
     fn __repr__(&self) -> String {
         format!("CompositePattern(op={:?}, ...)", self.op)
     }
@@ -455,27 +1956,235 @@ impl CompositePattern {
     #[must_use]
     pub fn new(left: PatternExpr, op: ObservationOp, right: PatternExpr) -> Self {
         Self {
-            left: Box::new(left),
+            left: Arc::new(left),
             op,
-            right: Box::new(right),
+            right: Arc::new(right),
+            parenthesized: false,
+            span: SourceSpan::default(),
+        }
+    }
+
+    /// Attach the node's position in the parsed input.
+    #[must_use]
+    pub fn with_span(mut self, span: SourceSpan) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// The operands, moved out of their `Arc`s when this node is their only
+    /// owner.
+    #[must_use]
+    pub fn into_operands(mut self) -> (PatternExpr, PatternExpr) {
+        self.take_operands()
+    }
+
+    /// Replace both operands with `f` applied to them, keeping the operator,
+    /// grouping and span.
+    #[must_use]
+    pub fn map_operands(mut self, mut f: impl FnMut(PatternExpr) -> PatternExpr) -> Self {
+        let (left, right) = self.take_operands();
+        self.left = Arc::new(f(left));
+        self.right = Arc::new(f(right));
+        self
+    }
+
+    /// Like [`CompositeComparison::map_chain`]: `f` gets the right operand
+    /// of each composite down the left side and the first left operand that
+    /// is not a composite, in document order.
+    #[must_use]
+    pub(crate) fn map_chain(mut self, mut f: impl FnMut(PatternExpr) -> PatternExpr) -> Self {
+        let (mut left, right) = self.take_operands();
+        let mut chain = vec![(self, right)];
+        let first = loop {
+            match left {
+                PatternExpr::Composite(mut c) => {
+                    let (l, r) = c.take_operands();
+                    chain.push((c, r));
+                    left = l;
+                }
+                other => break other,
+            }
+        };
+        let mut left = f(first);
+        loop {
+            let (mut c, right) = chain.pop().expect("the chain holds this node");
+            c.left = Arc::new(left);
+            c.right = Arc::new(f(right));
+            if chain.is_empty() {
+                return c;
+            }
+            left = c.into();
+        }
+    }
+
+    /// The composites down the left side, starting with this one, and the
+    /// operand on the left of the last.
+    pub(crate) fn left_spine(&self) -> (Vec<&Self>, &PatternExpr) {
+        let mut spine = vec![self];
+        loop {
+            let left = spine
+                .last()
+                .expect("the spine holds this node")
+                .left
+                .as_ref();
+            match left {
+                PatternExpr::Composite(c) => spine.push(c),
+                other => return (spine, other),
+            }
         }
     }
+
+    fn take_operands(&mut self) -> (PatternExpr, PatternExpr) {
+        static EMPTY: LazyLock<Arc<PatternExpr>> = LazyLock::new(|| {
+            let path = ObjectPath::new("", Vec::new());
+            let comparison = Comparison::new(path, UnaryOp::Exists, None, false);
+            Arc::new(ComparisonExpr::from(comparison).into())
+        });
+        let left = std::mem::replace(&mut self.left, Arc::clone(&EMPTY));
+        let right = std::mem::replace(&mut self.right, Arc::clone(&EMPTY));
+        (Arc::unwrap_or_clone(left), Arc::unwrap_or_clone(right))
+    }
 }
 
-#[pyclass(frozen)]
-#[derive(Debug, Clone)]
+impl Drop for CompositePattern {
+    /// Dropped like [`CompositeComparison`], as chains of observations are
+    /// folded from the left too.
+    fn drop(&mut self) {
+        let mut next = detach_left_pattern(self);
+        while let Some(mut c) = next {
+            next = detach_left_pattern(&mut c);
+        }
+    }
+}
+
+impl PartialEq for CompositePattern {
+    /// Compared down the left side in a loop, like [`Drop`].
+    fn eq(&self, other: &Self) -> bool {
+        let (a, a_first) = self.left_spine();
+        let (b, b_first) = other.left_spine();
+        a.len() == b.len()
+            && a_first == b_first
+            && a.iter()
+                .zip(&b)
+                .all(|(a, b)| a.op == b.op && a.right == b.right)
+    }
+}
+
+fn detach_left_pattern(c: &mut CompositePattern) -> Option<CompositePattern> {
+    if !matches!(Arc::get_mut(&mut c.left), Some(PatternExpr::Composite(_))) {
+        return None;
+    }
+    let left = std::mem::replace(&mut c.left, Arc::clone(&c.right));
+    match Arc::into_inner(left) {
+        Some(PatternExpr::Composite(left)) => Some(left),
+        _ => None,
+    }
+}
+
+/// A pattern with `REPEATS`, `WITHIN` or `START`/`STOP` qualifiers.
+///
+/// The parser sets one qualifier per node: chained qualifiers produce nested
+/// nodes, the first qualifier innermost.
+#[cfg_attr(feature = "python", pyclass(frozen, eq, extends = PatternNode, module = "stix_patterns_parser"))]
+#[derive(Debug, Clone, PartialEq)]
 pub struct QualifiedPattern {
-    pattern: Box<PatternExpr>,
-    #[pyo3(get)]
+    pub pattern: Arc<PatternExpr>,
     pub repeat: Option<u32>,
-    #[pyo3(get)]
     pub within: Option<f64>,
-    start: Option<DateTime<Utc>>,
-    stop: Option<DateTime<Utc>>,
+    pub start: Option<DateTime<Utc>>,
+    pub stop: Option<DateTime<Utc>>,
+    pub span: SourceSpan,
+    /// The `WITHIN` seconds as written, so that `0.10` or digits beyond
+    /// `f64` precision display unchanged.
+    pub within_raw: RawText<f64>,
 }
 
+#[cfg(feature = "python")]
+/// `(pattern, repeat, within, start, stop)`
+type QualifiedPatternArgs = (
+    Py<PyAny>,
+    Option<u32>,
+    Option<f64>,
+    Option<String>,
+    Option<String>,
+);
+
+#[cfg(feature = "python")]
 #[pymethods]
 impl QualifiedPattern {
+    #[new]
+    #[pyo3(signature = (pattern, repeat=None, within=None, start=None, stop=None))]
+    pub(crate) fn py_new(
+        pattern: PatternExpr,
+        repeat: Option<u32>,
+        within: Option<f64>,
+        start: Option<&Bound<'_, PyAny>>,
+        stop: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<(Self, PatternNode)> {
+        let timestamp = |t: Option<&Bound<'_, PyAny>>| {
+            t.filter(|t| !t.is_none())
+                .map(datetime_from_pyobject)
+                .transpose()
+        };
+        let node = Self::new(pattern, repeat, within, timestamp(start)?, timestamp(stop)?);
+        Ok((node, PatternNode))
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> Reduce<'py, QualifiedPatternArgs> {
+        let this = slf.get();
+        let timestamp =
+            |t: &Option<DateTime<Utc>>| t.map(|t| t.to_rfc3339_opts(SecondsFormat::AutoSi, true));
+        Ok((
+            slf.get_type(),
+            (
+                this.pattern.to_pyobject(slf.py())?,
+                this.repeat,
+                this.within,
+                timestamp(&this.start),
+                timestamp(&this.stop),
+            ),
+        ))
+    }
+
+    #[getter]
+    fn start_offset(&self) -> Option<usize> {
+        self.span.start()
+    }
+
+    #[getter]
+    fn end_offset(&self) -> Option<usize> {
+        self.span.end()
+    }
+
+    #[getter]
+    fn is_single_observation(&self) -> bool {
+        false
+    }
+
+    #[getter]
+    fn is_composite(&self) -> bool {
+        false
+    }
+
+    #[getter]
+    fn is_qualified(&self) -> bool {
+        true
+    }
+
+    fn __copy__<'py>(slf: &Bound<'py, Self>) -> Bound<'py, Self> {
+        slf.clone()
+    }
+
+    fn __deepcopy__<'py>(slf: &Bound<'py, Self>, _memo: &Bound<'_, PyAny>) -> Bound<'py, Self> {
+        slf.clone()
+    }
+
+    /// The `WITHIN` seconds exactly as written in the parsed source, if any.
+    #[getter]
+    fn within_raw(&self) -> Option<&str> {
+        self.within.and_then(|w| self.within_raw.text_for(&w))
+    }
+
     #[getter]
     fn pattern(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
         self.pattern.to_pyobject(py)
@@ -497,6 +2206,16 @@ impl QualifiedPattern {
             .transpose()
     }
 
+    #[getter]
+    fn repeat(&self) -> Option<u32> {
+        self.repeat
+    }
+
+    #[getter]
+    fn within(&self) -> Option<f64> {
+        self.within
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "QualifiedPattern(repeat={:?}, within={:?}, ...)",
@@ -515,16 +2234,38 @@ impl QualifiedPattern {
         stop: Option<DateTime<Utc>>,
     ) -> Self {
         Self {
-            pattern: Box::new(pattern),
+            pattern: Arc::new(pattern),
             repeat,
             within,
             start,
             stop,
+            span: SourceSpan::default(),
+            within_raw: RawText::default(),
+        }
+    }
+
+    /// Attach the node's position in the parsed input.
+    #[must_use]
+    pub fn with_span(self, span: SourceSpan) -> Self {
+        Self { span, ..self }
+    }
+
+    /// Record the source text of the current `within` value, which
+    /// `Display` then writes instead of the canonical form.
+    #[must_use]
+    pub fn with_raw_within(self, text: impl Into<String>) -> Self {
+        match self.within {
+            Some(within) => Self {
+                within_raw: RawText::new(text, within),
+                ..self
+            },
+            None => self,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "python", derive(FromPyObject))]
 pub enum PatternExpr {
     Comparison(ComparisonExpr),
     Composite(CompositePattern),
@@ -532,11 +2273,23 @@ pub enum PatternExpr {
 }
 
 impl PatternExpr {
+    /// The node's span. For an observation this is the span of its
+    /// comparison expression, without the brackets.
+    #[must_use]
+    pub fn span(&self) -> SourceSpan {
+        match self {
+            Self::Comparison(c) => c.span(),
+            Self::Composite(c) => c.span,
+            Self::Qualified(q) => q.span,
+        }
+    }
+
+    #[cfg(feature = "python")]
     pub fn to_pyobject(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
         match self {
             Self::Comparison(c) => c.to_pyobject(py),
-            Self::Composite(c) => Ok(c.clone().into_pyobject(py)?.into_any().unbind()),
-            Self::Qualified(q) => Ok(q.clone().into_pyobject(py)?.into_any().unbind()),
+            Self::Composite(c) => node_into_pyobject(py, c.clone()),
+            Self::Qualified(q) => node_into_pyobject(py, q.clone()),
         }
     }
 }
@@ -559,6 +2312,7 @@ impl From<QualifiedPattern> for PatternExpr {
     }
 }
 
+#[cfg(feature = "python")]
 fn datetime_to_pyobject(dt: &DateTime<Utc>, py: Python<'_>) -> PyResult<Py<PyDateTime>> {
     let datetime = PyDateTime::new(
         py,
@@ -573,3 +2327,138 @@ fn datetime_to_pyobject(dt: &DateTime<Utc>, py: Python<'_>) -> PyResult<Py<PyDat
     )?;
     Ok(datetime.unbind())
 }
+
+#[cfg(feature = "python")]
+/// Accepts a `datetime` (naive values are taken as UTC) or an RFC 3339 string.
+fn datetime_from_pyobject(obj: &Bound<'_, PyAny>) -> PyResult<DateTime<Utc>> {
+    if let Ok(s) = obj.extract::<String>() {
+        return DateTime::parse_from_rfc3339(&s)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|e| PyValueError::new_err(format!("invalid timestamp {s:?}: {e}")));
+    }
+
+    let mut datetime = obj.cast::<PyDateTime>()?.clone();
+    if datetime.get_tzinfo().is_some() {
+        let utc = PyTzInfo::utc(obj.py())?;
+        datetime = datetime
+            .call_method1("astimezone", (utc,))?
+            .cast_into::<PyDateTime>()?;
+    }
+    NaiveDate::from_ymd_opt(
+        datetime.get_year(),
+        datetime.get_month().into(),
+        datetime.get_day().into(),
+    )
+    .and_then(|date| {
+        date.and_hms_micro_opt(
+            datetime.get_hour().into(),
+            datetime.get_minute().into(),
+            datetime.get_second().into(),
+            datetime.get_microsecond(),
+        )
+    })
+    .map(|dt| dt.and_utc())
+    .ok_or_else(|| PyValueError::new_err("timestamp out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clone_shares_children() {
+        let leaf = |v: i64| {
+            PatternExpr::from(ComparisonExpr::from(Comparison::new(
+                ObjectPath::new("a", vec![PathComponent::new("x", None)]),
+                ComparisonOp::Eq,
+                Some(StixValue::Int(v).into()),
+                false,
+            )))
+        };
+        let inner = CompositePattern::new(leaf(1), ObservationOp::And, leaf(2));
+        let outer = CompositePattern::new(inner.into(), ObservationOp::Or, leaf(3));
+        let copy = outer.clone();
+        assert!(Arc::ptr_eq(&outer.left, &copy.left));
+        assert!(Arc::ptr_eq(&outer.right, &copy.right));
+    }
+
+    #[test]
+    fn test_operator_codes() {
+        // These codes are part of the public interface and must not change.
+        let comparison = [
+            (ComparisonOp::Eq, 0),
+            (ComparisonOp::Neq, 1),
+            (ComparisonOp::Gt, 2),
+            (ComparisonOp::Lt, 3),
+            (ComparisonOp::Ge, 4),
+            (ComparisonOp::Le, 5),
+            (ComparisonOp::In, 6),
+            (ComparisonOp::Like, 7),
+            (ComparisonOp::Matches, 8),
+            (ComparisonOp::IsSubset, 9),
+            (ComparisonOp::IsSuperset, 10),
+        ];
+        for (op, code) in comparison {
+            assert_eq!(op.code(), code);
+            assert_eq!(ComparisonOp::from_code(code), Some(op));
+        }
+        assert_eq!(ComparisonOp::from_code(11), None);
+        assert!(ComparisonOp::ALL.iter().map(ComparisonOp::code).eq(0..11));
+
+        assert_eq!(UnaryOp::Exists.code(), 0);
+        assert_eq!(UnaryOp::from_code(0), Some(UnaryOp::Exists));
+        assert_eq!(UnaryOp::from_code(1), None);
+        assert_eq!(UnaryOp::ALL, [UnaryOp::Exists]);
+
+        for (op, code) in [(BooleanOp::And, 0), (BooleanOp::Or, 1)] {
+            assert_eq!(op.code(), code);
+            assert_eq!(BooleanOp::from_code(code), Some(op));
+        }
+        assert_eq!(BooleanOp::from_code(2), None);
+
+        let observation = [
+            (ObservationOp::And, 0),
+            (ObservationOp::Or, 1),
+            (ObservationOp::FollowedBy, 2),
+        ];
+        for (op, code) in observation {
+            assert_eq!(op.code(), code);
+            assert_eq!(ObservationOp::from_code(code), Some(op));
+        }
+        assert_eq!(ObservationOp::from_code(3), None);
+    }
+
+    #[test]
+    fn test_stix_value_order() {
+        let mut values = vec![
+            StixValue::Binary("aGk=".into()),
+            StixValue::Float(f64::NAN),
+            StixValue::Bool(false),
+            StixValue::Float(1.5),
+            StixValue::Int(2),
+            StixValue::String("b".into()),
+            StixValue::Float(-0.0),
+            StixValue::Int(-1),
+            StixValue::String("a".into()),
+            StixValue::Float(0.0),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            [
+                StixValue::String("a".into()),
+                StixValue::String("b".into()),
+                StixValue::Int(-1),
+                StixValue::Int(2),
+                StixValue::Float(-0.0),
+                StixValue::Float(0.0),
+                StixValue::Float(1.5),
+                StixValue::Float(f64::NAN),
+                StixValue::Bool(false),
+                StixValue::Binary("aGk=".into()),
+            ]
+        );
+        assert_eq!(StixValue::Float(f64::NAN), StixValue::Float(-f64::NAN));
+        assert_ne!(StixValue::Float(-0.0), StixValue::Float(0.0));
+    }
+}
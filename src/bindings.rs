@@ -1,32 +1,712 @@
 //! PyO3 bindings registration for STIX pattern parser.
 
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
 use pyo3::prelude::*;
+use pyo3::types::{PyDict, PySet};
 
+use crate::analysis::{self, NodeRef, PatternSummary, Qualifier};
 use crate::ast::{
-    BooleanOp, Comparison, ComparisonOp, CompositeComparison, CompositePattern, ObjectPath,
-    ObservationOp, PathComponent, QualifiedPattern, UnaryOp,
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, ComparisonRhs,
+    CompositeComparison, CompositePattern, ObjectPath, ObservationOp, PathComponent, PatternExpr,
+    PatternNode, PropertyKind, QualifiedPattern, StixValue, UnaryOp, node_into_pyobject,
 };
-use crate::parser;
+use crate::builder;
+use crate::cache::ParserCache;
+use crate::diagnostics::ParseWarning;
+use crate::dict;
+use crate::dot;
+use crate::es::{self, EsError};
+use crate::eval::{self, Data, EvalError};
+use crate::json;
+use crate::like;
+use crate::lint;
+use crate::parser::{self, ParseError, ParseOptions, ParseStats, PatternComment};
+use crate::serialize::{self, FloatFormat};
+use crate::sql::{self, SqlError};
+use crate::transform;
+
+impl From<ParseError> for PyErr {
+    fn from(err: ParseError) -> Self {
+        pyo3::exceptions::PyValueError::new_err(err.to_string())
+    }
+}
+
+impl From<SqlError> for PyErr {
+    fn from(err: SqlError) -> Self {
+        pyo3::exceptions::PyValueError::new_err(err.to_string())
+    }
+}
+
+impl From<EsError> for PyErr {
+    fn from(err: EsError) -> Self {
+        pyo3::exceptions::PyValueError::new_err(err.to_string())
+    }
+}
+
+impl From<EvalError> for PyErr {
+    fn from(err: EvalError) -> Self {
+        match err {
+            EvalError::TypeMismatch { .. } => {
+                pyo3::exceptions::PyTypeError::new_err(err.to_string())
+            }
+            _ => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+// The parse functions release the GIL while the Rust parser runs, so they
+// can be called concurrently from threads, e.g. via `loop.run_in_executor`.
 
 #[pyfunction]
-pub fn parse(py: Python<'_>, pattern: &str) -> PyResult<Py<PyAny>> {
-    let ast = parser::parse_pattern(pattern)
-        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+#[pyo3(signature = (pattern, options = None))]
+pub fn parse(py: Python<'_>, pattern: &str, options: Option<ParseOptions>) -> PyResult<Py<PyAny>> {
+    let options = options.unwrap_or_default();
+    let ast = py.detach(|| parser::parse_pattern_with_options(pattern, &options))?;
 
     ast.to_pyobject(py)
 }
 
+#[pyfunction]
+#[pyo3(signature = (expression, options = None))]
+pub fn parse_comparison_expr(
+    py: Python<'_>,
+    expression: &str,
+    options: Option<ParseOptions>,
+) -> PyResult<Py<PyAny>> {
+    let options = options.unwrap_or_default();
+    let expr = py.detach(|| parser::parse_comparison_expr_with_options(expression, &options))?;
+
+    expr.to_pyobject(py)
+}
+
+/// Parse the `pattern` of a STIX indicator object. A missing
+/// `pattern_type` is taken as `"stix"`, as in STIX 2.0 indicators.
+#[pyfunction]
+#[pyo3(signature = (indicator, options = None))]
+pub fn parse_indicator(
+    py: Python<'_>,
+    indicator: &Bound<'_, PyDict>,
+    options: Option<ParseOptions>,
+) -> PyResult<Py<PyAny>> {
+    let pattern_type: Option<String> = match indicator.get_item("pattern_type")? {
+        Some(t) if !t.is_none() => Some(t.extract()?),
+        _ => None,
+    };
+    if let Some(pattern_type) = pattern_type.filter(|t| t != "stix") {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "Indicator pattern_type is {pattern_type:?}, only \"stix\" patterns can be parsed"
+        )));
+    }
+    let pattern: String = indicator
+        .get_item("pattern")?
+        .ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err("Indicator has no \"pattern\" field")
+        })?
+        .extract()?;
+
+    parse(py, &pattern, options)
+}
+
+#[pyfunction]
+#[pyo3(signature = (pattern, options = None))]
+pub fn parse_with_diagnostics(
+    py: Python<'_>,
+    pattern: &str,
+    options: Option<ParseOptions>,
+) -> PyResult<(Py<PyAny>, Vec<ParseWarning>)> {
+    let options = options.unwrap_or_default();
+    let (ast, warnings) =
+        py.detach(|| parser::parse_pattern_with_diagnostics(pattern, &options))?;
+
+    Ok((ast.to_pyobject(py)?, warnings))
+}
+
+#[pyfunction]
+#[pyo3(signature = (pattern, options = None))]
+pub fn parse_with_stats(
+    py: Python<'_>,
+    pattern: &str,
+    options: Option<ParseOptions>,
+) -> PyResult<(Py<PyAny>, ParseStats)> {
+    let options = options.unwrap_or_default();
+    let (ast, stats) = py.detach(|| parser::parse_pattern_with_stats(pattern, &options))?;
+
+    Ok((ast.to_pyobject(py)?, stats))
+}
+
+#[pyfunction]
+#[pyo3(signature = (pattern, options = None))]
+pub fn parse_recover(
+    py: Python<'_>,
+    pattern: &str,
+    options: Option<ParseOptions>,
+) -> PyResult<(Option<Py<PyAny>>, Vec<String>)> {
+    let options = options.unwrap_or_default();
+    let (ast, errors) = py.detach(|| parser::parse_pattern_recover(pattern, &options));
+    let ast = ast.map(|ast| ast.to_pyobject(py)).transpose()?;
+
+    Ok((ast, errors.iter().map(ToString::to_string).collect()))
+}
+
+/// `(line, ast, error)`, with either the AST or the error message set.
+type LineResult = (usize, Option<Py<PyAny>>, Option<String>);
+
+/// Parse a file of one pattern per line into a [`LineResult`] per pattern,
+/// through `parser::parse_file`. The line number counts from the start of
+/// the file, but positions inside the error message are relative to its
+/// line. Reading the file and parsing run without the GIL.
+#[pyfunction]
+#[pyo3(signature = (path, options = None))]
+pub fn parse_file(
+    py: Python<'_>,
+    path: PathBuf,
+    options: Option<ParseOptions>,
+) -> PyResult<Vec<LineResult>> {
+    let options = options.unwrap_or_default();
+    let results = py.detach(|| parser::parse_file(&path, &options))?;
+
+    results
+        .into_iter()
+        .map(|(line, result)| match result {
+            Ok(ast) => Ok((line, Some(ast.to_pyobject(py)?), None)),
+            Err(err) => Ok((line, None, Some(err.to_string()))),
+        })
+        .collect()
+}
+
+#[pyfunction]
+pub fn find_comments(pattern: &str) -> PyResult<Vec<PatternComment>> {
+    let options = ParseOptions {
+        allow_comments: true,
+        ..Default::default()
+    };
+    parser::parse_pattern_with_options(pattern, &options)?;
+
+    Ok(parser::find_comments(pattern))
+}
+
+#[pyfunction]
+pub fn clear_intern_pool() {
+    parser::clear_intern_pool();
+}
+
+#[pyfunction]
+#[pyo3(signature = (pattern, indent = 2))]
+pub fn format_pattern(pattern: &str, indent: usize) -> PyResult<String> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(serialize::format_pattern(&ast, indent))
+}
+
+#[pyfunction]
+pub fn like_to_regex(pattern: &str) -> String {
+    like::like_to_regex(pattern)
+}
+
+#[pyfunction]
+pub fn like_shape(comparison: &Bound<'_, Comparison>) -> PyResult<&'static str> {
+    let comparison = comparison.get();
+    match (&comparison.op, &comparison.constant) {
+        (
+            ComparisonOperator::Comparison(ComparisonOp::Like),
+            Some(ComparisonRhs::Value(StixValue::String(pattern))),
+        ) => Ok(like::like_shape(pattern).as_str()),
+        _ => Err(pyo3::exceptions::PyValueError::new_err(
+            "like_shape expects a LIKE comparison with a string operand",
+        )),
+    }
+}
+
+#[pyfunction]
+pub fn summarize(pattern: &str) -> PyResult<PatternSummary> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(analysis::summarize(&ast))
+}
+
+#[pyfunction]
+pub fn push_negations(py: Python<'_>, expr: ComparisonExpr) -> PyResult<Py<PyAny>> {
+    transform::push_negations(expr).to_pyobject(py)
+}
+
+#[pyfunction]
+pub fn equivalent(p1: &str, p2: &str) -> PyResult<bool> {
+    let a = parser::parse_pattern(p1)?;
+    let b = parser::parse_pattern(p2)?;
+
+    Ok(transform::equivalent(&a, &b))
+}
+
+#[pyfunction]
+pub fn diff(p1: &str, p2: &str) -> PyResult<Vec<String>> {
+    let a = parser::parse_pattern(p1)?;
+    let b = parser::parse_pattern(p2)?;
+
+    Ok(transform::diff(&a, &b))
+}
+
+#[pyfunction]
+pub fn flatten(
+    py: Python<'_>,
+    expr: ComparisonExpr,
+) -> PyResult<(Option<BooleanOp>, Vec<Py<PyAny>>)> {
+    let (op, operands) = analysis::flatten_comparison(&expr);
+    let operands = operands
+        .into_iter()
+        .map(|operand| operand.to_pyobject(py))
+        .collect::<PyResult<_>>()?;
+
+    Ok((op, operands))
+}
+
+#[pyfunction]
+pub fn flatten_pattern(
+    py: Python<'_>,
+    expr: PatternExpr,
+) -> PyResult<(Option<ObservationOp>, Vec<Py<PyAny>>)> {
+    let (op, operands) = analysis::flatten_pattern(&expr);
+    let operands = operands
+        .into_iter()
+        .map(|operand| operand.to_pyobject(py))
+        .collect::<PyResult<_>>()?;
+
+    Ok((op, operands))
+}
+
+#[pyfunction]
+pub fn contains_operator(pattern: &str, op: ComparisonOperator) -> PyResult<bool> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(ast.contains_operator(op))
+}
+
+#[pyfunction]
+pub fn operators_used<'py>(py: Python<'py>, pattern: &str) -> PyResult<Bound<'py, PySet>> {
+    let ast = parser::parse_pattern(pattern)?;
+    let operators = ast
+        .operators_used()
+        .into_iter()
+        .map(|op| op.to_pyobject(py))
+        .collect::<PyResult<Vec<_>>>()?;
+
+    PySet::new(py, operators)
+}
+
+/// Every comparison and unary operator, as dicts with the enum member under
+/// `"op"`, its `"name"`, `"symbol"` and `"code"`, its `"arity"`, `"unary"`
+/// or `"binary"`, what it takes on the right under `"rhs"`, `"scalar"`,
+/// `"list"` or `None`, and under `"negatable"` whether `NOT` may precede it.
+#[pyfunction]
+pub fn operator_table(py: Python<'_>) -> PyResult<Vec<Bound<'_, PyDict>>> {
+    let entry = |op: ComparisonOperator, repr: &str, code: u8| -> PyResult<_> {
+        let dict = PyDict::new(py);
+        dict.set_item("op", op.to_pyobject(py)?)?;
+        dict.set_item("name", repr.split_once('.').map_or(repr, |(_, name)| name))?;
+        let (symbol, arity, rhs, negatable) = match op {
+            ComparisonOperator::Comparison(op) => {
+                let rhs = if op.takes_list() { "list" } else { "scalar" };
+                (op.value(), "binary", Some(rhs), true)
+            }
+            ComparisonOperator::Unary(op) => (op.value(), "unary", None, false),
+        };
+        dict.set_item("symbol", symbol)?;
+        dict.set_item("code", code)?;
+        dict.set_item("arity", arity)?;
+        dict.set_item("rhs", rhs)?;
+        dict.set_item("negatable", negatable)?;
+        Ok(dict)
+    };
+
+    let comparison = ComparisonOp::ALL
+        .into_iter()
+        .map(|op| entry(op.into(), op.__repr__(), op.code()));
+    let unary = UnaryOp::ALL
+        .into_iter()
+        .map(|op| entry(op.into(), op.__repr__(), op.code()));
+    comparison.chain(unary).collect()
+}
+
+#[pyfunction]
+pub fn qualifiers<'py>(py: Python<'py>, pattern: &str) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    let ast = parser::parse_pattern(pattern)?;
+    let timestamp = |dt: Option<_>| -> PyResult<Py<PyAny>> {
+        match dt {
+            Some(dt) => StixValue::Timestamp(dt).to_pyobject(py),
+            None => Ok(py.None()),
+        }
+    };
+
+    ast.qualifiers()
+        .into_iter()
+        .map(|info| {
+            let dict = PyDict::new(py);
+            dict.set_item("kind", info.qualifier.kind())?;
+            dict.set_item("object_types", info.object_types)?;
+            match info.qualifier {
+                Qualifier::Repeats(n) => dict.set_item("repeat", n)?,
+                Qualifier::Within(seconds) => dict.set_item("within", seconds)?,
+                Qualifier::Interval { start, stop } => {
+                    dict.set_item("start", timestamp(start)?)?;
+                    dict.set_item("stop", timestamp(stop)?)?;
+                }
+            }
+            Ok(dict)
+        })
+        .collect()
+}
+
+/// Per observation, the object types under `"any_of"`, one list per
+/// alternative or `None` past `analysis::MAX_ALTERNATIVES` of them, and
+/// those every alternative needs under `"all_of"`.
+#[pyfunction]
+pub fn required_objects<'py>(py: Python<'py>, pattern: &str) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    ast.observations()
+        .into_iter()
+        .map(|observation| {
+            let dict = PyDict::new(py);
+            dict.set_item("all_of", Vec::from_iter(observation.common_objects()))?;
+            dict.set_item(
+                "any_of",
+                observation.required_objects().map(|alternatives| {
+                    alternatives
+                        .into_iter()
+                        .map(Vec::from_iter)
+                        .collect::<Vec<_>>()
+                }),
+            )?;
+            Ok(dict)
+        })
+        .collect()
+}
+
+#[pyfunction]
+#[pyo3(signature = (pattern, object_type, property, ignore_indices = false))]
+pub fn find_comparisons(
+    py: Python<'_>,
+    pattern: &str,
+    object_type: &str,
+    property: &str,
+    ignore_indices: bool,
+) -> PyResult<Vec<Py<PyAny>>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    ast.find_comparisons(object_type, property, ignore_indices)
+        .into_iter()
+        .map(|c| node_into_pyobject(py, c.clone()))
+        .collect()
+}
+
+/// Byte offsets of the first node of `pattern` for which `predicate`
+/// returns true, visiting parents before their operands. An exception
+/// raised by `predicate` stops the search and propagates.
+#[pyfunction]
+pub fn locate(
+    py: Python<'_>,
+    pattern: &str,
+    predicate: &Bound<'_, PyAny>,
+) -> PyResult<Option<(usize, usize)>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    let mut error = None;
+    let found = ast.find_node(|node| {
+        let matched = match node {
+            NodeRef::Comparison(c) => node_into_pyobject(py, c.clone()),
+            NodeRef::CompositeComparison(c) => node_into_pyobject(py, c.clone()),
+            NodeRef::CompositePattern(c) => node_into_pyobject(py, c.clone()),
+            NodeRef::QualifiedPattern(q) => node_into_pyobject(py, q.clone()),
+        }
+        .and_then(|node| predicate.call1((node,))?.is_truthy());
+        matched.unwrap_or_else(|err| {
+            error = Some(err);
+            true
+        })
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+    Ok(found.and_then(|node| node.span().start().zip(node.span().end())))
+}
+
+#[pyfunction]
+pub fn observation_groups(py: Python<'_>, pattern: &str) -> PyResult<Vec<Vec<Py<PyAny>>>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    ast.observation_groups()
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .map(|c| node_into_pyobject(py, c.clone()))
+                .collect()
+        })
+        .collect()
+}
+
+#[pyfunction]
+pub fn constants(py: Python<'_>, pattern: &str) -> PyResult<Vec<Py<PyAny>>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    ast.constants()
+        .into_iter()
+        .map(|value| value.to_pyobject(py))
+        .collect()
+}
+
+#[pyfunction]
+pub fn depth(pattern: &str) -> PyResult<usize> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(ast.depth())
+}
+
+#[pyfunction]
+pub fn observation_count(pattern: &str) -> PyResult<usize> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(ast.observation_count())
+}
+
+#[pyfunction]
+pub fn is_trivial(pattern: &str) -> PyResult<bool> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(ast.is_trivial())
+}
+
+#[pyfunction]
+pub fn matches_object_type(pattern: &str, glob: &str) -> PyResult<bool> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(ast.matches_object_type(glob))
+}
+
+#[pyfunction]
+pub fn find_contradictions(pattern: &str) -> PyResult<Vec<String>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(lint::find_contradictions(&ast))
+}
+
+#[pyfunction]
+#[pyo3(name = "lint")]
+pub fn lint_pattern(pattern: &str) -> PyResult<Vec<String>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(lint::lint(&ast))
+}
+
+#[pyfunction]
+pub fn extract_iocs(pattern: &str) -> PyResult<BTreeMap<String, Vec<String>>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(analysis::extract_iocs(&ast))
+}
+
+#[pyfunction]
+#[pyo3(signature = (pattern, placeholder = "REDACTED"))]
+pub fn redact(pattern: &str, placeholder: &str) -> PyResult<String> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(transform::redact(ast, placeholder).to_string())
+}
+
+#[pyfunction]
+pub fn strip_qualifiers(pattern: &str) -> PyResult<String> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(transform::strip_qualifiers(ast).to_string())
+}
+
+#[pyfunction]
+pub fn simplify(pattern: &str) -> PyResult<String> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(transform::simplify(ast).to_string())
+}
+
+#[pyfunction]
+pub fn combine(patterns: Vec<String>, op: ObservationOp) -> PyResult<String> {
+    let asts = patterns
+        .iter()
+        .enumerate()
+        .map(|(i, pattern)| {
+            parser::parse_pattern(pattern).map_err(|err| {
+                pyo3::exceptions::PyValueError::new_err(format!("pattern {i}: {err}"))
+            })
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    builder::combine(asts, op)
+        .map(|ast| ast.to_string())
+        .ok_or_else(|| pyo3::exceptions::PyValueError::new_err("no patterns to combine"))
+}
+
+#[pyfunction]
+pub fn matches_data(pattern: &str, data: Data) -> PyResult<bool> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(eval::matches_data(&ast, &data)?)
+}
+
+#[pyfunction]
+pub fn resolve_path(
+    py: Python<'_>,
+    data: Data,
+    object_path: ObjectPath,
+) -> PyResult<Vec<Py<PyAny>>> {
+    eval::resolve_path(&data, &object_path)
+        .into_iter()
+        .map(|value| value.to_pyobject(py))
+        .collect()
+}
+
+#[pyfunction]
+#[pyo3(signature = (op, lhs, rhs, rhs_type = None))]
+pub fn apply_op(
+    op: ComparisonOp,
+    lhs: Data,
+    rhs: &Bound<'_, PyAny>,
+    rhs_type: Option<&Bound<'_, PyAny>>,
+) -> PyResult<bool> {
+    let rhs = ComparisonRhs::from_pyobject(rhs, rhs_type)?;
+
+    Ok(eval::apply_op(op, &lhs, &rhs)?)
+}
+
+#[pyfunction]
+pub fn to_sql(
+    py: Python<'_>,
+    pattern: &str,
+    table_map: HashMap<String, String>,
+) -> PyResult<(String, Vec<Py<PyAny>>)> {
+    let ast = parser::parse_pattern(pattern)?;
+    let (clause, params) = sql::to_sql(&ast, &table_map)?;
+    let params = params
+        .iter()
+        .map(|value| value.to_pyobject(py))
+        .collect::<PyResult<_>>()?;
+
+    Ok((clause, params))
+}
+
+#[pyfunction]
+pub fn to_es_query(
+    py: Python<'_>,
+    pattern: &str,
+    field_map: HashMap<String, String>,
+) -> PyResult<Py<PyAny>> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    es::to_es_query(&ast, &field_map)?.to_pyobject(py)
+}
+
+#[pyfunction]
+pub fn to_dot(pattern: &str) -> PyResult<String> {
+    let ast = parser::parse_pattern(pattern)?;
+
+    Ok(dot::to_dot(&ast))
+}
+
+#[pyfunction]
+#[pyo3(signature = (expr, preserve_grouping = false, *, min_decimals = 1, scientific_threshold = None))]
+pub fn to_stix_string(
+    expr: PatternExpr,
+    preserve_grouping: bool,
+    min_decimals: usize,
+    scientific_threshold: Option<u32>,
+) -> String {
+    let floats = FloatFormat {
+        min_decimals,
+        scientific_threshold,
+    };
+    serialize::to_stix_string_with(&expr, preserve_grouping, floats)
+}
+
+#[pyfunction]
+pub fn to_dict<'py>(py: Python<'py>, expr: PatternExpr) -> PyResult<Bound<'py, PyDict>> {
+    dict::to_dict(py, &expr)
+}
+
+#[pyfunction]
+pub fn to_json(expr: PatternExpr) -> String {
+    json::to_json(&expr)
+}
+
+#[pyfunction]
+pub fn from_dict(py: Python<'_>, data: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+    dict::from_dict(data)?.to_pyobject(py)
+}
+
 pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<ComparisonOp>()?;
     m.add_class::<UnaryOp>()?;
     m.add_class::<BooleanOp>()?;
     m.add_class::<ObservationOp>()?;
+    m.add_class::<PropertyKind>()?;
     m.add_class::<PathComponent>()?;
     m.add_class::<ObjectPath>()?;
+    m.add_class::<PatternNode>()?;
     m.add_class::<Comparison>()?;
     m.add_class::<CompositeComparison>()?;
     m.add_class::<CompositePattern>()?;
     m.add_class::<QualifiedPattern>()?;
+    m.add_class::<ParseWarning>()?;
+    m.add_class::<ParseOptions>()?;
+    m.add_class::<PatternSummary>()?;
+    m.add_class::<ParseStats>()?;
+    m.add_class::<PatternComment>()?;
+    m.add_class::<ParserCache>()?;
     m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_comparison_expr, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_indicator, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_with_diagnostics, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_with_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_recover, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_file, m)?)?;
+    m.add_function(wrap_pyfunction!(find_comments, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_intern_pool, m)?)?;
+    m.add_function(wrap_pyfunction!(format_pattern, m)?)?;
+    m.add_function(wrap_pyfunction!(like_to_regex, m)?)?;
+    m.add_function(wrap_pyfunction!(like_shape, m)?)?;
+    m.add_function(wrap_pyfunction!(summarize, m)?)?;
+    m.add_function(wrap_pyfunction!(push_negations, m)?)?;
+    m.add_function(wrap_pyfunction!(equivalent, m)?)?;
+    m.add_function(wrap_pyfunction!(diff, m)?)?;
+    m.add_function(wrap_pyfunction!(flatten, m)?)?;
+    m.add_function(wrap_pyfunction!(flatten_pattern, m)?)?;
+    m.add_function(wrap_pyfunction!(contains_operator, m)?)?;
+    m.add_function(wrap_pyfunction!(operators_used, m)?)?;
+    m.add_function(wrap_pyfunction!(operator_table, m)?)?;
+    m.add_function(wrap_pyfunction!(qualifiers, m)?)?;
+    m.add_function(wrap_pyfunction!(required_objects, m)?)?;
+    m.add_function(wrap_pyfunction!(find_comparisons, m)?)?;
+    m.add_function(wrap_pyfunction!(locate, m)?)?;
+    m.add_function(wrap_pyfunction!(observation_groups, m)?)?;
+    m.add_function(wrap_pyfunction!(constants, m)?)?;
+    m.add_function(wrap_pyfunction!(depth, m)?)?;
+    m.add_function(wrap_pyfunction!(observation_count, m)?)?;
+    m.add_function(wrap_pyfunction!(is_trivial, m)?)?;
+    m.add_function(wrap_pyfunction!(matches_object_type, m)?)?;
+    m.add_function(wrap_pyfunction!(find_contradictions, m)?)?;
+    m.add_function(wrap_pyfunction!(lint_pattern, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_iocs, m)?)?;
+    m.add_function(wrap_pyfunction!(redact, m)?)?;
+    m.add_function(wrap_pyfunction!(strip_qualifiers, m)?)?;
+    m.add_function(wrap_pyfunction!(simplify, m)?)?;
+    m.add_function(wrap_pyfunction!(combine, m)?)?;
+    m.add_function(wrap_pyfunction!(matches_data, m)?)?;
+    m.add_function(wrap_pyfunction!(resolve_path, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_op, m)?)?;
+    m.add_function(wrap_pyfunction!(to_sql, m)?)?;
+    m.add_function(wrap_pyfunction!(to_es_query, m)?)?;
+    m.add_function(wrap_pyfunction!(to_dot, m)?)?;
+    m.add_function(wrap_pyfunction!(to_stix_string, m)?)?;
+    m.add_function(wrap_pyfunction!(to_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(to_json, m)?)?;
+    m.add_function(wrap_pyfunction!(from_dict, m)?)?;
     Ok(())
 }
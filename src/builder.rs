@@ -0,0 +1,333 @@
+//! Fluent construction of patterns from Rust code.
+//!
+//! ```
+//! use stix_patterns_parser::ast::ComparisonOp;
+//! use stix_patterns_parser::builder::{ObservationBuilder, PatternBuilder, path};
+//!
+//! let malware = ObservationBuilder::new()
+//!     .compare(path("file", &["name"]), ComparisonOp::Eq, "evil.exe")
+//!     .or()
+//!     .compare(path("file", &["size"]), ComparisonOp::Gt, 1024_i64)
+//!     .build();
+//! let beacon = ObservationBuilder::new()
+//!     .exists(path("network-traffic", &["dst_port"]))
+//!     .build();
+//! let pattern = PatternBuilder::from(malware)
+//!     .followed_by(beacon)
+//!     .within(300.0)
+//!     .build();
+//! assert_eq!(
+//!     pattern.to_string(),
+//!     "([file:name = 'evil.exe' OR file:size > 1024] FOLLOWEDBY \
+//!      [EXISTS network-traffic:dst_port]) WITHIN 300 SECONDS"
+//! );
+//! ```
+
+use chrono::{DateTime, Utc};
+
+use crate::ast::{
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonRhs, CompositeComparison,
+    CompositePattern, ObjectPath, ObservationOp, PathComponent, PatternExpr, QualifiedPattern,
+    StixValue, UnaryOp,
+};
+
+/// An object path with one unindexed component per property.
+#[must_use]
+pub fn path(object_type: &str, properties: &[&str]) -> ObjectPath {
+    ObjectPath::new(
+        object_type.to_owned(),
+        properties
+            .iter()
+            .map(|p| PathComponent::new((*p).to_owned(), None))
+            .collect(),
+    )
+}
+
+/// Builds the comparison expression of a single `[...]` observation.
+///
+/// Comparisons are combined left to right whatever their operators, so
+/// `a.or().b.and().c` is `(a OR b) AND c`, where the parser reads
+/// `a OR b AND c` as `a OR (b AND c)`: use [`group`](Self::group) for the
+/// latter. `and` and `or` set the operator joining the next comparison,
+/// which defaults to `AND`.
+#[derive(Debug, Clone, Default)]
+pub struct ObservationBuilder {
+    expr: Option<ComparisonExpr>,
+    pending: Option<BooleanOp>,
+}
+
+impl ObservationBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn compare(self, path: ObjectPath, op: ComparisonOp, value: impl Into<StixValue>) -> Self {
+        self.push(Comparison::new(path, op, Some(value.into().into()), false))
+    }
+
+    /// `path NOT op value`.
+    #[must_use]
+    pub fn compare_not(
+        self,
+        path: ObjectPath,
+        op: ComparisonOp,
+        value: impl Into<StixValue>,
+    ) -> Self {
+        self.push(Comparison::new(path, op, Some(value.into().into()), true))
+    }
+
+    /// A comparison against a list of values, e.g. for `IN`.
+    #[must_use]
+    pub fn compare_list<V: Into<StixValue>>(
+        self,
+        path: ObjectPath,
+        op: ComparisonOp,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        let values: Vec<StixValue> = values.into_iter().map(Into::into).collect();
+        self.push(Comparison::new(
+            path,
+            op,
+            Some(ComparisonRhs::from(values)),
+            false,
+        ))
+    }
+
+    #[must_use]
+    pub fn exists(self, path: ObjectPath) -> Self {
+        self.push(Comparison::new(path, UnaryOp::Exists, None, false))
+    }
+
+    /// Add the expression of another builder as a parenthesized operand.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `group` holds no comparison.
+    #[must_use]
+    pub fn group(self, group: ObservationBuilder) -> Self {
+        let mut expr = group.into_expr();
+        if let ComparisonExpr::Composite(c) = &mut expr {
+            c.parenthesized = true;
+        }
+        self.push(expr)
+    }
+
+    #[must_use]
+    pub fn and(mut self) -> Self {
+        self.pending = Some(BooleanOp::And);
+        self
+    }
+
+    #[must_use]
+    pub fn or(mut self) -> Self {
+        self.pending = Some(BooleanOp::Or);
+        self
+    }
+
+    /// The comparison expression built so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no comparison was added.
+    #[must_use]
+    pub fn into_expr(self) -> ComparisonExpr {
+        self.expr.expect("observation has no comparison")
+    }
+
+    /// The observation as a pattern.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no comparison was added.
+    #[must_use]
+    pub fn build(self) -> PatternExpr {
+        self.into_expr().into()
+    }
+
+    fn push(mut self, comparison: impl Into<ComparisonExpr>) -> Self {
+        let right = comparison.into();
+        self.expr = Some(match self.expr.take() {
+            None => right,
+            Some(left) => {
+                CompositeComparison::new(left, self.pending.take().unwrap_or_default(), right)
+                    .into()
+            }
+        });
+        self
+    }
+}
+
+impl From<ObservationBuilder> for PatternExpr {
+    fn from(observation: ObservationBuilder) -> Self {
+        observation.build()
+    }
+}
+
+/// Join patterns left to right with `op`, as in `[a] OR [b] OR [c]`, or
+/// `None` if there are none.
+#[must_use]
+pub fn combine(
+    patterns: impl IntoIterator<Item = PatternExpr>,
+    op: ObservationOp,
+) -> Option<PatternExpr> {
+    patterns
+        .into_iter()
+        .reduce(|left, right| CompositePattern::new(left, op, right).into())
+}
+
+/// Combines observations with observation operators and qualifiers.
+///
+/// Operators apply left to right and a qualifier applies to everything
+/// built so far, so `a.followed_by(b).within(5.0)` qualifies the whole
+/// sequence.
+#[derive(Debug, Clone)]
+pub struct PatternBuilder {
+    expr: PatternExpr,
+}
+
+impl From<PatternExpr> for PatternBuilder {
+    fn from(expr: PatternExpr) -> Self {
+        Self { expr }
+    }
+}
+
+impl From<ObservationBuilder> for PatternBuilder {
+    fn from(observation: ObservationBuilder) -> Self {
+        observation.build().into()
+    }
+}
+
+impl From<CompositePattern> for PatternBuilder {
+    fn from(c: CompositePattern) -> Self {
+        PatternExpr::from(c).into()
+    }
+}
+
+impl From<QualifiedPattern> for PatternBuilder {
+    fn from(q: QualifiedPattern) -> Self {
+        PatternExpr::from(q).into()
+    }
+}
+
+impl PatternBuilder {
+    #[must_use]
+    pub fn and(self, other: impl Into<PatternExpr>) -> Self {
+        self.combine(ObservationOp::And, other.into())
+    }
+
+    #[must_use]
+    pub fn or(self, other: impl Into<PatternExpr>) -> Self {
+        self.combine(ObservationOp::Or, other.into())
+    }
+
+    #[must_use]
+    pub fn followed_by(self, other: impl Into<PatternExpr>) -> Self {
+        self.combine(ObservationOp::FollowedBy, other.into())
+    }
+
+    #[must_use]
+    pub fn repeats(self, times: u32) -> Self {
+        self.qualify(Some(times), None, None)
+    }
+
+    #[must_use]
+    pub fn within(self, seconds: f64) -> Self {
+        self.qualify(None, Some(seconds), None)
+    }
+
+    #[must_use]
+    pub fn start_stop(self, start: DateTime<Utc>, stop: DateTime<Utc>) -> Self {
+        self.qualify(None, None, Some((start, stop)))
+    }
+
+    #[must_use]
+    pub fn build(self) -> PatternExpr {
+        self.expr
+    }
+
+    fn combine(self, op: ObservationOp, right: PatternExpr) -> Self {
+        CompositePattern::new(self.expr, op, right).into()
+    }
+
+    fn qualify(
+        self,
+        repeat: Option<u32>,
+        within: Option<f64>,
+        interval: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Self {
+        let (start, stop) = interval.unzip();
+        QualifiedPattern::new(self.expr, repeat, within, start, stop).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_pattern;
+
+    #[test]
+    fn test_combine() {
+        let patterns = [
+            "[a:x = 1]",
+            "[b:y = 2] AND [c:z = 3]",
+            "[d:w = 4] WITHIN 5 SECONDS",
+        ]
+        .map(|p| parse_pattern(p).unwrap());
+        let combined = combine(patterns, ObservationOp::Or).unwrap();
+        assert_eq!(
+            combined.to_string(),
+            "[a:x = 1] OR ([b:y = 2] AND [c:z = 3]) OR [d:w = 4] WITHIN 5 SECONDS"
+        );
+        let reparsed = parse_pattern(&combined.to_string()).unwrap();
+        assert_eq!(reparsed.to_string(), combined.to_string());
+        assert_eq!(combine([], ObservationOp::And), None);
+    }
+
+    #[test]
+    fn test_module_example() {
+        let malware = ObservationBuilder::new()
+            .compare(path("file", &["name"]), ComparisonOp::Eq, "evil.exe")
+            .or()
+            .compare(path("file", &["size"]), ComparisonOp::Gt, 1024_i64)
+            .build();
+        let beacon = ObservationBuilder::new()
+            .exists(path("network-traffic", &["dst_port"]))
+            .build();
+        let pattern = PatternBuilder::from(malware)
+            .followed_by(beacon)
+            .within(300.0)
+            .build();
+        assert_eq!(
+            pattern.to_string(),
+            "([file:name = 'evil.exe' OR file:size > 1024] FOLLOWEDBY \
+             [EXISTS network-traffic:dst_port]) WITHIN 300 SECONDS"
+        );
+    }
+
+    #[test]
+    fn test_builder_matches_parser() {
+        let observation = ObservationBuilder::new()
+            .compare(path("file", &["hashes", "SHA-256"]), ComparisonOp::Eq, "ab")
+            .and()
+            .group(
+                ObservationBuilder::new()
+                    .compare_list(path("file", &["name"]), ComparisonOp::In, ["a", "b"])
+                    .or()
+                    .compare_not(path("file", &["size"]), ComparisonOp::Lt, 10_i64),
+            )
+            .build();
+        let built = PatternBuilder::from(observation)
+            .or(ObservationBuilder::new().exists(path("url", &["value"])))
+            .repeats(2)
+            .build();
+
+        let parsed = parse_pattern(
+            "([file:hashes.'SHA-256' = 'ab' AND (file:name IN ('a', 'b') OR file:size NOT < 10)] \
+             OR [EXISTS url:value]) REPEATS 2 TIMES",
+        )
+        .unwrap();
+        assert_eq!(built.to_string(), parsed.to_string());
+    }
+}
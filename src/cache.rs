@@ -0,0 +1,368 @@
+//! Memoized parsing, for workloads that parse the same patterns again and
+//! again.
+
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+use crate::ast::PatternExpr;
+use crate::parser::{self, ParseOptions, Result};
+
+/// A least-recently-used cache of parsed patterns, keyed on the input.
+///
+/// A hit returns a clone of the cached AST, which shares every subtree with
+/// it, so it costs a few reference count increments rather than a parse.
+/// From Python, a hit returns the object built for the input the first
+/// time, as the AST classes are immutable. Inputs that fail to parse are
+/// not cached. The cache can be shared between threads.
+#[cfg_attr(feature = "python", pyclass(frozen, module = "stix_patterns_parser"))]
+#[derive(Debug)]
+pub struct ParserCache {
+    options: ParseOptions,
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// The slot holding each cached input.
+    index: HashMap<String, usize>,
+    /// Linked from the most recently used, `head`, to the least, `tail`.
+    slots: Vec<Slot>,
+    head: Option<usize>,
+    tail: Option<usize>,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug)]
+struct Slot {
+    input: String,
+    entry: Entry,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    expr: PatternExpr,
+    /// The Python object built from `expr`, once a Python caller needed it.
+    #[cfg(feature = "python")]
+    object: Option<Py<PyAny>>,
+}
+
+impl Entry {
+    fn new(expr: PatternExpr) -> Self {
+        Self {
+            expr,
+            #[cfg(feature = "python")]
+            object: None,
+        }
+    }
+}
+
+impl ParserCache {
+    /// A cache of up to `capacity` patterns parsed with the default options.
+    /// A capacity of 0 caches nothing.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self::with_options(capacity, ParseOptions::default())
+    }
+
+    #[must_use]
+    pub fn with_options(capacity: usize, options: ParseOptions) -> Self {
+        Self {
+            options,
+            capacity,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Parse `input` like [`parse_pattern_with_options`](parser::parse_pattern_with_options),
+    /// or return the AST cached for it.
+    pub fn parse(&self, input: &str) -> Result<PatternExpr> {
+        let cached = self.lock().lookup(input).map(|entry| entry.expr.clone());
+        if let Some(expr) = cached {
+            return Ok(expr);
+        }
+        // Parse without the lock, so other threads can still hit.
+        let expr = parser::parse_pattern_with_options(input, &self.options)?;
+        let _evicted = self.insert(input, Entry::new(expr.clone()));
+        Ok(expr)
+    }
+
+    /// Number of parses answered from the cache.
+    #[must_use]
+    pub fn hits(&self) -> u64 {
+        self.lock().hits
+    }
+
+    /// Number of parses that had to run the parser, failed ones included.
+    #[must_use]
+    pub fn misses(&self) -> u64 {
+        self.lock().misses
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of patterns currently cached.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().index.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every cached pattern and reset the hit and miss counts.
+    pub fn clear(&self) {
+        // Dropped after unlocking, as dropping Python objects may run code
+        // that uses the cache.
+        let _cleared = mem::take(&mut *self.lock());
+    }
+
+    /// Cache `entry`, returning the one it evicts or replaces, for the
+    /// caller to drop after unlocking.
+    fn insert(&self, input: &str, entry: Entry) -> Option<Entry> {
+        self.lock().insert(input, entry, self.capacity)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CacheState {
+    fn lookup(&mut self, input: &str) -> Option<&Entry> {
+        match self.index.get(input) {
+            Some(&slot) => {
+                self.hits += 1;
+                self.touch(slot);
+                Some(&self.slots[slot].entry)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, input: &str, entry: Entry, capacity: usize) -> Option<Entry> {
+        if capacity == 0 {
+            return Some(entry);
+        }
+        if let Some(&slot) = self.index.get(input) {
+            self.touch(slot);
+            return Some(mem::replace(&mut self.slots[slot].entry, entry));
+        }
+        let evicted = match self.tail {
+            // Full: the least recently used slot takes the new entry.
+            Some(slot) if self.slots.len() >= capacity => {
+                self.unlink(slot);
+                let old = &mut self.slots[slot];
+                self.index.remove(&old.input);
+                old.input = input.to_owned();
+                Some((slot, mem::replace(&mut old.entry, entry)))
+            }
+            _ => {
+                self.slots.push(Slot {
+                    input: input.to_owned(),
+                    entry,
+                    prev: None,
+                    next: None,
+                });
+                None
+            }
+        };
+        let (slot, evicted) = match evicted {
+            Some((slot, old)) => (slot, Some(old)),
+            None => (self.slots.len() - 1, None),
+        };
+        self.index.insert(input.to_owned(), slot);
+        self.push_front(slot);
+        evicted
+    }
+
+    /// Mark `slot` as the most recently used.
+    fn touch(&mut self, slot: usize) {
+        if self.head != Some(slot) {
+            self.unlink(slot);
+            self.push_front(slot);
+        }
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = (self.slots[slot].prev, self.slots[slot].next);
+        match prev {
+            Some(prev) => self.slots[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.slots[next].prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn push_front(&mut self, slot: usize) {
+        self.slots[slot].prev = None;
+        self.slots[slot].next = self.head;
+        match self.head {
+            Some(head) => self.slots[head].prev = Some(slot),
+            None => self.tail = Some(slot),
+        }
+        self.head = Some(slot);
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ParserCache {
+    #[new]
+    #[pyo3(signature = (maxsize = 128, options = None))]
+    fn py_new(maxsize: usize, options: Option<ParseOptions>) -> Self {
+        Self::with_options(maxsize, options.unwrap_or_default())
+    }
+
+    #[pyo3(name = "parse")]
+    fn py_parse(&self, py: Python<'_>, pattern: &str) -> PyResult<Py<PyAny>> {
+        let cached = self.lock().lookup(pattern).map(|entry| {
+            let object = entry.object.as_ref().map(|object| object.clone_ref(py));
+            (entry.expr.clone(), object)
+        });
+        let expr = match cached {
+            Some((_, Some(object))) => return Ok(object),
+            Some((expr, None)) => expr,
+            None => py.detach(|| parser::parse_pattern_with_options(pattern, &self.options))?,
+        };
+        // Converted without the lock, as building objects may run Python
+        // code that uses the cache.
+        let object = expr.to_pyobject(py)?;
+        let entry = Entry {
+            expr,
+            object: Some(object.clone_ref(py)),
+        };
+        let _evicted = self.insert(pattern, entry);
+        Ok(object)
+    }
+
+    #[getter(hits)]
+    fn py_hits(&self) -> u64 {
+        self.hits()
+    }
+
+    #[getter(misses)]
+    fn py_misses(&self) -> u64 {
+        self.misses()
+    }
+
+    #[getter]
+    fn maxsize(&self) -> usize {
+        self.capacity
+    }
+
+    #[pyo3(name = "clear")]
+    fn py_clear(&self) {
+        self.clear();
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+
+    fn __repr__(&self) -> String {
+        let state = self.lock();
+        format!(
+            "ParserCache(maxsize={}, currsize={}, hits={}, misses={})",
+            self.capacity,
+            state.index.len(),
+            state.hits,
+            state.misses
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ParseError;
+
+    #[test]
+    fn test_parser_cache() {
+        let cache = ParserCache::new(2);
+        let first = cache.parse("[a:x = 1]").unwrap();
+        assert_eq!(cache.parse("[a:x = 1]").unwrap(), first);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // `[a:x = 1]` was used last, so `[a:y = 2]` is evicted.
+        cache.parse("[a:y = 2]").unwrap();
+        cache.parse("[a:x = 1]").unwrap();
+        cache.parse("[a:z = 3]").unwrap();
+        assert_eq!(cache.len(), 2);
+        cache.parse("[a:x = 1]").unwrap();
+        cache.parse("[a:y = 2]").unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (3, 4));
+
+        assert!(matches!(cache.parse("[a:x ="), Err(ParseError::Grammar(_))));
+        assert_eq!((cache.len(), cache.misses()), (2, 5));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!((cache.hits(), cache.misses()), (0, 0));
+
+        let disabled = ParserCache::new(0);
+        disabled.parse("[a:x = 1]").unwrap();
+        disabled.parse("[a:x = 1]").unwrap();
+        assert_eq!(
+            (disabled.hits(), disabled.misses(), disabled.len()),
+            (0, 2, 0)
+        );
+    }
+
+    #[test]
+    fn test_parser_cache_order() {
+        let cache = ParserCache::new(3);
+        let inputs = ["[a:v = 0]", "[a:w = 1]", "[a:x = 2]", "[a:y = 3]"];
+        for input in &inputs[..3] {
+            cache.parse(input).unwrap();
+        }
+        // Using the oldest entries moves `[a:x = 2]` to the back.
+        cache.parse(inputs[0]).unwrap();
+        cache.parse(inputs[1]).unwrap();
+        cache.parse(inputs[3]).unwrap();
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (2, 4, 3));
+        for input in [inputs[0], inputs[1], inputs[3]] {
+            cache.parse(input).unwrap();
+        }
+        assert_eq!(cache.hits(), 5);
+        cache.parse(inputs[2]).unwrap();
+        assert_eq!((cache.misses(), cache.len()), (5, 3));
+
+        // `[a:v = 0]` evicts `[a:w = 1]`, which then evicts `[a:y = 3]`,
+        // leaving `[a:x = 2]` cached.
+        cache.parse(inputs[0]).unwrap();
+        cache.parse(inputs[1]).unwrap();
+        cache.parse(inputs[2]).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (6, 7));
+        cache.parse(inputs[3]).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (6, 8));
+    }
+
+    #[test]
+    fn test_parser_cache_options() {
+        let options = ParseOptions {
+            allow_null: true,
+            ..Default::default()
+        };
+        let cache = ParserCache::with_options(4, options);
+        assert!(cache.parse("[a:x = null]").is_ok());
+        assert!(ParserCache::new(4).parse("[a:x = null]").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Non-fatal diagnostics reported while parsing STIX patterns.
+
+use pest::Span;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// STIX 2.1 Cyber-observable Object types (STIX 2.1 Specification Section 6).
+pub const KNOWN_OBJECT_TYPES: &[&str] = &[
+    "artifact",
+    "autonomous-system",
+    "directory",
+    "domain-name",
+    "email-addr",
+    "email-message",
+    "file",
+    "ipv4-addr",
+    "ipv6-addr",
+    "mac-addr",
+    "mutex",
+    "network-traffic",
+    "process",
+    "software",
+    "url",
+    "user-account",
+    "windows-registry-key",
+    "x509-certificate",
+];
+
+/// Hash algorithm names of the STIX 2.1 `hash-algorithm-ov` vocabulary
+/// (STIX 2.1 Specification Section 10.11).
+pub const HASH_ALGORITHMS: &[&str] = &[
+    "MD5", "SHA-1", "SHA-256", "SHA-512", "SHA3-256", "SHA3-512", "SSDEEP", "TLSH",
+];
+
+/// A suspicious construct that does not prevent the pattern from parsing.
+///
+/// `start` and `end` are byte offsets into the parsed input.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, get_all, module = "stix_patterns_parser")
+)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParseWarning {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ParseWarning {
+    fn __repr__(&self) -> String {
+        format!(
+            "ParseWarning(message={:?}, start={}, end={})",
+            self.message, self.start, self.end
+        )
+    }
+}
+
+impl ParseWarning {
+    #[must_use]
+    pub fn new(message: impl Into<String>, span: Span<'_>) -> Self {
+        Self {
+            message: message.into(),
+            start: span.start(),
+            end: span.end(),
+        }
+    }
+}
+
+/// Returns true for the STIX 2.1 object types and for custom (`x-`) types.
+#[must_use]
+pub fn is_known_object_type(object_type: &str) -> bool {
+    object_type.starts_with("x-") || KNOWN_OBJECT_TYPES.contains(&object_type)
+}
+
+/// The vocabulary spelling of the hash algorithm `name`, ignoring case,
+/// hyphens and underscores, so `sha256`, `sha_256` and `Sha-256` all give
+/// `SHA-256`. `None` for algorithms outside the vocabulary.
+#[must_use]
+pub fn canonical_hash_name(name: &str) -> Option<&'static str> {
+    let key = |s: &str| {
+        s.chars()
+            .filter(|c| !matches!(c, '-' | '_'))
+            .map(|c| c.to_ascii_uppercase())
+            .collect::<String>()
+    };
+    let name = key(name);
+    HASH_ALGORITHMS.iter().copied().find(|a| key(a) == name)
+}
+
+/// Whether a custom object type follows the STIX naming rules: lowercase
+/// ASCII letters, digits and single hyphens, ending in a letter or digit.
+#[must_use]
+pub fn is_valid_custom_object_type(object_type: &str) -> bool {
+    object_type.split('-').all(|segment| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    })
+}
@@ -0,0 +1,347 @@
+//! Conversion of patterns to and from plain Python dicts, e.g. for storage
+//! as JSON.
+//!
+//! Every node is a dict whose `"type"` key names its class, with one key per
+//! constructor argument:
+//!
+//! ```text
+//! {"type": "Comparison",
+//!  "object_path": {"type": "ObjectPath", "object_type": "file",
+//!                  "property_path": [{"type": "PathComponent", "property": "name",
+//!                                     "index": None, "kind": "PLAIN"}]},
+//!  "op": "=", "constant": "x", "constant_type": "string", "negated": False}
+//! ```
+//!
+//! Operators are written as their `value`, timestamps as RFC 3339 strings
+//! and an object path on the right-hand side as an `ObjectPath` dict.
+//! Source spans and source text are not kept. [`crate::json`] writes the
+//! same nodes as a JSON document.
+
+use std::fmt::Display;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde_json::{Map, Value};
+
+use crate::ast::{
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, CompositeComparison,
+    CompositePattern, ObjectPath, ObservationOp, PathComponent, PatternExpr, PropertyKind,
+    QualifiedPattern, UnaryOp,
+};
+use crate::json;
+
+/// Convert `expr` to nested dicts, built from its [`json`] node.
+pub fn to_dict<'py>(py: Python<'py>, expr: &PatternExpr) -> PyResult<Bound<'py, PyDict>> {
+    let value = json::to_json_value(expr);
+    let dict = json_to_py(py, &value);
+    json::drop_value(value);
+    Ok(dict?.cast_into::<PyDict>()?)
+}
+
+/// Built bottom-up with a stack, as the nodes of a chain nest too deeply to
+/// recurse down.
+fn json_to_py<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    enum Step<'a> {
+        Value(&'a Value),
+        List(usize),
+        Dict(&'a Map<String, Value>),
+    }
+
+    let mut pending = vec![Step::Value(value)];
+    let mut built = Vec::new();
+    while let Some(step) = pending.pop() {
+        match step {
+            Step::Value(Value::Array(items)) => {
+                pending.push(Step::List(items.len()));
+                pending.extend(items.iter().rev().map(Step::Value));
+            }
+            Step::Value(Value::Object(map)) => {
+                pending.push(Step::Dict(map));
+                pending.extend(map.values().rev().map(Step::Value));
+            }
+            Step::Value(Value::Null) => built.push(py.None().into_bound(py)),
+            Step::Value(Value::Bool(b)) => built.push(b.into_pyobject(py)?.to_owned().into_any()),
+            Step::Value(Value::Number(n)) => built.push(match n.as_i64() {
+                Some(i) => i.into_pyobject(py)?.into_any(),
+                None => n.as_f64().into_pyobject(py)?.into_any(),
+            }),
+            Step::Value(Value::String(s)) => built.push(s.into_pyobject(py)?.into_any()),
+            Step::List(len) => {
+                let items = built.split_off(built.len() - len);
+                built.push(PyList::new(py, items)?.into_any());
+            }
+            Step::Dict(map) => {
+                let values = built.split_off(built.len() - map.len());
+                let dict = PyDict::new(py);
+                for (key, value) in map.keys().zip(values) {
+                    dict.set_item(key, value)?;
+                }
+                built.push(dict.into_any());
+            }
+        }
+    }
+    Ok(built.pop().expect("the root is built last"))
+}
+
+/// Rebuild a pattern from the output of [`to_dict`], raising `ValueError`
+/// with the location of the first malformed node.
+pub fn from_dict(data: &Bound<'_, PyAny>) -> PyResult<PatternExpr> {
+    pattern_from_dict(data, "pattern")
+}
+
+fn pattern_from_dict(data: &Bound<'_, PyAny>, at: &str) -> PyResult<PatternExpr> {
+    let (dict, type_name) = node(
+        data,
+        at,
+        &[
+            "Comparison",
+            "CompositeComparison",
+            "CompositePattern",
+            "QualifiedPattern",
+        ],
+    )?;
+    match type_name.as_str() {
+        "CompositePattern" => {
+            let left = pattern_from_dict(&required(&dict, "left", at)?, &format!("{at}.left"))?;
+            let op: String = extract(&dict, "op", at)?;
+            let op = ObservationOp::from_value(&op)
+                .ok_or_else(|| invalid(&format!("{at}.op"), format!("unknown operator {op:?}")))?;
+            let right = pattern_from_dict(&required(&dict, "right", at)?, &format!("{at}.right"))?;
+            let mut pattern = CompositePattern::new(left, op, right);
+            pattern.parenthesized =
+                extract::<Option<bool>>(&dict, "parenthesized", at)?.unwrap_or_default();
+            Ok(pattern.into())
+        }
+        "QualifiedPattern" => {
+            let pattern =
+                pattern_from_dict(&required(&dict, "pattern", at)?, &format!("{at}.pattern"))?;
+            let (node, _) = QualifiedPattern::py_new(
+                pattern,
+                extract(&dict, "repeat", at)?,
+                extract(&dict, "within", at)?,
+                dict.get_item("start")?.as_ref(),
+                dict.get_item("stop")?.as_ref(),
+            )
+            .map_err(|err| invalid(at, err_message(&err, data.py())))?;
+            Ok(node.into())
+        }
+        _ => comparison_expr_from_dict(data, at).map(Into::into),
+    }
+}
+
+fn comparison_expr_from_dict(data: &Bound<'_, PyAny>, at: &str) -> PyResult<ComparisonExpr> {
+    let (dict, type_name) = node(data, at, &["Comparison", "CompositeComparison"])?;
+    if type_name == "Comparison" {
+        return comparison_from_dict(&dict, at).map(Into::into);
+    }
+
+    let left = comparison_expr_from_dict(&required(&dict, "left", at)?, &format!("{at}.left"))?;
+    let op: String = extract(&dict, "op", at)?;
+    let op = BooleanOp::from_value(&op)
+        .ok_or_else(|| invalid(&format!("{at}.op"), format!("unknown operator {op:?}")))?;
+    let right = comparison_expr_from_dict(&required(&dict, "right", at)?, &format!("{at}.right"))?;
+    let mut expr = CompositeComparison::new(left, op, right);
+    expr.parenthesized = extract::<Option<bool>>(&dict, "parenthesized", at)?.unwrap_or_default();
+    expr.negated = extract::<Option<bool>>(&dict, "negated", at)?.unwrap_or_default();
+    Ok(expr.into())
+}
+
+fn comparison_from_dict(dict: &Bound<'_, PyDict>, at: &str) -> PyResult<Comparison> {
+    let py = dict.py();
+    let object_path = path_from_dict(
+        &required(dict, "object_path", at)?,
+        &format!("{at}.object_path"),
+    )?;
+    let op: String = extract(dict, "op", at)?;
+    let op = match op.as_str() {
+        "EXISTS" => ComparisonOperator::Unary(UnaryOp::Exists),
+        _ => ComparisonOp::from_value(&op)
+            .ok_or_else(|| invalid(&format!("{at}.op"), format!("unknown operator {op:?}")))?
+            .into(),
+    };
+    let constant = match dict.get_item("constant")? {
+        Some(c) if c.is_instance_of::<PyDict>() => {
+            let path = path_from_dict(&c, &format!("{at}.constant"))?;
+            Some(Bound::new(py, path)?.into_any())
+        }
+        Some(c) if c.is_none() => None,
+        c => c,
+    };
+    let (node, _) = Comparison::py_new(
+        object_path,
+        op,
+        constant.as_ref(),
+        extract::<Option<bool>>(dict, "negated", at)?.unwrap_or_default(),
+        dict.get_item("constant_type")?
+            .filter(|t| !t.is_none())
+            .as_ref(),
+    )
+    .map_err(|err| invalid(at, err_message(&err, py)))?;
+    Ok(node)
+}
+
+fn path_from_dict(data: &Bound<'_, PyAny>, at: &str) -> PyResult<ObjectPath> {
+    let (dict, _) = node(data, at, &["ObjectPath"])?;
+    let object_type: String = extract(&dict, "object_type", at)?;
+    let components: Vec<Bound<'_, PyAny>> = extract(&dict, "property_path", at)?;
+    let property_path = components
+        .iter()
+        .enumerate()
+        .map(|(i, c)| component_from_dict(c, &format!("{at}.property_path[{i}]")))
+        .collect::<PyResult<_>>()?;
+    Ok(ObjectPath::new(object_type, property_path))
+}
+
+fn component_from_dict(data: &Bound<'_, PyAny>, at: &str) -> PyResult<PathComponent> {
+    let (dict, _) = node(data, at, &["PathComponent"])?;
+    let kind = match extract::<Option<String>>(&dict, "kind", at)?.as_deref() {
+        None | Some("PLAIN") => PropertyKind::Plain,
+        Some("QUOTED") => PropertyKind::Quoted,
+        Some(other) => {
+            return Err(invalid(
+                &format!("{at}.kind"),
+                format!("unknown kind {other:?}"),
+            ));
+        }
+    };
+    PathComponent::py_new(
+        extract(&dict, "property", at)?,
+        dict.get_item("index")?.as_ref(),
+        kind,
+    )
+    .map_err(|err| invalid(at, err_message(&err, data.py())))
+}
+
+/// `data` as a dict whose `"type"` is one of `expected`, with that type.
+fn node<'py>(
+    data: &Bound<'py, PyAny>,
+    at: &str,
+    expected: &[&str],
+) -> PyResult<(Bound<'py, PyDict>, String)> {
+    let dict = data
+        .cast::<PyDict>()
+        .map_err(|_| invalid(at, "expected a dict"))?;
+    let type_name: String = extract(dict, "type", at)?;
+    if !expected.contains(&type_name.as_str()) {
+        return Err(invalid(
+            at,
+            format!(
+                "expected type {}, found {type_name:?}",
+                expected.join(" or ")
+            ),
+        ));
+    }
+    Ok((dict.clone(), type_name))
+}
+
+fn required<'py>(dict: &Bound<'py, PyDict>, key: &str, at: &str) -> PyResult<Bound<'py, PyAny>> {
+    dict.get_item(key)?
+        .ok_or_else(|| invalid(at, format!("missing key {key:?}")))
+}
+
+/// The value under `key`, a missing key reading as `None` for optional
+/// values.
+fn extract<'py, T>(dict: &Bound<'py, PyDict>, key: &str, at: &str) -> PyResult<T>
+where
+    T: for<'a> FromPyObject<'a, 'py>,
+{
+    let Some(value) = dict.get_item(key)? else {
+        return dict
+            .py()
+            .None()
+            .into_bound(dict.py())
+            .extract()
+            .map_err(|_| invalid(at, format!("missing key {key:?}")));
+    };
+    value
+        .extract()
+        .map_err(Into::<PyErr>::into)
+        .map_err(|err| invalid(&format!("{at}.{key}"), err_message(&err, dict.py())))
+}
+
+fn err_message(err: &PyErr, py: Python<'_>) -> String {
+    err.value(py).to_string()
+}
+
+fn invalid(at: &str, message: impl Display) -> PyErr {
+    PyValueError::new_err(format!("invalid pattern dict at {at}: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParseOptions, parse_pattern, parse_pattern_with_options};
+
+    /// One pattern per kind of node and constant.
+    const PATTERNS: &[&str] = &[
+        "[file:name = 'a']",
+        "[file:hashes.'SHA-256' = 'ab' AND file:x[0][*] IN (1, 2.5)]",
+        "[file:size NOT > 1 OR EXISTS file:name]",
+        "[a:t = t'2023-01-01T00:00:00.5Z' AND a:h = h'ff' AND a:b = b'YWI=' AND a:x = true]",
+        "[file:parent_directory_ref.path = file:name]",
+        "[a:x = null]",
+        "[NOT (a:x = 1 AND a:y = 2)]",
+        "[a:x = 1] AND ([a:y = 2] OR [a:z = 3]) FOLLOWEDBY [a:w = 4]",
+        "[a:x = 1] REPEATS 2 TIMES WITHIN 5.5 SECONDS \
+         START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'",
+    ];
+
+    fn options() -> ParseOptions {
+        ParseOptions {
+            allow_path_rhs: true,
+            allow_null: true,
+            allow_grouped_negation: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_from_dict_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            for input in PATTERNS {
+                let expr = parse_pattern_with_options(input, &options()).unwrap();
+                let dict = to_dict(py, &expr).unwrap();
+                assert_eq!(from_dict(dict.as_any()).unwrap(), expr, "{input}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_from_dict_errors() {
+        Python::initialize();
+        Python::attach(|py| {
+            let error =
+                |dict: &Bound<'_, PyDict>| err_message(&from_dict(dict.as_any()).unwrap_err(), py);
+            let expr = parse_pattern("[a:x = 1] AND [a:y = 2]").unwrap();
+            let dict = || to_dict(py, &expr).unwrap();
+
+            let bad_type = dict();
+            bad_type.set_item("type", "Pattern").unwrap();
+            assert_eq!(
+                error(&bad_type),
+                "invalid pattern dict at pattern: expected type Comparison or \
+                 CompositeComparison or CompositePattern or QualifiedPattern, found \"Pattern\""
+            );
+
+            let missing = dict();
+            missing.del_item("right").unwrap();
+            assert_eq!(
+                error(&missing),
+                "invalid pattern dict at pattern: missing key \"right\""
+            );
+
+            let missing_path = dict();
+            let left = missing_path.get_item("left").unwrap().unwrap();
+            left.cast::<PyDict>()
+                .unwrap()
+                .del_item("object_path")
+                .unwrap();
+            assert_eq!(
+                error(&missing_path),
+                "invalid pattern dict at pattern.left: missing key \"object_path\""
+            );
+        });
+    }
+}
@@ -0,0 +1,136 @@
+//! Graphviz DOT rendering of the AST.
+
+use std::fmt::Write;
+
+use crate::ast::{ComparisonExpr, PatternExpr};
+use crate::serialize::qualifier_suffix;
+
+/// Render a pattern as a DOT digraph with one vertex per AST node.
+///
+/// Composite nodes are labeled with their operator, qualified patterns
+/// with their qualifiers, observations with `[ ]` and leaf comparisons with
+/// their source form. Edges are labeled `left`, `right` or `inner`.
+#[must_use]
+pub fn to_dot(expr: &PatternExpr) -> String {
+    let mut graph = Graph::default();
+    graph.pattern(expr);
+    format!("digraph pattern {{\n{}}}\n", graph.body)
+}
+
+#[derive(Default)]
+struct Graph {
+    body: String,
+    next_id: usize,
+}
+
+impl Graph {
+    fn node(&mut self, label: &str, shape: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let _ = writeln!(
+            self.body,
+            "  n{id} [label=\"{}\", shape={shape}];",
+            escape_label(label)
+        );
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: &str) {
+        let _ = writeln!(self.body, "  n{from} -> n{to} [label=\"{label}\"];");
+    }
+
+    /// Writes each vertex, then its subtrees, then the edges to them, with
+    /// a stack of what is left rather than recursion, as chains nest too
+    /// deeply.
+    fn pattern(&mut self, expr: &PatternExpr) {
+        let mut pending = vec![Step::Pattern(expr, None)];
+        while let Some(step) = pending.pop() {
+            match step {
+                Step::Edge(from, to, label) => self.edge(from, to, label),
+                Step::Pattern(PatternExpr::Comparison(c), parent) => {
+                    let id = self.vertex("[ ]", "box", parent, &mut pending);
+                    pending.push(Step::Comparison(c, Some((id, "inner"))));
+                }
+                Step::Pattern(PatternExpr::Composite(c), parent) => {
+                    let id = self.vertex(c.op.value(), "ellipse", parent, &mut pending);
+                    pending.extend([
+                        Step::Pattern(&c.right, Some((id, "right"))),
+                        Step::Pattern(&c.left, Some((id, "left"))),
+                    ]);
+                }
+                Step::Pattern(PatternExpr::Qualified(q), parent) => {
+                    let label = qualifier_suffix(q);
+                    let id = self.vertex(label.trim_start(), "hexagon", parent, &mut pending);
+                    pending.push(Step::Pattern(&q.pattern, Some((id, "inner"))));
+                }
+                Step::Comparison(ComparisonExpr::Single(c), parent) => {
+                    self.vertex(&c.to_string(), "note", parent, &mut pending);
+                }
+                Step::Comparison(ComparisonExpr::Composite(c), parent) => {
+                    let label = if c.negated {
+                        format!("NOT {}", c.op.value())
+                    } else {
+                        c.op.value().to_owned()
+                    };
+                    let id = self.vertex(&label, "ellipse", parent, &mut pending);
+                    pending.extend([
+                        Step::Comparison(&c.right, Some((id, "right"))),
+                        Step::Comparison(&c.left, Some((id, "left"))),
+                    ]);
+                }
+            }
+        }
+    }
+
+    /// Write a vertex, leaving the edge from its parent to be written once
+    /// the steps pushed after it, for its subtrees, are done.
+    fn vertex<'a>(
+        &mut self,
+        label: &str,
+        shape: &str,
+        parent: Option<(usize, &'static str)>,
+        pending: &mut Vec<Step<'a>>,
+    ) -> usize {
+        let id = self.node(label, shape);
+        if let Some((from, label)) = parent {
+            pending.push(Step::Edge(from, id, label));
+        }
+        id
+    }
+}
+
+/// What is left to write, with the parent vertex and edge label of nodes.
+enum Step<'a> {
+    Pattern(&'a PatternExpr, Option<(usize, &'static str)>),
+    Comparison(&'a ComparisonExpr, Option<(usize, &'static str)>),
+    Edge(usize, usize, &'static str),
+}
+
+fn escape_label(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_pattern;
+
+    #[test]
+    fn test_to_dot() {
+        let expr = parse_pattern("[file:name = 'a\"b' AND file:size > 1] REPEATS 2 TIMES").unwrap();
+        assert_eq!(
+            to_dot(&expr),
+            "digraph pattern {\n\
+             \x20 n0 [label=\"REPEATS 2 TIMES\", shape=hexagon];\n\
+             \x20 n1 [label=\"[ ]\", shape=box];\n\
+             \x20 n2 [label=\"AND\", shape=ellipse];\n\
+             \x20 n3 [label=\"file:name = 'a\\\"b'\", shape=note];\n\
+             \x20 n2 -> n3 [label=\"left\"];\n\
+             \x20 n4 [label=\"file:size > 1\", shape=note];\n\
+             \x20 n2 -> n4 [label=\"right\"];\n\
+             \x20 n1 -> n2 [label=\"inner\"];\n\
+             \x20 n0 -> n1 [label=\"inner\"];\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Translation of an observation into an Elasticsearch or OpenSearch query.
+//!
+//! Object paths become fields through a map keyed by the path as written
+//! in a pattern, such as `file:name`. `AND` and `OR` become `bool` queries
+//! with `must` and `should` clauses, and `NOT` and `!=` a `must_not`:
+//!
+//! ```text
+//! [file:name LIKE '%.exe' AND file:size NOT > 10]
+//! {"bool": {"must": [{"wildcard": {"name": {"value": "*.exe"}}},
+//!                    {"bool": {"filter": [{"exists": {"field": "size"}}],
+//!                              "must_not": [{"range": {"size": {"gt": 10}}}]}}]}}
+//! ```
+//!
+//! A comparison on a missing property never matches in STIX, negated or
+//! not, so negated comparisons also require the field to exist, as the SQL
+//! translation does with `NULL`. A negated group `NOT (...)` matches
+//! whenever the group does not, documents missing its fields included.
+//!
+//! `LIKE` becomes a `wildcard` query, `MATCHES` a `regexp` query whose
+//! operand is passed on as is, although Lucene regular expressions are
+//! always anchored, and `ISSUBSET` a `term` query, which matches the CIDR
+//! range on `ip` fields. `ISSUPERSET`, object paths on the right-hand side,
+//! observation operators and qualifiers have no query form and are
+//! reported as `EsError::Unsupported`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::SecondsFormat;
+use thiserror::Error;
+
+use crate::ast::{
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, ComparisonRhs,
+    PatternExpr, StixValue,
+};
+use crate::eval::Data;
+use crate::like::{LikeToken, tokenize_like};
+
+/// Errors that can occur while translating a pattern to a query.
+#[derive(Error, Debug)]
+pub enum EsError {
+    #[error("Cannot translate {0} to a query")]
+    Unsupported(&'static str),
+
+    #[error("No field for object path {0}")]
+    UnmappedPath(String),
+}
+
+pub type Result<T> = std::result::Result<T, EsError>;
+
+/// Translate the observation `expr` into a query, looking fields up in
+/// `fields`.
+pub fn to_es_query(expr: &PatternExpr, fields: &HashMap<String, String>) -> Result<Data> {
+    match expr {
+        PatternExpr::Comparison(c) => comparison_expr(c, fields),
+        PatternExpr::Composite(_) => Err(EsError::Unsupported("observation operators")),
+        PatternExpr::Qualified(_) => Err(EsError::Unsupported("qualifiers")),
+    }
+}
+
+fn comparison_expr(expr: &ComparisonExpr, fields: &HashMap<String, String>) -> Result<Data> {
+    let c = match expr {
+        ComparisonExpr::Single(c) => return comparison(c, fields),
+        ComparisonExpr::Composite(c) => c,
+    };
+
+    // A chain of the same operator becomes a single `bool` query.
+    let mut clauses = Vec::new();
+    let mut pending = vec![&*c.right, &*c.left];
+    while let Some(next) = pending.pop() {
+        match next {
+            ComparisonExpr::Composite(inner) if inner.op == c.op && !inner.negated => {
+                pending.push(&inner.right);
+                pending.push(&inner.left);
+            }
+            other => clauses.push(comparison_expr(other, fields)?),
+        }
+    }
+    let query = match c.op {
+        BooleanOp::And => bool_query([("must", Data::List(clauses))]),
+        BooleanOp::Or => bool_query([
+            ("should", Data::List(clauses)),
+            ("minimum_should_match", Data::Int(1)),
+        ]),
+    };
+    Ok(if c.negated { not(query) } else { query })
+}
+
+fn comparison(c: &Comparison, fields: &HashMap<String, String>) -> Result<Data> {
+    let key = c.object_path.to_string();
+    let field = fields.get(&key).ok_or(EsError::UnmappedPath(key))?.as_str();
+    let op = match c.op {
+        ComparisonOperator::Unary(_) => return Ok(exists(field)),
+        ComparisonOperator::Comparison(op) => op,
+    };
+    let value = match &c.constant {
+        Some(ComparisonRhs::Value(value)) => value,
+        Some(ComparisonRhs::List(values)) if op == ComparisonOp::In => {
+            let values = values.iter().map(constant).collect::<Result<_>>()?;
+            let query = leaf("terms", field, Data::List(values));
+            return Ok(if c.negated {
+                not_present(field, query)
+            } else {
+                query
+            });
+        }
+        Some(ComparisonRhs::List(_)) => return Err(EsError::Unsupported("a list outside IN")),
+        Some(ComparisonRhs::Path(_)) => {
+            return Err(EsError::Unsupported(
+                "an object path on the right-hand side",
+            ));
+        }
+        None => return Err(EsError::Unsupported("a comparison without a constant")),
+    };
+
+    let query = match op {
+        // A field compared with `null` is one that is missing.
+        ComparisonOp::Eq | ComparisonOp::Neq if *value == StixValue::Null => {
+            let missing = (op == ComparisonOp::Eq) != c.negated;
+            return Ok(if missing {
+                not(exists(field))
+            } else {
+                exists(field)
+            });
+        }
+        ComparisonOp::Eq | ComparisonOp::IsSubset => leaf("term", field, constant(value)?),
+        ComparisonOp::Neq => {
+            let query = leaf("term", field, constant(value)?);
+            return Ok(if c.negated {
+                query
+            } else {
+                not_present(field, query)
+            });
+        }
+        ComparisonOp::Gt | ComparisonOp::Lt | ComparisonOp::Ge | ComparisonOp::Le => {
+            let bound = match op {
+                ComparisonOp::Gt => "gt",
+                ComparisonOp::Lt => "lt",
+                ComparisonOp::Ge => "gte",
+                _ => "lte",
+            };
+            leaf("range", field, object([(bound, constant(value)?)]))
+        }
+        ComparisonOp::Like => {
+            let StixValue::String(pattern) = value else {
+                return Err(EsError::Unsupported("LIKE with a non-string constant"));
+            };
+            let wildcard = Data::String(like_to_wildcard(pattern));
+            leaf("wildcard", field, object([("value", wildcard)]))
+        }
+        ComparisonOp::Matches => leaf("regexp", field, object([("value", constant(value)?)])),
+        ComparisonOp::IsSuperset => return Err(EsError::Unsupported("ISSUPERSET")),
+        ComparisonOp::In => return Err(EsError::Unsupported("IN without a list")),
+    };
+    Ok(if c.negated {
+        not_present(field, query)
+    } else {
+        query
+    })
+}
+
+/// Convert a `LIKE` operand into a wildcard pattern, where `*` matches any
+/// run of characters, `?` exactly one and a backslash escapes.
+fn like_to_wildcard(pattern: &str) -> String {
+    let mut wildcard = String::with_capacity(pattern.len());
+    for token in tokenize_like(pattern) {
+        match token {
+            LikeToken::AnyRun => wildcard.push('*'),
+            LikeToken::AnyChar => wildcard.push('?'),
+            LikeToken::Literal(c) => {
+                if matches!(c, '*' | '?' | '\\') {
+                    wildcard.push('\\');
+                }
+                wildcard.push(c);
+            }
+        }
+    }
+    wildcard
+}
+
+fn constant(value: &StixValue) -> Result<Data> {
+    Ok(match value {
+        StixValue::String(s) | StixValue::Hex(s) | StixValue::Binary(s) => text(s),
+        StixValue::Int(i) => Data::Int(*i),
+        StixValue::Float(f) => Data::Float(*f),
+        StixValue::Bool(b) => Data::Bool(*b),
+        StixValue::Timestamp(t) => Data::String(t.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        StixValue::Null => return Err(EsError::Unsupported("null outside = and !=")),
+    })
+}
+
+fn exists(field: &str) -> Data {
+    object([("exists", object([("field", text(field))]))])
+}
+
+fn leaf(kind: &str, field: &str, value: Data) -> Data {
+    object([(kind, object([(field, value)]))])
+}
+
+fn not(query: Data) -> Data {
+    bool_query([("must_not", Data::List(vec![query]))])
+}
+
+/// A negated comparison: `field` exists and `query` does not match.
+fn not_present(field: &str, query: Data) -> Data {
+    bool_query([
+        ("filter", Data::List(vec![exists(field)])),
+        ("must_not", Data::List(vec![query])),
+    ])
+}
+
+fn bool_query<const N: usize>(clauses: [(&str, Data); N]) -> Data {
+    object([("bool", object(clauses))])
+}
+
+fn object<const N: usize>(entries: [(&str, Data); N]) -> Data {
+    Data::Object(
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v))
+            .collect::<BTreeMap<_, _>>(),
+    )
+}
+
+fn text(s: &str) -> Data {
+    Data::String(s.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParseOptions, parse_pattern, parse_pattern_with_options};
+
+    fn fields() -> HashMap<String, String> {
+        [
+            ("file:name", "file.name"),
+            ("file:size", "file.size"),
+            ("ipv4-addr:value", "source.ip"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect()
+    }
+
+    fn query(pattern: &str) -> Data {
+        to_es_query(&parse_pattern(pattern).unwrap(), &fields()).unwrap()
+    }
+
+    #[test]
+    fn test_to_es_query() {
+        assert_eq!(
+            query("[file:name LIKE '%.exe' AND file:size NOT > 10]"),
+            bool_query([(
+                "must",
+                Data::List(vec![
+                    leaf("wildcard", "file.name", object([("value", text("*.exe"))])),
+                    not_present(
+                        "file.size",
+                        leaf("range", "file.size", object([("gt", Data::Int(10))]))
+                    ),
+                ])
+            )])
+        );
+        assert_eq!(
+            query("[file:name = 'a' OR file:name = 'b' OR EXISTS file:size]"),
+            bool_query([
+                (
+                    "should",
+                    Data::List(vec![
+                        leaf("term", "file.name", text("a")),
+                        leaf("term", "file.name", text("b")),
+                        exists("file.size"),
+                    ])
+                ),
+                ("minimum_should_match", Data::Int(1)),
+            ])
+        );
+        assert_eq!(
+            query("[file:size IN (1, 2) AND (file:name != 'a' OR file:name MATCHES '.*x')]"),
+            bool_query([(
+                "must",
+                Data::List(vec![
+                    leaf(
+                        "terms",
+                        "file.size",
+                        Data::List(vec![Data::Int(1), Data::Int(2)])
+                    ),
+                    bool_query([
+                        (
+                            "should",
+                            Data::List(vec![
+                                not_present("file.name", leaf("term", "file.name", text("a"))),
+                                leaf("regexp", "file.name", object([("value", text(".*x"))])),
+                            ])
+                        ),
+                        ("minimum_should_match", Data::Int(1)),
+                    ]),
+                ])
+            )])
+        );
+        assert_eq!(
+            query("[ipv4-addr:value ISSUBSET '10.0.0.0/8']"),
+            leaf("term", "source.ip", text("10.0.0.0/8"))
+        );
+    }
+
+    #[test]
+    fn test_to_es_query_negation() {
+        // Negated comparisons need the field, negated groups do not.
+        let name_a = leaf("term", "file.name", text("a"));
+        assert_eq!(query("[file:name NOT != 'a']"), name_a);
+        assert_eq!(
+            query("[file:name NOT IN ('a')]"),
+            not_present(
+                "file.name",
+                leaf("terms", "file.name", Data::List(vec![text("a")]))
+            )
+        );
+        let options = ParseOptions {
+            allow_null: true,
+            allow_grouped_negation: true,
+            ..Default::default()
+        };
+        let query = |pattern: &str| {
+            let expr = parse_pattern_with_options(pattern, &options).unwrap();
+            to_es_query(&expr, &fields()).unwrap()
+        };
+        assert_eq!(query("[file:name NOT = null]"), exists("file.name"));
+        assert_eq!(query("[file:name NOT != null]"), not(exists("file.name")));
+        assert_eq!(
+            query("[NOT (file:name = 'a' OR file:name = 'b')]"),
+            not(query("[file:name = 'a' OR file:name = 'b']"))
+        );
+    }
+
+    #[test]
+    fn test_like_to_wildcard() {
+        assert_eq!(like_to_wildcard(r"a_b%c\%*?"), r"a?b*c%\*\?");
+    }
+
+    #[test]
+    fn test_to_es_query_unsupported() {
+        let error =
+            |pattern: &str| to_es_query(&parse_pattern(pattern).unwrap(), &fields()).unwrap_err();
+        assert!(matches!(
+            error("[file:name = 'a'] WITHIN 5 SECONDS"),
+            EsError::Unsupported("qualifiers")
+        ));
+        assert!(matches!(
+            error("[ipv4-addr:value ISSUPERSET '10.0.0.1']"),
+            EsError::Unsupported("ISSUPERSET")
+        ));
+        assert!(matches!(
+            error("[url:value = 'a']"),
+            EsError::UnmappedPath(path) if path == "url:value"
+        ));
+    }
+}
@@ -0,0 +1,696 @@
+//! Evaluation of a single observation against observed data.
+//!
+//! The data is either a STIX observed-data object, whose `objects` (a dict
+//! keyed by index, as in STIX 2.0, or a list) are the objects observed, or
+//! a single cyber-observable object. A comparison holds when some value
+//! its object path resolves to, in some object of its type, satisfies it;
+//! comparisons are then combined with `AND` and `OR` as booleans.
+//!
+//! Properties ending in `_ref` or `_refs` followed by more path components
+//! are dereferenced through the keys of `objects` or through the `id` of
+//! the objects.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyTypeError;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use thiserror::Error;
+
+use crate::ast::{
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, ComparisonRhs,
+    ListIndex, ObjectPath, PathComponent, PatternExpr, StixValue,
+};
+use crate::like::like_matches;
+
+/// A JSON-like value, such as observed data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Data {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Data>),
+    Object(BTreeMap<String, Data>),
+}
+
+impl Data {
+    /// Name of the value's type, as used in error messages.
+    #[must_use]
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Bool(_) => "bool",
+            Self::Int(_) => "int",
+            Self::Float(_) => "float",
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::Object(_) => "object",
+        }
+    }
+
+    #[cfg(feature = "python")]
+    pub fn to_pyobject(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        match self {
+            Self::Null => Ok(py.None()),
+            Self::Bool(b) => Ok(b.into_pyobject(py)?.to_owned().into_any().unbind()),
+            Self::Int(i) => Ok(i.into_pyobject(py)?.into_any().unbind()),
+            Self::Float(f) => Ok(f.into_pyobject(py)?.into_any().unbind()),
+            Self::String(s) => Ok(s.into_pyobject(py)?.into_any().unbind()),
+            Self::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| item.to_pyobject(py))
+                    .collect::<PyResult<Vec<_>>>()?;
+                Ok(PyList::new(py, items)?.into_any().unbind())
+            }
+            Self::Object(map) => {
+                let dict = PyDict::new(py);
+                for (key, value) in map {
+                    dict.set_item(key, value.to_pyobject(py)?)?;
+                }
+                Ok(dict.into_any().unbind())
+            }
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Data> {
+        match self {
+            Self::Object(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+impl<'py> FromPyObject<'_, 'py> for Data {
+    type Error = PyErr;
+
+    fn extract(obj: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        if obj.is_none() {
+            Ok(Self::Null)
+        } else if obj.is_instance_of::<PyBool>() {
+            Ok(Self::Bool(obj.extract()?))
+        } else if obj.is_instance_of::<PyInt>() {
+            Ok(Self::Int(obj.extract()?))
+        } else if obj.is_instance_of::<PyFloat>() {
+            Ok(Self::Float(obj.extract()?))
+        } else if obj.is_instance_of::<PyString>() {
+            Ok(Self::String(obj.extract()?))
+        } else if obj.is_instance_of::<PyList>() || obj.is_instance_of::<PyTuple>() {
+            Ok(Self::List(obj.extract()?))
+        } else if let Ok(dict) = obj.cast::<PyDict>() {
+            dict.iter()
+                .map(|(key, value)| Ok((key.extract::<String>()?, value.extract()?)))
+                .collect::<PyResult<_>>()
+                .map(Self::Object)
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "unsupported observed data value of type {}",
+                obj.get_type().name()?
+            )))
+        }
+    }
+}
+
+/// Errors that can occur while evaluating a pattern.
+#[derive(Error, Debug)]
+pub enum EvalError {
+    #[error("Cannot evaluate {0}")]
+    Unsupported(&'static str),
+
+    #[error("Operator {op} cannot compare {found} with {expected}")]
+    TypeMismatch {
+        op: &'static str,
+        found: &'static str,
+        expected: &'static str,
+    },
+
+    #[error("'{0}' is not an IP address or CIDR range")]
+    InvalidAddress(String),
+
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(String),
+}
+
+pub type Result<T> = std::result::Result<T, EvalError>;
+
+/// Whether the observation `expr` matches `data`.
+///
+/// Only a single observation can be evaluated: observation operators and
+/// qualifiers relate several observed-data objects.
+pub fn matches_data(expr: &PatternExpr, data: &Data) -> Result<bool> {
+    match expr {
+        PatternExpr::Comparison(c) => eval_comparison_expr(&Objects::new(data), c),
+        PatternExpr::Composite(_) => Err(EvalError::Unsupported("observation operators")),
+        PatternExpr::Qualified(_) => Err(EvalError::Unsupported("qualifiers")),
+    }
+}
+
+/// The values `path` leads to in `data`, in order.
+///
+/// In an observed-data object they are looked up in every object of the
+/// path's type; any other `data` is taken as the object itself, whatever
+/// its type. `[*]` expands into every element of a list, while missing
+/// properties and out-of-range indices lead nowhere, so an unbound path
+/// resolves to nothing.
+#[must_use]
+pub fn resolve_path<'d>(data: &'d Data, path: &ObjectPath) -> Vec<&'d Data> {
+    let objects = Objects::new(data);
+    if data.get("objects").is_none() {
+        return resolve(&objects, data, &path.property_path);
+    }
+    objects
+        .of_type(&path.object_type)
+        .flat_map(|object| resolve(&objects, object, &path.property_path))
+        .collect()
+}
+
+/// The objects of one observed-data object.
+struct Objects<'d> {
+    /// Each object with its key in `objects`, if any.
+    objects: Vec<(Option<&'d str>, &'d Data)>,
+}
+
+impl<'d> Objects<'d> {
+    fn new(data: &'d Data) -> Self {
+        let objects = match data.get("objects") {
+            Some(Data::Object(map)) => map.iter().map(|(k, v)| (Some(k.as_str()), v)).collect(),
+            Some(Data::List(list)) => list.iter().map(|v| (None, v)).collect(),
+            _ => vec![(None, data)],
+        };
+        Self { objects }
+    }
+
+    fn of_type<'a>(&'a self, object_type: &'a str) -> impl Iterator<Item = &'d Data> + 'a {
+        self.objects
+            .iter()
+            .map(|(_, object)| *object)
+            .filter(move |object| object.get("type").and_then(Data::as_str) == Some(object_type))
+    }
+
+    /// The object a reference points to, by key or by id.
+    fn dereference(&self, reference: &str) -> Option<&'d Data> {
+        self.objects
+            .iter()
+            .find(|(key, object)| {
+                *key == Some(reference)
+                    || object.get("id").and_then(Data::as_str) == Some(reference)
+            })
+            .map(|(_, object)| *object)
+    }
+}
+
+/// The values `path` leads to from `object`, with `[*]` expanded into
+/// every element. Missing properties and out-of-range indices lead nowhere.
+fn resolve<'d>(objects: &Objects<'d>, object: &'d Data, path: &[PathComponent]) -> Vec<&'d Data> {
+    let mut current = vec![object];
+
+    for (i, component) in path.iter().enumerate() {
+        let mut next: Vec<&Data> = current
+            .iter()
+            .filter_map(|value| value.get(&component.property))
+            .collect();
+        for index in &component.indices {
+            next = next
+                .into_iter()
+                .flat_map(|value| match (value, index) {
+                    (Data::List(items), ListIndex::Star) => items.iter().collect(),
+                    (Data::List(items), ListIndex::Index(n)) => {
+                        items.get(*n as usize).into_iter().collect()
+                    }
+                    _ => Vec::new(),
+                })
+                .collect();
+        }
+        if component.is_reference() && i + 1 < path.len() {
+            next = next
+                .into_iter()
+                .filter_map(|value| value.as_str().and_then(|r| objects.dereference(r)))
+                .collect();
+        }
+        current = next;
+    }
+
+    current
+}
+
+/// Chains nest on the left, so the composites down that side are evaluated
+/// in a loop, from the innermost out.
+fn eval_comparison_expr(objects: &Objects, expr: &ComparisonExpr) -> Result<bool> {
+    let c = match expr {
+        ComparisonExpr::Single(c) => return eval_comparison(objects, c),
+        ComparisonExpr::Composite(c) => c,
+    };
+    let (spine, first) = c.left_spine();
+    let mut matched = eval_comparison(objects, first)?;
+    for c in spine.into_iter().rev() {
+        matched = match c.op {
+            BooleanOp::And if !matched => false,
+            BooleanOp::Or if matched => true,
+            _ => eval_comparison_expr(objects, &c.right)?,
+        };
+        // A negated group matches whenever the group does not.
+        matched = matched != c.negated;
+    }
+    Ok(matched)
+}
+
+/// Values of another type than the constant, and values that are not
+/// addresses for `ISSUBSET` and `ISSUPERSET`, never match, negated or not.
+fn eval_comparison(objects: &Objects, c: &Comparison) -> Result<bool> {
+    let path = &c.object_path;
+    let mut values = objects
+        .of_type(&path.object_type)
+        .flat_map(|object| resolve(objects, object, &path.property_path));
+
+    let op = match c.op {
+        ComparisonOperator::Unary(_) => return Ok(values.next().is_some()),
+        ComparisonOperator::Comparison(op) => op,
+    };
+    let rhs = c
+        .constant
+        .as_ref()
+        .ok_or(EvalError::Unsupported("a comparison without a constant"))?;
+    for value in values {
+        match apply_op(op, value, rhs) {
+            Ok(result) if result != c.negated => return Ok(true),
+            Ok(_) | Err(EvalError::TypeMismatch { .. } | EvalError::InvalidAddress(_)) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(false)
+}
+
+/// Apply `op` to a resolved value and a constant.
+///
+/// Numbers compare across ints and floats, strings compare to timestamps
+/// as RFC 3339 and to hex constants ignoring case, and `IN` holds when the
+/// value equals an element of the list. `ISSUBSET` holds when the address
+/// or CIDR range `lhs` lies within the range `rhs`, and `ISSUPERSET` the
+/// other way round, a range containing itself.
+///
+/// # Errors
+///
+/// `EvalError::TypeMismatch` when the value and the constant cannot be
+/// compared, and `EvalError::InvalidAddress` when either operand of
+/// `ISSUBSET` or `ISSUPERSET` is not an address or CIDR range.
+pub fn apply_op(op: ComparisonOp, lhs: &Data, rhs: &ComparisonRhs) -> Result<bool> {
+    let mismatch = |expected| EvalError::TypeMismatch {
+        op: op.value(),
+        found: lhs.type_name(),
+        expected,
+    };
+    let constant = match rhs {
+        ComparisonRhs::Value(value) => value,
+        ComparisonRhs::List(values) if op == ComparisonOp::In => {
+            return Ok(values
+                .iter()
+                .any(|value| compare(lhs, value) == Some(Ordering::Equal)));
+        }
+        ComparisonRhs::List(_) => return Err(mismatch("list")),
+        ComparisonRhs::Path(_) => {
+            return Err(EvalError::Unsupported(
+                "an object path on the right-hand side",
+            ));
+        }
+    };
+
+    let ordering = || compare(lhs, constant).ok_or_else(|| mismatch(constant.type_name()));
+    match op {
+        ComparisonOp::Eq => Ok(ordering()? == Ordering::Equal),
+        ComparisonOp::Neq => Ok(ordering()? != Ordering::Equal),
+        ComparisonOp::Gt => Ok(ordering()? == Ordering::Greater),
+        ComparisonOp::Lt => Ok(ordering()? == Ordering::Less),
+        ComparisonOp::Ge => Ok(ordering()? != Ordering::Less),
+        ComparisonOp::Le => Ok(ordering()? != Ordering::Greater),
+        ComparisonOp::In => Err(mismatch(constant.type_name())),
+        ComparisonOp::Like => match (lhs, constant) {
+            (Data::String(text), StixValue::String(pattern)) => Ok(like_matches(pattern, text)),
+            _ => Err(mismatch(constant.type_name())),
+        },
+        ComparisonOp::Matches => match (lhs, constant) {
+            (Data::String(text), StixValue::String(pattern)) => regex_matches(pattern, text),
+            _ => Err(mismatch(constant.type_name())),
+        },
+        ComparisonOp::IsSubset | ComparisonOp::IsSuperset => {
+            let (Data::String(value), StixValue::String(range)) = (lhs, constant) else {
+                return Err(mismatch(constant.type_name()));
+            };
+            if op == ComparisonOp::IsSubset {
+                is_subset(value, range)
+            } else {
+                is_superset(value, range)
+            }
+        }
+    }
+}
+
+/// Whether the address or CIDR range `value` lies within the range
+/// `range`, as in `'198.51.100.7' ISSUBSET '198.51.100.0/24'`. Addresses
+/// of different IP versions are never subsets of each other.
+///
+/// # Errors
+///
+/// `EvalError::InvalidAddress` when either is not an address or range.
+pub fn is_subset(value: &str, range: &str) -> Result<bool> {
+    Ok(parse_network(range)?.contains(&parse_network(value)?))
+}
+
+/// Whether the CIDR range `value` contains the address or range `range`,
+/// as in `'198.51.100.0/24' ISSUPERSET '198.51.100.7'`.
+///
+/// # Errors
+///
+/// `EvalError::InvalidAddress` when either is not an address or range.
+pub fn is_superset(value: &str, range: &str) -> Result<bool> {
+    is_subset(range, value)
+}
+
+/// An address or a CIDR range, an address being a range of one.
+pub(crate) fn parse_network(s: &str) -> Result<IpNet> {
+    s.parse::<IpNet>()
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| EvalError::InvalidAddress(s.to_owned()))
+}
+
+/// Order a data value against a constant, or `None` if they are of
+/// different types or, like `NaN`, unordered. Strings are compared to
+/// timestamps as RFC 3339 and to hex constants ignoring case.
+fn compare(lhs: &Data, constant: &StixValue) -> Option<Ordering> {
+    match (lhs, constant) {
+        (Data::String(s), StixValue::String(c) | StixValue::Binary(c)) => Some(s.as_str().cmp(c)),
+        (Data::String(s), StixValue::Hex(c)) => {
+            Some(s.to_ascii_lowercase().cmp(&c.to_ascii_lowercase()))
+        }
+        (Data::String(s), StixValue::Timestamp(c)) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.to_utc().cmp(c)),
+        (Data::Int(n), StixValue::Int(c)) => Some(n.cmp(c)),
+        #[allow(clippy::cast_precision_loss)]
+        (Data::Int(n), StixValue::Float(c)) => (*n as f64).partial_cmp(c),
+        #[allow(clippy::cast_precision_loss)]
+        (Data::Float(n), StixValue::Int(c)) => n.partial_cmp(&(*c as f64)),
+        (Data::Float(n), StixValue::Float(c)) => n.partial_cmp(c),
+        (Data::Bool(b), StixValue::Bool(c)) => Some(b.cmp(c)),
+        (Data::Null, StixValue::Null) => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "regex")]
+fn regex_matches(pattern: &str, text: &str) -> Result<bool> {
+    regex::Regex::new(pattern)
+        .map(|re| re.is_match(text))
+        .map_err(|e| EvalError::InvalidRegex(e.to_string()))
+}
+
+#[cfg(not(feature = "regex"))]
+fn regex_matches(_pattern: &str, _text: &str) -> Result<bool> {
+    Err(EvalError::Unsupported(
+        "MATCHES without the `regex` feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParseOptions, parse_pattern, parse_pattern_with_options};
+
+    fn object(entries: &[(&str, Data)]) -> Data {
+        Data::Object(
+            entries
+                .iter()
+                .map(|(k, v)| ((*k).to_owned(), v.clone()))
+                .collect(),
+        )
+    }
+
+    fn string(s: &str) -> Data {
+        Data::String(s.to_owned())
+    }
+
+    fn observed_data() -> Data {
+        let file = object(&[
+            ("type", string("file")),
+            ("name", string("evil.exe")),
+            ("size", Data::Int(2048)),
+            (
+                "hashes",
+                object(&[("SHA-256", string("AB12")), ("MD5", string("cd34"))]),
+            ),
+            ("parent_directory_ref", string("1")),
+            ("ctime", string("2023-01-01T12:00:00Z")),
+        ]);
+        let directory = object(&[("type", string("directory")), ("path", string("C:\\tmp"))]);
+        let email = object(&[
+            ("type", string("email-message")),
+            ("to_refs", Data::List(vec![string("3"), string("4")])),
+        ]);
+        let addr =
+            |value: &str| object(&[("type", string("email-addr")), ("value", string(value))]);
+        object(&[(
+            "objects",
+            object(&[
+                ("0", file),
+                ("1", directory),
+                ("2", email),
+                ("3", addr("a@example.com")),
+                ("4", addr("b@example.com")),
+            ]),
+        )])
+    }
+
+    fn matches(pattern: &str) -> bool {
+        matches_data(&parse_pattern(pattern).unwrap(), &observed_data()).unwrap()
+    }
+
+    #[test]
+    fn test_matches_data() {
+        assert!(matches("[file:name = 'evil.exe']"));
+        assert!(!matches("[file:name = 'good.exe']"));
+        assert!(matches("[file:size > 1024 AND file:size <= 2048]"));
+        assert!(matches("[file:size >= 2048.0]"));
+        assert!(matches("[file:name LIKE '%.exe']"));
+        #[cfg(feature = "regex")]
+        assert!(matches("[file:name MATCHES '^evil']"));
+        assert!(matches("[file:name IN ('a', 'evil.exe')]"));
+        assert!(matches("[file:hashes.'SHA-256' = h'ab12']"));
+        assert!(matches("[file:ctime < t'2023-01-02T00:00:00Z']"));
+        assert!(matches("[file:parent_directory_ref.path = 'C:\\\\tmp']"));
+        assert!(matches(
+            "[email-message:to_refs[*].value = 'b@example.com']"
+        ));
+        assert!(matches(
+            "[email-message:to_refs[0].value = 'a@example.com']"
+        ));
+        assert!(!matches(
+            "[email-message:to_refs[0].value = 'b@example.com']"
+        ));
+        assert!(!matches(
+            "[email-message:to_refs[5].value = 'a@example.com']"
+        ));
+        assert!(matches("[EXISTS file:hashes.MD5]"));
+        assert!(!matches("[EXISTS file:hashes.SHA1]"));
+        assert!(matches("[file:name NOT = 'good.exe']"));
+        assert!(!matches("[file:name NOT = 'evil.exe']"));
+        assert!(!matches("[file:size = 'evil.exe']"));
+        assert!(!matches("[file:size NOT = 'evil.exe']"));
+        assert!(matches("[url:value = 'x' OR directory:path LIKE 'C:%']"));
+        assert!(!matches("[url:value = 'x' AND directory:path LIKE 'C:%']"));
+
+        let options = ParseOptions {
+            allow_grouped_negation: true,
+            ..Default::default()
+        };
+        let negated = |pattern: &str| {
+            let expr = parse_pattern_with_options(pattern, &options).unwrap();
+            matches_data(&expr, &observed_data()).unwrap()
+        };
+        assert!(negated("[NOT (file:name = 'evil.exe' AND file:size = 0)]"));
+        assert!(!negated("[NOT (file:name = 'evil.exe' OR file:size = 0)]"));
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let data = observed_data();
+        let resolve = |input: &str| {
+            let expr = parse_pattern(&format!("[EXISTS {input}]")).unwrap();
+            let c = &expr.comparisons()[0];
+            resolve_path(&data, &c.object_path)
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(resolve("file:size"), [Data::Int(2048)]);
+        assert_eq!(
+            resolve("email-message:to_refs[*].value"),
+            [string("a@example.com"), string("b@example.com")]
+        );
+        assert_eq!(
+            resolve("email-message:to_refs"),
+            [Data::List(vec![string("3"), string("4")])]
+        );
+        assert_eq!(resolve("email-message:to_refs[1]"), [string("4")]);
+        assert_eq!(resolve("email-message:to_refs[2]"), []);
+        assert_eq!(resolve("file:missing"), []);
+        assert_eq!(resolve("file:size[0]"), []);
+        assert_eq!(
+            resolve("email-addr:value"),
+            [string("a@example.com"), string("b@example.com")]
+        );
+
+        let file = object(&[("name", string("a"))]);
+        let expr = parse_pattern("[EXISTS file:name]").unwrap();
+        let c = &expr.comparisons()[0];
+        assert_eq!(resolve_path(&file, &c.object_path), [&string("a")]);
+    }
+
+    #[test]
+    fn test_matches_single_object() {
+        let data = object(&[("type", string("url")), ("value", string("http://x"))]);
+        let expr = parse_pattern("[url:value = 'http://x']").unwrap();
+        assert!(matches_data(&expr, &data).unwrap());
+    }
+
+    #[test]
+    fn test_apply_op() {
+        let value = |v: StixValue| ComparisonRhs::Value(v);
+        let text = |s: &str| value(StixValue::String(s.to_owned()));
+        let apply = |op, lhs: &str, rhs: &ComparisonRhs| apply_op(op, &string(lhs), rhs);
+
+        assert!(
+            apply(
+                ComparisonOp::IsSubset,
+                "198.51.100.7",
+                &text("198.51.100.0/24")
+            )
+            .unwrap()
+        );
+        assert!(
+            apply(
+                ComparisonOp::IsSubset,
+                "198.51.100.0/25",
+                &text("198.51.100.0/24")
+            )
+            .unwrap()
+        );
+        assert!(
+            !apply(
+                ComparisonOp::IsSubset,
+                "198.51.101.7",
+                &text("198.51.100.0/24")
+            )
+            .unwrap()
+        );
+        assert!(
+            !apply(
+                ComparisonOp::IsSubset,
+                "2001:db8::1",
+                &text("198.51.100.0/24")
+            )
+            .unwrap()
+        );
+        assert!(
+            apply(
+                ComparisonOp::IsSuperset,
+                "2001:db8::/32",
+                &text("2001:db8::1")
+            )
+            .unwrap()
+        );
+        assert!(apply(ComparisonOp::IsSuperset, "10.0.0.0/8", &text("10.0.0.0/8")).unwrap());
+        assert!(matches!(
+            apply(ComparisonOp::IsSubset, "evil.exe", &text("10.0.0.0/8")),
+            Err(EvalError::InvalidAddress(a)) if a == "evil.exe"
+        ));
+        assert!(matches!(
+            apply_op(ComparisonOp::IsSubset, &Data::Int(1), &text("10.0.0.0/8")),
+            Err(EvalError::TypeMismatch {
+                op: "ISSUBSET",
+                found: "int",
+                expected: "string"
+            })
+        ));
+
+        assert!(apply(ComparisonOp::Like, "evil.exe", &text("%.EXE")).is_ok_and(|r| !r));
+        assert!(
+            apply_op(
+                ComparisonOp::Le,
+                &Data::Float(1.5),
+                &value(StixValue::Int(2))
+            )
+            .unwrap()
+        );
+        let list = ComparisonRhs::List(vec![StixValue::Int(1), StixValue::Int(2)]);
+        assert!(apply_op(ComparisonOp::In, &Data::Int(2), &list).unwrap());
+        assert!(matches!(
+            apply_op(ComparisonOp::Eq, &Data::Int(2), &list),
+            Err(EvalError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            apply(ComparisonOp::Gt, "a", &value(StixValue::Int(2))),
+            Err(EvalError::TypeMismatch {
+                op: ">",
+                found: "string",
+                expected: "int"
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cidr_helpers() {
+        assert!(is_subset("10.1.2.3", "10.0.0.0/8").unwrap());
+        assert!(is_subset("10.1.0.0/16", "10.0.0.0/8").unwrap());
+        assert!(!is_subset("10.0.0.0/8", "10.1.0.0/16").unwrap());
+        assert!(is_superset("10.0.0.0/8", "10.1.2.3").unwrap());
+        assert!(is_subset("2001:db8::8", "2001:db8::/64").unwrap());
+        assert!(!is_subset("::ffff:10.1.2.3", "10.0.0.0/8").unwrap());
+        assert!(matches!(
+            is_subset("10.0.0.0/8", "10.0.0.0/33"),
+            Err(EvalError::InvalidAddress(_))
+        ));
+    }
+
+    #[test]
+    fn test_matches_addresses() {
+        let data = object(&[
+            ("type", string("ipv4-addr")),
+            ("value", string("198.51.100.7")),
+        ]);
+        let matches =
+            |pattern: &str| matches_data(&parse_pattern(pattern).unwrap(), &data).unwrap();
+        assert!(matches("[ipv4-addr:value ISSUBSET '198.51.100.0/24']"));
+        assert!(!matches("[ipv4-addr:value ISSUBSET '203.0.113.0/24']"));
+        assert!(matches("[ipv4-addr:value NOT ISSUBSET '203.0.113.0/24']"));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let data = observed_data();
+        for pattern in [
+            "[file:name = 'a'] AND [file:name = 'b']",
+            "[file:name = 'a'] REPEATS 2 TIMES",
+        ] {
+            let expr = parse_pattern(pattern).unwrap();
+            assert!(matches!(
+                matches_data(&expr, &data),
+                Err(EvalError::Unsupported(_))
+            ));
+        }
+    }
+}
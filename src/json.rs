@@ -0,0 +1,322 @@
+//! Serialization of patterns to a versioned JSON document.
+//!
+//! The document wraps the root node with the version of the node schema:
+//!
+//! ```text
+//! {"ast_version": 2, "root": {"type": "Comparison", ...}}
+//! ```
+//!
+//! Nodes follow the schema described in [`crate::dict`], which builds its
+//! dicts from [`to_json_value`]. `AST_VERSION` is raised whenever a node
+//! gains, loses or changes the meaning of a key. Infinite floats, which
+//! JSON cannot represent, are written as `null`.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Map, Value, json};
+
+use crate::ast::{
+    Comparison, ComparisonExpr, ComparisonOperator, ComparisonRhs, CompositeComparison,
+    CompositePattern, ListIndex, ObjectPath, PatternExpr, PropertyKind, QualifiedPattern,
+    StixValue,
+};
+
+/// Version of the node schema written by [`to_json`].
+pub const AST_VERSION: u32 = 2;
+
+/// Serialize `expr` to a compact JSON document.
+#[must_use]
+pub fn to_json(expr: &PatternExpr) -> String {
+    let mut doc = Map::new();
+    doc.insert("ast_version".into(), AST_VERSION.into());
+    doc.insert("root".into(), to_json_value(expr));
+    let doc = Value::Object(doc);
+    let mut out = String::new();
+    write_value(&doc, &mut out);
+    drop_value(doc);
+    out
+}
+
+/// The JSON node for `expr`, without the version envelope.
+///
+/// Like the AST, the node nests once per operand of a chain, which
+/// serde_json serializes and drops by recursion; [`to_json`] writes it with
+/// a loop instead.
+#[must_use]
+pub fn to_json_value(expr: &PatternExpr) -> Value {
+    enum Step<'a> {
+        Pattern(&'a PatternExpr),
+        Comparison(&'a ComparisonExpr),
+        CompositePattern(&'a CompositePattern),
+        QualifiedPattern(&'a QualifiedPattern),
+        CompositeComparison(&'a CompositeComparison),
+    }
+
+    // Built bottom-up, each node taking its operands off `built`, as chains
+    // nest too deeply to recurse down.
+    let mut pending = vec![Step::Pattern(expr)];
+    let mut built = Vec::new();
+    while let Some(step) = pending.pop() {
+        match step {
+            Step::Pattern(PatternExpr::Comparison(c)) => pending.push(Step::Comparison(c)),
+            Step::Pattern(PatternExpr::Composite(c)) => pending.extend([
+                Step::CompositePattern(c),
+                Step::Pattern(&c.right),
+                Step::Pattern(&c.left),
+            ]),
+            Step::Pattern(PatternExpr::Qualified(q)) => {
+                pending.extend([Step::QualifiedPattern(q), Step::Pattern(&q.pattern)]);
+            }
+            Step::Comparison(ComparisonExpr::Single(c)) => built.push(comparison(c)),
+            Step::Comparison(ComparisonExpr::Composite(c)) => pending.extend([
+                Step::CompositeComparison(c),
+                Step::Comparison(&c.right),
+                Step::Comparison(&c.left),
+            ]),
+            Step::CompositePattern(c) => {
+                let right = built.pop().expect("operands are built first");
+                let left = built.pop().expect("operands are built first");
+                built.push(object([
+                    ("type", "CompositePattern".into()),
+                    ("left", left),
+                    ("op", c.op.value().into()),
+                    ("right", right),
+                    ("parenthesized", c.parenthesized.into()),
+                ]));
+            }
+            Step::QualifiedPattern(q) => {
+                let pattern = built.pop().expect("operands are built first");
+                built.push(object([
+                    ("type", "QualifiedPattern".into()),
+                    ("pattern", pattern),
+                    ("repeat", q.repeat.into()),
+                    ("within", q.within.into()),
+                    ("start", q.start.as_ref().map(timestamp).into()),
+                    ("stop", q.stop.as_ref().map(timestamp).into()),
+                ]));
+            }
+            Step::CompositeComparison(c) => {
+                let right = built.pop().expect("operands are built first");
+                let left = built.pop().expect("operands are built first");
+                built.push(object([
+                    ("type", "CompositeComparison".into()),
+                    ("left", left),
+                    ("op", c.op.value().into()),
+                    ("right", right),
+                    ("parenthesized", c.parenthesized.into()),
+                    ("negated", c.negated.into()),
+                ]));
+            }
+        }
+    }
+    built.pop().expect("the root is built last")
+}
+
+/// An object of `entries` in order. `json!` would serialize each value
+/// again, copying whole subtrees.
+fn object<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::Object(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect(),
+    )
+}
+
+fn comparison(c: &Comparison) -> Value {
+    let (constant, constant_type) = match &c.constant {
+        None => (Value::Null, Value::Null),
+        Some(ComparisonRhs::Path(path)) => (object_path(path), Value::Null),
+        Some(ComparisonRhs::Value(v)) => (value(v), v.type_name().into()),
+        Some(ComparisonRhs::List(values)) => (
+            values.iter().map(value).collect(),
+            values.iter().map(StixValue::type_name).collect(),
+        ),
+    };
+    let op = match c.op {
+        ComparisonOperator::Comparison(op) => op.value(),
+        ComparisonOperator::Unary(op) => op.value(),
+    };
+    json!({
+        "type": "Comparison",
+        "object_path": object_path(&c.object_path),
+        "op": op,
+        "constant": constant,
+        "constant_type": constant_type,
+        "negated": c.negated,
+    })
+}
+
+fn object_path(path: &ObjectPath) -> Value {
+    let components: Vec<Value> = path
+        .property_path
+        .iter()
+        .map(|c| {
+            let index = |idx: &ListIndex| match idx {
+                ListIndex::Index(i) => Value::from(*i),
+                ListIndex::Star => Value::from("*"),
+            };
+            let index = match c.indices.as_slice() {
+                [] => Value::Null,
+                [idx] => index(idx),
+                indices => indices.iter().map(index).collect(),
+            };
+            json!({
+                "type": "PathComponent",
+                "property": &*c.property,
+                "index": index,
+                "kind": match c.kind {
+                    PropertyKind::Plain => "PLAIN",
+                    PropertyKind::Quoted => "QUOTED",
+                },
+            })
+        })
+        .collect();
+    json!({
+        "type": "ObjectPath",
+        "object_type": &*path.object_type,
+        "property_path": components,
+    })
+}
+
+fn value(v: &StixValue) -> Value {
+    match v {
+        StixValue::String(s) | StixValue::Hex(s) | StixValue::Binary(s) => s.as_str().into(),
+        StixValue::Int(i) => (*i).into(),
+        StixValue::Float(f) => (*f).into(),
+        StixValue::Bool(b) => (*b).into(),
+        StixValue::Timestamp(dt) => timestamp(dt).into(),
+        StixValue::Null => Value::Null,
+    }
+}
+
+fn timestamp(dt: &DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Write `value` compactly, as `Value::to_string` does, with a stack of
+/// what is left to write instead of recursion.
+fn write_value(value: &Value, out: &mut String) {
+    enum Step<'a> {
+        Value(&'a Value),
+        Key(&'a str),
+        Text(&'static str),
+    }
+
+    let mut pending = vec![Step::Value(value)];
+    while let Some(step) = pending.pop() {
+        match step {
+            Step::Value(Value::Array(items)) => {
+                out.push('[');
+                pending.push(Step::Text("]"));
+                for (i, item) in items.iter().enumerate().rev() {
+                    pending.push(Step::Value(item));
+                    if i > 0 {
+                        pending.push(Step::Text(","));
+                    }
+                }
+            }
+            Step::Value(Value::Object(map)) => {
+                out.push('{');
+                pending.push(Step::Text("}"));
+                for (i, (key, value)) in map.iter().enumerate().rev() {
+                    pending.push(Step::Value(value));
+                    pending.push(Step::Key(key));
+                    if i > 0 {
+                        pending.push(Step::Text(","));
+                    }
+                }
+            }
+            Step::Value(scalar) => write!(out, "{scalar}").expect("a String never fails"),
+            Step::Key(key) => {
+                write!(out, "{}:", Value::from(key)).expect("a String never fails");
+            }
+            Step::Text(text) => out.push_str(text),
+        }
+    }
+}
+
+/// Drop `value` one node at a time rather than by recursion.
+pub(crate) fn drop_value(value: Value) {
+    let mut pending = vec![value];
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::Array(items) => pending.extend(items),
+            Value::Object(map) => pending.extend(map.into_iter().map(|(_, value)| value)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_pattern;
+
+    #[test]
+    fn test_to_json() {
+        let expr = parse_pattern(
+            "[file:hashes.'SHA-256' = 'ab' AND file:x[0][*] IN (1, 2.5)] \
+             REPEATS 2 TIMES START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'",
+        )
+        .unwrap();
+        let output = to_json(&expr);
+        let doc: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(output, doc.to_string());
+        assert_eq!(doc["ast_version"], AST_VERSION);
+        let root = &doc["root"];
+        assert_eq!(root["type"], "QualifiedPattern");
+        assert_eq!(root["repeat"], Value::Null);
+        assert_eq!(root["start"], "2023-01-01T00:00:00Z");
+        assert_eq!(root["pattern"]["repeat"], 2);
+
+        let and = &root["pattern"]["pattern"];
+        assert_eq!(and["type"], "CompositeComparison");
+        assert_eq!(and["op"], "AND");
+        assert_eq!(
+            and["left"]["object_path"]["property_path"][1],
+            json!({
+                "type": "PathComponent",
+                "property": "SHA-256",
+                "index": null,
+                "kind": "QUOTED",
+            })
+        );
+        assert_eq!(and["left"]["constant_type"], "string");
+        let right = &and["right"];
+        assert_eq!(right["op"], "IN");
+        assert_eq!(right["constant"], json!([1, 2.5]));
+        assert_eq!(right["constant_type"], json!(["int", "float"]));
+        assert_eq!(
+            right["object_path"]["property_path"][0]["index"],
+            json!([0, "*"])
+        );
+    }
+
+    #[test]
+    fn test_to_json_long_chain() {
+        let n = 10_000;
+        let observations: Vec<String> = (0..n).map(|i| format!("[a:x = {i}]")).collect();
+        let expr = parse_pattern(&format!(
+            "({}) WITHIN 5 SECONDS",
+            observations.join(" AND ")
+        ))
+        .unwrap();
+        let output = to_json(&expr);
+        assert!(output.starts_with(
+            r#"{"ast_version":2,"root":{"type":"QualifiedPattern","pattern":{"type":"CompositePattern","left":{"type":"CompositePattern","#
+        ));
+        assert_eq!(
+            output.matches(r#""type":"CompositePattern""#).count(),
+            n - 1
+        );
+        assert!(output.ends_with(r#""within":5.0,"start":null,"stop":null}}"#));
+    }
+
+    #[test]
+    fn test_to_json_key_order() {
+        let json = to_json(&parse_pattern("[file:name = 'a']").unwrap());
+        assert!(json.starts_with(r#"{"ast_version":2,"root":{"type":"Comparison","object_path":"#));
+    }
+}
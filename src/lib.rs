@@ -1,9 +1,27 @@
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
+pub mod analysis;
 pub mod ast;
+#[cfg(feature = "python")]
 pub mod bindings;
+pub mod builder;
+pub mod cache;
+pub mod diagnostics;
+#[cfg(feature = "python")]
+pub mod dict;
+pub mod dot;
+pub mod es;
+pub mod eval;
+pub mod json;
+pub mod like;
+pub mod lint;
 pub mod parser;
+pub mod serialize;
+pub mod sql;
+pub mod transform;
 
+#[cfg(feature = "python")]
 #[pymodule(name = "stix_patterns_parser")]
 fn pythonapi(m: &Bound<'_, PyModule>) -> PyResult<()> {
     bindings::register(m)?;
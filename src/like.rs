@@ -0,0 +1,213 @@
+//! Helpers for the wildcard semantics of the `LIKE` operator.
+//!
+//! `%` matches any run of characters and `_` matches exactly one. A
+//! backslash makes the following character literal, so `\%` and `\_`
+//! match a percent sign and an underscore.
+
+/// A single element of a `LIKE` operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LikeToken {
+    Literal(char),
+    /// `%`
+    AnyRun,
+    /// `_`
+    AnyChar,
+}
+
+/// How the wildcards of a `LIKE` operand are placed around its literal text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LikeShape {
+    /// No wildcards, as in `'abc'`.
+    Exact,
+    /// `%` at the end only, as in `'abc%'`.
+    Prefix,
+    /// `%` at the start only, as in `'%abc'`.
+    Suffix,
+    /// `%` at both ends, as in `'%abc%'` or `'%'`.
+    Contains,
+    /// `_` anywhere or `%` between literals, as in `'a%c'`.
+    Complex,
+}
+
+impl LikeShape {
+    /// `"exact"`, `"prefix"`, `"suffix"`, `"contains"` or `"complex"`.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::Prefix => "prefix",
+            Self::Suffix => "suffix",
+            Self::Contains => "contains",
+            Self::Complex => "complex",
+        }
+    }
+}
+
+/// Split a `LIKE` operand into literals and wildcards, resolving escapes.
+#[must_use]
+pub fn tokenize_like(pattern: &str) -> Vec<LikeToken> {
+    let mut tokens = Vec::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '\\' => LikeToken::Literal(chars.next().unwrap_or('\\')),
+            '%' => LikeToken::AnyRun,
+            '_' => LikeToken::AnyChar,
+            c => LikeToken::Literal(c),
+        });
+    }
+
+    tokens
+}
+
+/// Convert a `LIKE` operand into an equivalent anchored regular expression.
+#[must_use]
+pub fn like_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("(?s)^");
+    for token in tokenize_like(pattern) {
+        match token {
+            LikeToken::AnyRun => regex.push_str(".*"),
+            LikeToken::AnyChar => regex.push('.'),
+            LikeToken::Literal(c) => {
+                if is_regex_meta(c) {
+                    regex.push('\\');
+                }
+                regex.push(c);
+            }
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Classify the wildcards of a `LIKE` operand. Escaped `\%` and `\_` are
+/// literals.
+#[must_use]
+pub fn like_shape(pattern: &str) -> LikeShape {
+    let tokens = tokenize_like(pattern);
+    let is_literal = |token: &LikeToken| matches!(token, LikeToken::Literal(_));
+    let start = tokens
+        .iter()
+        .position(|t| *t != LikeToken::AnyRun)
+        .unwrap_or(tokens.len());
+    let end = tokens
+        .iter()
+        .rposition(|t| *t != LikeToken::AnyRun)
+        .map_or(start, |i| i + 1);
+
+    if !tokens[start..end].iter().all(is_literal) {
+        return LikeShape::Complex;
+    }
+    match (start > 0, end < tokens.len()) {
+        (false, false) => LikeShape::Exact,
+        (false, true) => LikeShape::Prefix,
+        (true, false) if end > start => LikeShape::Suffix,
+        _ => LikeShape::Contains,
+    }
+}
+
+/// Whether `text` matches the `LIKE` operand `pattern` as a whole.
+#[must_use]
+pub fn like_matches(pattern: &str, text: &str) -> bool {
+    let tokens = tokenize_like(pattern);
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `%` and of the text it currently absorbs up to.
+    let mut any_run: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(LikeToken::AnyRun) => {
+                any_run = Some((p, t));
+                p += 1;
+            }
+            Some(LikeToken::AnyChar) => {
+                p += 1;
+                t += 1;
+            }
+            Some(LikeToken::Literal(c)) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match any_run {
+                Some((run_p, run_t)) => {
+                    any_run = Some((run_p, run_t + 1));
+                    p = run_p + 1;
+                    t = run_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[p..].iter().all(|&token| token == LikeToken::AnyRun)
+}
+
+fn is_regex_meta(c: char) -> bool {
+    matches!(
+        c,
+        '\\' | '.'
+            | '+'
+            | '*'
+            | '?'
+            | '('
+            | ')'
+            | '|'
+            | '['
+            | ']'
+            | '{'
+            | '}'
+            | '^'
+            | '$'
+            | '#'
+            | '&'
+            | '-'
+            | '~'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert_eq!(like_to_regex("%.exe"), r"(?s)^.*\.exe$");
+        assert_eq!(like_to_regex("a_c"), "(?s)^a.c$");
+    }
+
+    #[test]
+    fn test_escaped_wildcards() {
+        assert_eq!(like_to_regex(r"100\%"), "(?s)^100%$");
+        assert_eq!(like_to_regex(r"a\_b%"), "(?s)^a_b.*$");
+        assert_eq!(like_to_regex(r"C:\\%"), r"(?s)^C:\\.*$");
+    }
+
+    #[test]
+    fn test_like_matches() {
+        assert!(like_matches("%.exe", "evil.exe"));
+        assert!(like_matches("a_c", "abc"));
+        assert!(!like_matches("a_c", "abbc"));
+        assert!(like_matches("%a%b%", "xaxxbx"));
+        assert!(!like_matches("%a%b%", "xbxa"));
+        assert!(like_matches(r"100\%", "100%"));
+        assert!(!like_matches(r"100\%", "1000"));
+        assert!(like_matches("%", ""));
+    }
+
+    #[test]
+    fn test_like_shape() {
+        assert_eq!(like_shape("abc"), LikeShape::Exact);
+        assert_eq!(like_shape(""), LikeShape::Exact);
+        assert_eq!(like_shape("abc%"), LikeShape::Prefix);
+        assert_eq!(like_shape("abc%%"), LikeShape::Prefix);
+        assert_eq!(like_shape("%abc"), LikeShape::Suffix);
+        assert_eq!(like_shape("%abc%"), LikeShape::Contains);
+        assert_eq!(like_shape("%"), LikeShape::Contains);
+        assert_eq!(like_shape("a%c"), LikeShape::Complex);
+        assert_eq!(like_shape("ab_"), LikeShape::Complex);
+        assert_eq!(like_shape(r"100\%"), LikeShape::Exact);
+        assert_eq!(like_shape(r"\%\_%"), LikeShape::Prefix);
+        assert_eq!(like_shape(r"%a\%b"), LikeShape::Suffix);
+    }
+}
@@ -0,0 +1,252 @@
+//! Checks for patterns that parse but are probably mistakes.
+
+use std::collections::HashMap;
+
+use crate::analysis::flatten_comparison;
+use crate::ast::{
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, ComparisonRhs,
+    ObjectPath, PatternExpr, StixValue, UnaryOp,
+};
+use crate::transform::push_negations;
+
+/// Run every check, contradictions first.
+#[must_use]
+pub fn lint(expr: &PatternExpr) -> Vec<String> {
+    let mut messages = find_contradictions(expr);
+    messages.extend(find_redundant_exists(expr));
+    messages
+}
+
+/// Report `EXISTS` comparisons joined by `AND` to a comparison on the same
+/// object path, such as `EXISTS file:name` next to `file:name = 'x'`. The
+/// comparison can only match if the property exists.
+///
+/// Negated comparisons are not taken to imply existence.
+#[must_use]
+pub fn find_redundant_exists(expr: &PatternExpr) -> Vec<String> {
+    let mut messages = Vec::new();
+    for observation in expr.observations() {
+        check_exists(observation, &mut messages);
+    }
+    messages
+}
+
+fn check_exists(expr: &ComparisonExpr, messages: &mut Vec<String>) {
+    let (op, operands) = flatten_comparison(expr);
+    if op == Some(BooleanOp::And) {
+        let comparisons: Vec<&Comparison> = operands
+            .iter()
+            .filter_map(|operand| match operand {
+                ComparisonExpr::Single(c) => Some(c),
+                ComparisonExpr::Composite(_) => None,
+            })
+            .collect();
+        for exists in &comparisons {
+            if exists.op != ComparisonOperator::Unary(UnaryOp::Exists) {
+                continue;
+            }
+            let implied_by = comparisons.iter().find(|c| {
+                c.op != ComparisonOperator::Unary(UnaryOp::Exists)
+                    && !c.negated
+                    && c.object_path == exists.object_path
+            });
+            if let Some(c) = implied_by {
+                let location = match exists.span.start() {
+                    Some(offset) => format!(" at offset {offset}"),
+                    None => String::new(),
+                };
+                messages.push(format!("'{exists}'{location} is implied by '{c}'"));
+            }
+        }
+    }
+    for operand in operands {
+        match operand {
+            // The group holds whether negated or not, so its redundancies
+            // are the same.
+            ComparisonExpr::Composite(c) if c.negated => {
+                let mut group = c.clone();
+                group.negated = false;
+                check_exists(&group.into(), messages);
+            }
+            ComparisonExpr::Composite(_) => check_exists(operand, messages),
+            ComparisonExpr::Single(_) => {}
+        }
+    }
+}
+
+/// Report pairs of numeric comparisons on the same object path that are
+/// joined by `AND` but cannot both hold, such as `file:size > 100` and
+/// `file:size < 50`.
+///
+/// Only `=`, `>`, `<`, `>=` and `<=` against int or float constants are
+/// considered, after negations are pushed down.
+#[must_use]
+pub fn find_contradictions(expr: &PatternExpr) -> Vec<String> {
+    let mut messages = Vec::new();
+    for observation in expr.observations() {
+        check_comparison(&push_negations(observation.clone()), &mut messages);
+    }
+    messages
+}
+
+fn check_comparison(expr: &ComparisonExpr, messages: &mut Vec<String>) {
+    let (op, operands) = flatten_comparison(expr);
+    if op == Some(BooleanOp::And) {
+        let ranges: Vec<_> = operands
+            .iter()
+            .filter_map(|operand| match operand {
+                ComparisonExpr::Single(c) => Range::of(c).map(|range| (c, range)),
+                ComparisonExpr::Composite(_) => None,
+            })
+            .collect();
+        // Only ranges on the same path are compared, each with the ones
+        // after it.
+        let mut by_path: HashMap<&ObjectPath, Vec<usize>> = HashMap::new();
+        for (i, (c, _)) in ranges.iter().enumerate() {
+            by_path.entry(&c.object_path).or_default().push(i);
+        }
+        for (i, (a, range_a)) in ranges.iter().enumerate() {
+            let same_path = &by_path[&a.object_path];
+            let later = same_path.partition_point(|&j| j <= i);
+            for &j in &same_path[later..] {
+                let (b, range_b) = &ranges[j];
+                if !range_a.intersects(range_b) {
+                    messages.push(format!("'{a}' and '{b}' can never both match"));
+                }
+            }
+        }
+    }
+    // Operands that are composites use the other operator. A negated group
+    // left by `push_negations` matches when its operands cannot all hold,
+    // so it is not checked.
+    for operand in operands {
+        if let ComparisonExpr::Composite(c) = operand
+            && !c.negated
+        {
+            check_comparison(operand, messages);
+        }
+    }
+}
+
+/// The values a numeric comparison accepts, as optional `(bound, inclusive)`
+/// pairs.
+struct Range {
+    low: Option<(f64, bool)>,
+    high: Option<(f64, bool)>,
+}
+
+impl Range {
+    fn of(c: &Comparison) -> Option<Self> {
+        let value = match &c.constant {
+            Some(ComparisonRhs::Value(StixValue::Int(i))) => *i as f64,
+            Some(ComparisonRhs::Value(StixValue::Float(f))) => *f,
+            _ => return None,
+        };
+        let ComparisonOperator::Comparison(op) = c.op else {
+            return None;
+        };
+        if c.negated {
+            return None;
+        }
+        let (low, high) = match op {
+            ComparisonOp::Eq => (Some((value, true)), Some((value, true))),
+            ComparisonOp::Gt => (Some((value, false)), None),
+            ComparisonOp::Ge => (Some((value, true)), None),
+            ComparisonOp::Lt => (None, Some((value, false))),
+            ComparisonOp::Le => (None, Some((value, true))),
+            _ => return None,
+        };
+        Some(Self { low, high })
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        let low = tighter(self.low, other.low, |a, b| a > b);
+        let high = tighter(self.high, other.high, |a, b| a < b);
+        match (low, high) {
+            (Some((low, low_inclusive)), Some((high, high_inclusive))) => {
+                low < high || (low == high && low_inclusive && high_inclusive)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// The stricter of two bounds, `better` saying whether a value is stricter.
+fn tighter(
+    a: Option<(f64, bool)>,
+    b: Option<(f64, bool)>,
+    better: impl Fn(f64, f64) -> bool,
+) -> Option<(f64, bool)> {
+    match (a, b) {
+        (Some(a), Some(b)) if better(a.0, b.0) => Some(a),
+        (Some(a), Some(b)) if better(b.0, a.0) => Some(b),
+        (Some(a), Some(b)) => Some((a.0, a.1 && b.1)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_pattern;
+
+    fn contradictions(input: &str) -> Vec<String> {
+        find_contradictions(&parse_pattern(input).unwrap())
+    }
+
+    #[test]
+    fn test_find_contradictions() {
+        assert_eq!(
+            contradictions("[file:size > 100 AND file:name = 'a' AND file:size < 50]"),
+            ["'file:size > 100' and 'file:size < 50' can never both match"]
+        );
+        assert_eq!(
+            contradictions("[a:x = 1 AND a:x = 2] OR [b:y >= 3 AND b:y NOT >= 3]"),
+            [
+                "'a:x = 1' and 'a:x = 2' can never both match",
+                "'b:y >= 3' and 'b:y < 3' can never both match"
+            ]
+        );
+        assert_eq!(
+            contradictions("[a:x = 1 OR (a:y > 5 AND a:y <= 5.0)]"),
+            ["'a:y > 5' and 'a:y <= 5.0' can never both match"]
+        );
+    }
+
+    #[test]
+    fn test_find_redundant_exists() {
+        let redundant = |input| find_redundant_exists(&parse_pattern(input).unwrap());
+        assert_eq!(
+            redundant("[file:name = 'x' AND EXISTS file:name]"),
+            ["'EXISTS file:name' at offset 21 is implied by 'file:name = 'x''"]
+        );
+        assert_eq!(
+            redundant("[a:y = 1 OR (EXISTS a:x AND a:x > 2)]"),
+            ["'EXISTS a:x' at offset 13 is implied by 'a:x > 2'"]
+        );
+        assert!(redundant("[file:name = 'x' OR EXISTS file:name]").is_empty());
+        assert!(redundant("[file:name NOT = 'x' AND EXISTS file:name]").is_empty());
+        assert!(redundant("[file:size = 1 AND EXISTS file:name]").is_empty());
+        assert!(redundant("[file:name = 'x'] AND [EXISTS file:name]").is_empty());
+    }
+
+    #[test]
+    fn test_lint() {
+        assert_eq!(
+            lint(&parse_pattern("[EXISTS a:x AND a:x > 5 AND a:x < 1]").unwrap()),
+            [
+                "'a:x > 5' and 'a:x < 1' can never both match",
+                "'EXISTS a:x' at offset 1 is implied by 'a:x > 5'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_satisfiable_ranges_are_not_reported() {
+        assert!(contradictions("[a:x >= 5 AND a:x <= 5]").is_empty());
+        assert!(contradictions("[a:x > 1 AND a:x < 10 AND a:x = 5]").is_empty());
+        assert!(contradictions("[a:x > 100 OR a:x < 50]").is_empty());
+        assert!(contradictions("[a:x > 100 AND b:x < 50]").is_empty());
+        assert!(contradictions("[a:x > 100] AND [a:x < 50]").is_empty());
+    }
+}
@@ -3,17 +3,31 @@
 //! This module uses pest to parse STIX pattern strings and converts
 //! the parse tree into our AST representation using recursive descent.
 
+use std::collections::HashSet;
+use std::num::{IntErrorKind, ParseIntError};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
 use chrono::{DateTime, Utc};
-use pest::Parser;
-use pest::iterators::Pair;
+use ipnet::IpNet;
+use pest::iterators::{Pair, Pairs};
+use pest::{Parser, Span};
 use pest_derive::Parser;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
 use thiserror::Error;
 
 use crate::ast::{
-    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonRhs, CompositeComparison,
-    CompositePattern, ListIndex, ObjectPath, ObservationOp, PathComponent, PatternExpr,
-    QualifiedPattern, StixValue, UnaryOp,
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, ComparisonRhs,
+    CompositeComparison, CompositePattern, ListIndex, ObjectPath, ObservationOp, PathComponent,
+    PatternExpr, PropertyKind, QualifiedPattern, SourceSpan, StixValue, UnaryOp,
+};
+use crate::diagnostics::{
+    ParseWarning, canonical_hash_name, is_known_object_type, is_valid_custom_object_type,
 };
+use crate::eval::parse_network;
 
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
@@ -22,8 +36,8 @@ struct StixParser;
 /// Errors that can occur during STIX pattern parsing.
 #[derive(Error, Debug)]
 pub enum ParseError {
-    #[error("Grammar error: {0}")]
-    Grammar(#[from] pest::error::Error<Rule>),
+    #[error("{}", render_grammar_error(.0))]
+    Grammar(Box<pest::error::Error<Rule>>),
 
     #[error("Invalid integer: {0}")]
     InvalidInt(#[from] std::num::ParseIntError),
@@ -31,165 +45,1477 @@ pub enum ParseError {
     #[error("Invalid float: {0}")]
     InvalidFloat(#[from] std::num::ParseFloatError),
 
+    #[error("Integer {literal} is out of range, expected {min} to {max}")]
+    IntOutOfRange { literal: String, min: i64, max: i64 },
+
+    #[error("Invalid float: {0} is not finite")]
+    NonFiniteFloat(String),
+
     #[error("Invalid timestamp: {0}")]
     InvalidTimestamp(String),
 
+    #[error("Timestamp {0} has no time zone")]
+    TimestampWithoutZone(String),
+
+    #[error("Hex constant {0} has an odd number of digits")]
+    InvalidHex(String),
+
+    #[error("Invalid base64 constant: {0}")]
+    InvalidBase64(String),
+
+    #[error("REPEATS count must be positive, at offset {0}")]
+    NonPositiveRepeat(usize),
+
+    #[error("START must be before STOP, at offset {0}")]
+    EmptyInterval(usize),
+
+    #[error("Invalid CIDR range: {0}")]
+    InvalidCidr(String),
+
     #[error("Unexpected rule: {0:?}")]
     UnexpectedRule(Rule),
 
     #[error("Missing expected element: {0}")]
     MissingElement(&'static str),
+
+    #[error("Invalid escape sequence: {0}")]
+    InvalidEscape(String),
+
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(String),
+
+    #[error("Keyword '{0}' must be uppercase")]
+    KeywordCase(String),
+
+    #[error("Object path used as a constant at offset {0}")]
+    PathRhs(usize),
+
+    #[error("null constant at offset {0}")]
+    NullConstant(usize),
+
+    #[error("More than {limit} operands at offset {offset}")]
+    TooManyOperands { limit: usize, offset: usize },
+
+    #[error("Parsing took longer than {0} ms")]
+    Timeout(u64),
+
+    #[error("Operator {op} is not allowed, at offset {offset}")]
+    DisallowedOperator { op: &'static str, offset: usize },
+
+    #[error("Reversed comparison at offset {0}")]
+    ReversedComparison(usize),
+
+    #[error("Operator {op} expects {expected} on the right, at offset {offset}")]
+    OperatorArityMismatch {
+        op: &'static str,
+        expected: &'static str,
+        offset: usize,
+    },
+
+    #[error("Operator {op} cannot have the object path on the right, at offset {offset}")]
+    NotSwappable { op: &'static str, offset: usize },
+
+    #[error("EXISTS cannot be negated, at offset {0}")]
+    NegatedExists(usize),
+
+    #[error("Comment at offset {0}")]
+    Comment(usize),
+
+    #[error("Double-quoted string at offset {0}")]
+    DoubleQuotes(usize),
+
+    #[error("BETWEEN at offset {0}")]
+    Between(usize),
+
+    #[error("Range at offset {0}")]
+    Range(usize),
+
+    #[error("NOT before a parenthesized expression at offset {0}")]
+    GroupedNegation(usize),
+
+    #[error(
+        "Range {0} must be ascending, and the ranges of a list hold at most {MAX_RANGE_LEN} values"
+    )]
+    InvalidRange(String),
+
+    #[error("Float with an exponent at offset {0}")]
+    Exponent(usize),
+
+    #[error("Empty list at offset {0}")]
+    EmptyList(usize),
+
+    #[error("Mixed list: element {index} is {found}, expected {expected}")]
+    MixedList {
+        index: usize,
+        found: &'static str,
+        expected: &'static str,
+    },
+}
+
+impl From<pest::error::Error<Rule>> for ParseError {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        Self::Grammar(Box::new(err))
+    }
 }
 
 pub type Result<T> = std::result::Result<T, ParseError>;
 
+/// A grammar error as the message followed by the offending line with a
+/// caret under the failure, like rustc:
+///
+/// ```text
+/// Grammar error at 1:5: expected path
+///   |
+/// 1 | [b:y
+///   |     ^
+/// ```
+fn render_grammar_error(err: &pest::error::Error<Rule>) -> String {
+    use pest::error::LineColLocation;
+
+    let ((line, col), width) = match err.line_col {
+        LineColLocation::Pos(pos) => (pos, 1),
+        LineColLocation::Span(start, end) if start.0 == end.0 => {
+            (start, end.1.saturating_sub(start.1).max(1))
+        }
+        LineColLocation::Span(start, _) => (start, 1),
+    };
+    let number = line.to_string();
+    let gutter = " ".repeat(number.len());
+    format!(
+        "Grammar error at {line}:{col}: {message}\n\
+         {gutter} |\n\
+         {number} | {source}\n\
+         {gutter} | {pad}{carets}",
+        message = err.variant.message(),
+        source = err.line().trim_end(),
+        pad = " ".repeat(col - 1),
+        carets = "^".repeat(width),
+    )
+}
+
+/// Options controlling which non-fatal constructs the parser accepts.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, get_all, module = "stix_patterns_parser")
+)]
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Accept `IN ()`, reporting a warning instead of `ParseError::EmptyList`.
+    pub allow_empty_lists: bool,
+    /// Accept lists whose elements have different types. Ints and floats
+    /// are always allowed together.
+    pub allow_mixed_lists: bool,
+    /// Compile every `MATCHES` operand and reject invalid regular
+    /// expressions. Requires the `regex` feature; the `regex` crate's syntax
+    /// is close to, but not identical to, PCRE.
+    pub validate_regex: bool,
+    /// Accept keywords such as `and`, `like` or `within` in any case.
+    pub case_insensitive_keywords: bool,
+    /// Accept an object path on the right-hand side of a comparison, as in
+    /// `a:x = b:y`, reporting `ParseError::PathRhs` otherwise.
+    pub allow_path_rhs: bool,
+    /// Maximum number of comparisons in one observation and of
+    /// observations in the whole pattern, reporting
+    /// `ParseError::TooManyOperands` beyond it. Unlimited when `None`.
+    pub max_operands: Option<usize>,
+    /// Give up with `ParseError::Timeout` once parsing has taken longer
+    /// than this many milliseconds, as a last resort against pathological
+    /// input. The time is checked when the grammar has run and at each
+    /// observation and comparison. Unlimited when `None`.
+    pub timeout_ms: Option<u64>,
+    /// Reject comparisons whose operator is not in the set, reporting
+    /// `ParseError::DisallowedOperator`, e.g. to keep `MATCHES` out of a
+    /// constrained query surface. `EXISTS` is always allowed and `BETWEEN`
+    /// needs `>=` and `<=`. Unrestricted when `None`.
+    pub allowed_operators: Option<HashSet<ComparisonOp>>,
+    /// Accept the non-standard `null` constant, as in `file:size = null`,
+    /// reporting `ParseError::NullConstant` otherwise.
+    pub allow_null: bool,
+    /// Lowercase object types, reporting a warning with the original
+    /// spelling for each one changed.
+    pub normalize_object_types: bool,
+    /// Rewrite the keys of `hashes` dictionaries to the spelling of the
+    /// STIX hash algorithm vocabulary, as in `file:hashes.sha256` to
+    /// `file:hashes.'SHA-256'`, reporting a warning with the original
+    /// spelling for each one changed. Unknown algorithms are kept as
+    /// written.
+    pub normalize_hash_names: bool,
+    /// Accept a single comparison expression without the enclosing
+    /// brackets, as in `file:name = 'x'`. It parses into the same
+    /// observation as `[file:name = 'x']`, with a warning.
+    pub allow_bare_observation: bool,
+    /// Accept the reversed form `value op path`, as in `'x' = file:name`,
+    /// canonicalized to `path op value` with ordering operators swapped and
+    /// a warning. Reports `ParseError::ReversedComparison` otherwise.
+    pub allow_reversed_comparison: bool,
+    /// Accept `/* ... */` comments wherever whitespace is allowed,
+    /// reporting `ParseError::Comment` otherwise. They are dropped from the
+    /// AST; `find_comments` returns their text.
+    pub allow_comments: bool,
+    /// Share the storage of object types and property names across every
+    /// pattern parsed with this option, through a process-wide pool. Saves
+    /// memory on large corpora whose patterns use the same names. The pool
+    /// drops names no parsed pattern uses any more as it grows;
+    /// `clear_intern_pool` empties it.
+    pub intern_strings: bool,
+    /// Accept double-quoted strings, as in `file:name = "x"`, which parse
+    /// to the same value as `'x'` and are written single-quoted. Reports
+    /// `ParseError::DoubleQuotes` otherwise.
+    pub allow_double_quotes: bool,
+    /// Accept the range shorthand `path BETWEEN low AND high`, parsed as
+    /// `(path >= low AND path <= high)` with a warning, so it is written in
+    /// that form. Reports `ParseError::Between` otherwise.
+    pub allow_between: bool,
+    /// Accept integer ranges in lists, as in `IN (1..3, 7)`, which some
+    /// dialects write. Both ends are included and the ranges of a list may
+    /// hold up to 1024 values in all. The list is parsed as its enumeration
+    /// `(1, 2, 3, 7)` with a warning, so it is written in that form and
+    /// consumers only see standard lists; `Comparison::has_ranges` records
+    /// that it was written with ranges. Reports `ParseError::Range`
+    /// otherwise.
+    pub allow_ranges: bool,
+    /// Accept `NOT` before a parenthesized comparison expression, as in
+    /// `NOT (a:x = 1 AND a:y = 2)`, which STIX does not define. The group
+    /// keeps its negation, as `CompositeComparison::negated`, so it is
+    /// written back in that form; `push_negations` rewrites it into
+    /// standard STIX. Reports `ParseError::GroupedNegation` otherwise.
+    pub allow_grouped_negation: bool,
+    /// Accept float constants with an exponent, as in `1.5e20`, which STIX
+    /// does not define. Reports `ParseError::Exponent` otherwise.
+    pub allow_exponent: bool,
+    /// Enforce the STIX 2.1 rules the parser is otherwise lenient about.
+    /// The `allow_*` extensions stay available, but `allow_empty_lists` and
+    /// `allow_exponent` are overridden as noted below:
+    ///
+    /// - timestamps must have a time zone, as in `t'2023-01-01T00:00:00Z'`,
+    ///   reporting `ParseError::TimestampWithoutZone`;
+    /// - lists must not be empty, reporting `ParseError::EmptyList` even
+    ///   with `allow_empty_lists`;
+    /// - floats must not have an exponent, reporting `ParseError::Exponent`
+    ///   even with `allow_exponent`;
+    /// - hex constants must have an even number of digits and binary
+    ///   constants must be padded base64, reporting `ParseError::InvalidHex`
+    ///   and `ParseError::InvalidBase64`;
+    /// - `MATCHES` operands must be valid regular expressions, as with
+    ///   `validate_regex`, when the `regex` feature is enabled; they are
+    ///   not checked without it;
+    /// - `REPEATS` counts must be positive, reporting
+    ///   `ParseError::NonPositiveRepeat`;
+    /// - `START` must be before `STOP`, reporting
+    ///   `ParseError::EmptyInterval`;
+    /// - the `ISSUBSET` and `ISSUPERSET` operands of `ipv4-addr` and
+    ///   `ipv6-addr` paths must be addresses or CIDR ranges of that IP
+    ///   version, reporting `ParseError::InvalidCidr` instead of a warning.
+    pub strict: bool,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ParseOptions {
+    #[new]
+    #[pyo3(signature = (
+        *,
+        allow_empty_lists = false,
+        allow_mixed_lists = false,
+        validate_regex = false,
+        case_insensitive_keywords = false,
+        allow_path_rhs = false,
+        max_operands = None,
+        timeout_ms = None,
+        allowed_operators = None,
+        allow_null = false,
+        normalize_object_types = false,
+        normalize_hash_names = false,
+        allow_bare_observation = false,
+        allow_reversed_comparison = false,
+        allow_comments = false,
+        intern_strings = false,
+        allow_double_quotes = false,
+        allow_between = false,
+        allow_ranges = false,
+        allow_grouped_negation = false,
+        allow_exponent = false,
+        strict = false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        allow_empty_lists: bool,
+        allow_mixed_lists: bool,
+        validate_regex: bool,
+        case_insensitive_keywords: bool,
+        allow_path_rhs: bool,
+        max_operands: Option<usize>,
+        timeout_ms: Option<u64>,
+        allowed_operators: Option<HashSet<ComparisonOp>>,
+        allow_null: bool,
+        normalize_object_types: bool,
+        normalize_hash_names: bool,
+        allow_bare_observation: bool,
+        allow_reversed_comparison: bool,
+        allow_comments: bool,
+        intern_strings: bool,
+        allow_double_quotes: bool,
+        allow_between: bool,
+        allow_ranges: bool,
+        allow_grouped_negation: bool,
+        allow_exponent: bool,
+        strict: bool,
+    ) -> Self {
+        Self {
+            allow_empty_lists,
+            allow_mixed_lists,
+            validate_regex,
+            case_insensitive_keywords,
+            allow_path_rhs,
+            max_operands,
+            timeout_ms,
+            allowed_operators,
+            allow_null,
+            normalize_object_types,
+            normalize_hash_names,
+            allow_bare_observation,
+            allow_reversed_comparison,
+            allow_comments,
+            intern_strings,
+            allow_double_quotes,
+            allow_between,
+            allow_ranges,
+            allow_grouped_negation,
+            allow_exponent,
+            strict,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ParseOptions(allow_empty_lists={}, allow_mixed_lists={}, validate_regex={}, \
+             case_insensitive_keywords={}, allow_path_rhs={}, max_operands={}, \
+             timeout_ms={}, allowed_operators={}, allow_null={}, normalize_object_types={}, \
+             normalize_hash_names={}, allow_bare_observation={}, allow_reversed_comparison={}, \
+             allow_comments={}, intern_strings={}, allow_double_quotes={}, allow_between={}, \
+             allow_ranges={}, allow_grouped_negation={}, allow_exponent={}, strict={})",
+            self.allow_empty_lists,
+            self.allow_mixed_lists,
+            self.validate_regex,
+            self.case_insensitive_keywords,
+            self.allow_path_rhs,
+            self.max_operands
+                .map_or_else(|| "None".to_owned(), |n| n.to_string()),
+            self.timeout_ms
+                .map_or_else(|| "None".to_owned(), |n| n.to_string()),
+            self.allowed_operators.as_ref().map_or_else(
+                || "None".to_owned(),
+                |ops| {
+                    let mut ops: Vec<_> = ops.iter().collect();
+                    ops.sort_by_key(|op| op.code());
+                    let ops: Vec<_> = ops.iter().map(|op| op.__repr__()).collect();
+                    format!("{{{}}}", ops.join(", "))
+                }
+            ),
+            self.allow_null,
+            self.normalize_object_types,
+            self.normalize_hash_names,
+            self.allow_bare_observation,
+            self.allow_reversed_comparison,
+            self.allow_comments,
+            self.intern_strings,
+            self.allow_double_quotes,
+            self.allow_between,
+            self.allow_ranges,
+            self.allow_grouped_negation,
+            self.allow_exponent,
+            self.strict
+        )
+    }
+}
+
+/// Timing and size figures for a single parse, for profiling.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, get_all, module = "stix_patterns_parser")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParseStats {
+    /// Wall-clock time spent running the grammar and building the AST.
+    pub parse_duration_us: u64,
+    /// Number of pest pairs in the parse tree.
+    pub node_count: usize,
+    pub input_bytes: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ParseStats {
+    fn __repr__(&self) -> String {
+        format!(
+            "ParseStats(parse_duration_us={}, node_count={}, input_bytes={})",
+            self.parse_duration_us, self.node_count, self.input_bytes
+        )
+    }
+}
+
+/// A `/* ... */` comment in a pattern.
+///
+/// `text` is the trimmed comment body; `start` and `end` are the byte
+/// offsets of the whole comment, delimiters included.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, get_all, module = "stix_patterns_parser")
+)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PatternComment {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PatternComment {
+    fn __repr__(&self) -> String {
+        format!(
+            "PatternComment(text={:?}, start={}, end={})",
+            self.text, self.start, self.end
+        )
+    }
+}
+
+/// The comments of a pattern accepted with `allow_comments`, in source
+/// order.
+///
+/// The grammar skips comments silently, so they are found by scanning the
+/// input outside of quoted constants and properties.
+#[must_use]
+pub fn find_comments(input: &str) -> Vec<PatternComment> {
+    let mut comments = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    let mut quote = None;
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote.is_some() => {
+                chars.next();
+            }
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            c if quote == Some(c) => quote = None,
+            '/' if quote.is_none() && input[i + 1..].starts_with('*') => {
+                let body = i + 2;
+                let (text_end, end) = match input[body..].find("*/") {
+                    Some(n) => (body + n, body + n + 2),
+                    None => (input.len(), input.len()),
+                };
+                comments.push(PatternComment {
+                    text: input[body..text_end].trim().to_owned(),
+                    start: i,
+                    end,
+                });
+                while chars.next_if(|&(j, _)| j < end).is_some() {}
+            }
+            _ => {}
+        }
+    }
+    comments
+}
+
+/// State shared by the conversion functions while walking the parse tree.
+/// Names interned by `intern_strings`, shared across parses.
+#[derive(Default)]
+struct InternPool {
+    names: HashSet<Arc<str>>,
+    /// Pool size at which names no pattern uses any more are dropped.
+    prune_at: usize,
+}
+
+static INTERN_POOL: LazyLock<Mutex<InternPool>> = LazyLock::new(Mutex::default);
+
+/// Smallest pool size that triggers a prune, so small pools are not
+/// scanned on every new name.
+const MIN_PRUNE_AT: usize = 1024;
+
+/// Empties the pool behind `intern_strings`.
+///
+/// The pool already drops names once it has doubled and no parsed pattern
+/// uses them, so this only matters to release its memory at once. Patterns
+/// parsed earlier keep their names; later parses no longer share them.
+pub fn clear_intern_pool() {
+    let mut pool = INTERN_POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    *pool = InternPool::default();
+}
+
+struct Context<'o> {
+    options: &'o ParseOptions,
+    warnings: Vec<ParseWarning>,
+    /// Comparisons seen in the current observation.
+    comparisons: usize,
+    /// Observations seen in the pattern.
+    observations: usize,
+    /// When `timeout_ms` runs out, counted from the start of the parse.
+    deadline: Option<Instant>,
+}
+
+impl<'o> Context<'o> {
+    /// A context for a parse that began at `started`, failing if the
+    /// grammar alone took longer than `timeout_ms`.
+    fn new(options: &'o ParseOptions, started: Instant) -> Result<Self> {
+        let cx = Self {
+            options,
+            warnings: Vec::new(),
+            comparisons: 0,
+            observations: 0,
+            deadline: options
+                .timeout_ms
+                .map(|ms| started + Duration::from_millis(ms)),
+        };
+        cx.check_deadline()?;
+        Ok(cx)
+    }
+
+    fn check_deadline(&self) -> Result<()> {
+        match (self.deadline, self.options.timeout_ms) {
+            (Some(deadline), Some(ms)) if Instant::now() > deadline => Err(ParseError::Timeout(ms)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Object types and property names, shared with earlier parses when
+    /// `intern_strings` is set.
+    fn intern(&self, name: &str) -> Arc<str> {
+        if !self.options.intern_strings {
+            return name.into();
+        }
+        let mut pool = INTERN_POOL.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(interned) = pool.names.get(name) {
+            return Arc::clone(interned);
+        }
+        if pool.names.len() >= pool.prune_at {
+            // Only the pool still holds names whose patterns were dropped.
+            pool.names
+                .retain(|interned| Arc::strong_count(interned) > 1);
+            pool.prune_at = (pool.names.len() * 2).max(MIN_PRUNE_AT);
+        }
+        let interned: Arc<str> = name.into();
+        pool.names.insert(Arc::clone(&interned));
+        interned
+    }
+
+    fn start_observation(&mut self, span: Span<'_>) -> Result<()> {
+        self.comparisons = 0;
+        self.observations += 1;
+        self.check_deadline()?;
+        self.check_operands(self.observations, span)
+    }
+
+    fn add_comparison(&mut self, span: Span<'_>) -> Result<()> {
+        self.comparisons += 1;
+        self.check_deadline()?;
+        self.check_operands(self.comparisons, span)
+    }
+
+    fn check_operands(&self, count: usize, span: Span<'_>) -> Result<()> {
+        match self.options.max_operands {
+            Some(limit) if count > limit => Err(ParseError::TooManyOperands {
+                limit,
+                offset: span.start(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_operator(&self, op: ComparisonOp, offset: usize) -> Result<()> {
+        match &self.options.allowed_operators {
+            Some(allowed) if !allowed.contains(&op) => Err(ParseError::DisallowedOperator {
+                op: op.value(),
+                offset,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn warn(&mut self, message: impl Into<String>, span: Span<'_>) {
+        self.warnings.push(ParseWarning::new(message, span));
+    }
+}
+
 /// Parse a STIX pattern string into a PatternExpr AST.
 pub fn parse_pattern(input: &str) -> Result<PatternExpr> {
-    let pair = StixParser::parse(Rule::pattern, input)?
+    parse_pattern_with_options(input, &ParseOptions::default())
+}
+
+/// Parse a STIX pattern string into a PatternExpr AST using the given options.
+pub fn parse_pattern_with_options(input: &str, options: &ParseOptions) -> Result<PatternExpr> {
+    parse_pattern_with_diagnostics(input, options).map(|(expr, _)| expr)
+}
+
+/// Parse a STIX pattern string, also returning the non-fatal warnings found.
+pub fn parse_pattern_with_diagnostics(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(PatternExpr, Vec<ParseWarning>)> {
+    let started = Instant::now();
+    build_pattern(parse_tree(input, options)?, options, started)
+}
+
+/// Parse a comparison expression without brackets, such as
+/// `file:size > 100 AND file:name LIKE 'a%'`, as it would appear inside an
+/// observation.
+pub fn parse_comparison_expr(input: &str) -> Result<ComparisonExpr> {
+    parse_comparison_expr_with_options(input, &ParseOptions::default())
+}
+
+/// Parse a comparison expression without brackets using the given options.
+pub fn parse_comparison_expr_with_options(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<ComparisonExpr> {
+    let started = Instant::now();
+    let pair = StixParser::parse(Rule::comparison_pattern, input)?
+        .next()
+        .ok_or(ParseError::MissingElement("comparison expression"))?;
+    check_tree(&pair, options)?;
+
+    let mut cx = Context::new(options, started)?;
+    cx.start_observation(pair.as_span())?;
+    parse_comparisons(&mut cx, pair.into_inner(), &mut Vec::new())
+}
+
+/// Parse a STIX pattern string, also returning how long parsing took and
+/// how large the parse tree was.
+pub fn parse_pattern_with_stats(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(PatternExpr, ParseStats)> {
+    let started = Instant::now();
+    let pair = parse_tree(input, options)?;
+    let tree = pair.clone();
+    let (expr, _) = build_pattern(pair, options, started)?;
+    let parse_duration_us = u64::try_from(started.elapsed().as_micros()).unwrap_or(u64::MAX);
+
+    let stats = ParseStats {
+        parse_duration_us,
+        node_count: tree.into_inner().flatten().count() + 1,
+        input_bytes: input.len(),
+    };
+    Ok((expr, stats))
+}
+
+/// Parse a STIX pattern string, recovering from errors at top-level
+/// observation boundaries to report several at once.
+///
+/// The observations, or parenthesized groups, joined by the outermost
+/// operators are parsed separately, each error is collected, and those that
+/// parse are joined by the operator preceding them into a partial AST. Error
+/// offsets refer to `input`. Without recovery, e.g. for an unbalanced
+/// bracket, the result is no AST and the single error of a normal parse.
+pub fn parse_pattern_recover(
+    input: &str,
+    options: &ParseOptions,
+) -> (Option<PatternExpr>, Vec<ParseError>) {
+    let err = match parse_pattern_with_options(input, options) {
+        Ok(expr) => return (Some(expr), Vec::new()),
+        Err(err) => err,
+    };
+    let segments = top_level_segments(input);
+    if segments.len() < 2 {
+        return (None, vec![err]);
+    }
+
+    let mut chain = OperandChain::default();
+    let mut errors = Vec::new();
+    for (op, range) in segments {
+        match parse_pattern_with_options(&mask_outside(input, range.clone()), options) {
+            Ok(operand) => {
+                if let (false, Some(op)) = (chain.is_empty(), op) {
+                    chain.push_op(op);
+                }
+                chain.push_operand(operand, range.start, range.end);
+            }
+            Err(err) => errors.push(unmask_error(err, input)),
+        }
+    }
+    let expr = chain.finish();
+    if errors.is_empty() {
+        // The operands are fine on their own, e.g. a limit was exceeded.
+        return (None, vec![err]);
+    }
+    (expr, errors)
+}
+
+/// Parse a file holding one pattern per line: [`parse_lines`] on the file's
+/// contents. Only reading the file fails as a whole; a pattern that does
+/// not parse gives an error in its line's result.
+pub fn parse_file(
+    path: impl AsRef<Path>,
+    options: &ParseOptions,
+) -> std::io::Result<Vec<(usize, Result<PatternExpr>)>> {
+    let input = std::fs::read_to_string(path)?;
+    Ok(parse_lines(&input, options))
+}
+
+/// Parse each line of `input` as a pattern, skipping blank lines and lines
+/// starting with `#`. Each result comes with its line number, counted from
+/// 1, so errors can be traced back to their line.
+///
+/// Each line is parsed on its own, so the positions inside an error, such
+/// as the `1:8` of a grammar error or an offset, are relative to its line;
+/// only the line number in the result counts from the start of `input`.
+#[must_use]
+pub fn parse_lines(input: &str, options: &ParseOptions) -> Vec<(usize, Result<PatternExpr>)> {
+    let input = input.strip_prefix('\u{FEFF}').unwrap_or(input);
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(i, line)| (i + 1, parse_pattern_with_options(line, options)))
+        .collect()
+}
+
+/// Split `input` at the observation operators outside of brackets,
+/// parentheses and quotes, pairing each part with the operator before it.
+fn top_level_segments(input: &str) -> Vec<(Option<ObservationOp>, std::ops::Range<usize>)> {
+    let mut segments = Vec::new();
+    let mut op = None;
+    let mut segment_start = 0;
+    let mut depth = 0_usize;
+    let mut quote = None;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote.is_some() => {
+                chars.next();
+            }
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            c if quote == Some(c) => quote = None,
+            _ if quote.is_some() => {}
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = depth.saturating_sub(1),
+            c if c.is_ascii_alphabetic() => {
+                let mut end = i + 1;
+                while let Some((j, _)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric()) {
+                    end = j + 1;
+                }
+                let word = &input[i..end];
+                let word_op = if word.eq_ignore_ascii_case("AND") {
+                    Some(ObservationOp::And)
+                } else if word.eq_ignore_ascii_case("OR") {
+                    Some(ObservationOp::Or)
+                } else if word.eq_ignore_ascii_case("FOLLOWEDBY") {
+                    Some(ObservationOp::FollowedBy)
+                } else {
+                    None
+                };
+                if depth == 0
+                    && let Some(word_op) = word_op
+                {
+                    segments.push((op, segment_start..i));
+                    op = Some(word_op);
+                    segment_start = end;
+                }
+            }
+            _ => {}
+        }
+    }
+    segments.push((op, segment_start..input.len()));
+    segments
+}
+
+/// A grammar error from a masked copy of `input`, pointing at the original
+/// line instead of its blanked copy.
+fn unmask_error(err: ParseError, input: &str) -> ParseError {
+    use pest::error::{Error, InputLocation};
+
+    let ParseError::Grammar(err) = err else {
+        return err;
+    };
+    let unmasked =
+        match err.location {
+            InputLocation::Pos(pos) => pest::Position::new(input, pos)
+                .map(|pos| Error::new_from_pos(err.variant.clone(), pos)),
+            InputLocation::Span((start, end)) => Span::new(input, start, end)
+                .map(|span| Error::new_from_span(err.variant.clone(), span)),
+        };
+    unmasked.map_or(ParseError::Grammar(err), |err| {
+        ParseError::Grammar(Box::new(err))
+    })
+}
+
+/// `input` with everything outside `range` blanked, keeping line breaks and
+/// byte offsets so errors point into the original input.
+fn mask_outside(input: &str, range: std::ops::Range<usize>) -> String {
+    input
+        .char_indices()
+        .map(|(i, c)| {
+            if range.contains(&i) || c == '\n' {
+                c.to_string()
+            } else {
+                " ".repeat(c.len_utf8())
+            }
+        })
+        .collect()
+}
+
+fn parse_tree<'i>(input: &'i str, options: &ParseOptions) -> Result<Pair<'i, Rule>> {
+    let rule = if options.allow_bare_observation {
+        Rule::lenient_pattern
+    } else {
+        Rule::pattern
+    };
+    StixParser::parse(rule, input)?
         .next()
-        .ok_or(ParseError::MissingElement("pattern"))?;
+        .ok_or(ParseError::MissingElement("pattern"))
+}
+
+fn build_pattern(
+    pair: Pair<'_, Rule>,
+    options: &ParseOptions,
+    started: Instant,
+) -> Result<(PatternExpr, Vec<ParseWarning>)> {
+    check_tree(&pair, options)?;
+
+    let mut cx = Context::new(options, started)?;
+    let expr = parse_pair(&mut cx, pair)?;
+    Ok((expr, cx.warnings))
+}
+
+/// Reject the keyword spellings and comments the grammar accepts but
+/// `options` do not.
+fn check_tree(pair: &Pair<'_, Rule>, options: &ParseOptions) -> Result<()> {
+    if !options.case_insensitive_keywords {
+        check_keyword_case(pair)?;
+    }
+    if !options.allow_comments
+        && let Some(comment) = find_comments(pair.as_str()).first()
+    {
+        return Err(ParseError::Comment(comment.start));
+    }
+    Ok(())
+}
 
-    parse_pair(pair)
+/// The grammar matches keywords in any case; strict parsing only accepts
+/// the canonical uppercase spelling.
+fn check_keyword_case(pair: &Pair<Rule>) -> Result<()> {
+    for p in pair.clone().into_inner().flatten() {
+        let is_keyword = matches!(
+            p.as_rule(),
+            Rule::r#in
+                | Rule::like
+                | Rule::r#match
+                | Rule::issubset
+                | Rule::issuperset
+                | Rule::exists
+                | Rule::between
+                | Rule::and
+                | Rule::or
+                | Rule::not
+                | Rule::followedby
+                | Rule::kw_repeats
+                | Rule::kw_times
+                | Rule::kw_within
+                | Rule::kw_seconds
+                | Rule::kw_start
+                | Rule::kw_stop
+        );
+        if is_keyword && p.as_str() != p.as_str().to_ascii_uppercase() {
+            return Err(ParseError::KeywordCase(p.as_str().to_owned()));
+        }
+    }
+    Ok(())
 }
 
 /// Main recursive dispatch based on rule type.
-fn parse_pair(pair: Pair<Rule>) -> Result<PatternExpr> {
+fn parse_pair(cx: &mut Context, pair: Pair<Rule>) -> Result<PatternExpr> {
     match pair.as_rule() {
-        Rule::pattern => parse_pattern_rule(pair),
-        Rule::expression => parse_expression(pair),
-        Rule::observation => parse_observation(pair),
-        Rule::observation_group => parse_observation_group(pair),
+        Rule::pattern | Rule::lenient_pattern => parse_pattern_rule(cx, pair),
+        Rule::expression => parse_expression(cx, pair),
+        Rule::observation => parse_observation(cx, pair),
+        Rule::bare_observation => {
+            cx.warn("observation without brackets", pair.as_span());
+            parse_observation(cx, pair)
+        }
         _ => Err(ParseError::UnexpectedRule(pair.as_rule())),
     }
 }
 
-fn parse_pattern_rule(pair: Pair<Rule>) -> Result<PatternExpr> {
+fn parse_pattern_rule(cx: &mut Context, pair: Pair<Rule>) -> Result<PatternExpr> {
     pair.into_inner()
-        .find(|p| p.as_rule() == Rule::expression)
-        .map(parse_expression)
+        .find(|p| matches!(p.as_rule(), Rule::expression | Rule::bare_observation))
+        .map(|p| parse_pair(cx, p))
         .ok_or(ParseError::MissingElement("expression"))?
 }
 
-fn parse_expression(pair: Pair<Rule>) -> Result<PatternExpr> {
-    let mut inner = pair.into_inner();
+/// Operands joined by binary operators, combined as they are read by
+/// precedence climbing: an operand is joined to the one before it once an
+/// operator binding no tighter follows, or at the end. Operands move into
+/// the new nodes without being copied, so a chain of n operands builds in
+/// O(n).
+struct OperandChain<E: ChainOperand> {
+    /// Operands not joined yet, with their start and end offsets.
+    operands: Vec<(E, usize, usize)>,
+    /// The operators between `operands`, each binding tighter than the
+    /// one before it.
+    ops: Vec<E::Op>,
+}
 
-    let first = inner
-        .next()
-        .ok_or(ParseError::MissingElement("expression"))?;
-    let mut left = parse_pair(first)?;
+/// A node that [`OperandChain`] can join with an operator.
+trait ChainOperand: Sized {
+    type Op: Copy;
+
+    fn precedence(op: Self::Op) -> u8;
+
+    fn join(left: Self, op: Self::Op, right: Self, span: SourceSpan) -> Self;
+}
 
-    while let Some(op_pair) = inner.next() {
-        let op = parse_obs_op(&op_pair)?;
-        let right_pair = inner
-            .next()
-            .ok_or(ParseError::MissingElement("right operand"))?;
-        let right = parse_pair(right_pair)?;
-        left = CompositePattern::new(left, op, right).into();
+impl ChainOperand for PatternExpr {
+    type Op = ObservationOp;
+
+    fn precedence(op: ObservationOp) -> u8 {
+        op.precedence()
     }
 
-    Ok(left)
+    fn join(left: Self, op: ObservationOp, right: Self, span: SourceSpan) -> Self {
+        CompositePattern::new(left, op, right)
+            .with_span(span)
+            .into()
+    }
 }
 
-fn parse_observation(pair: Pair<Rule>) -> Result<PatternExpr> {
-    let mut expr: Option<ComparisonExpr> = None;
-    let mut pending_op: Option<BooleanOp> = None;
-    let mut qualifiers = Qualifiers::default();
+impl ChainOperand for ComparisonExpr {
+    type Op = BooleanOp;
 
-    for p in pair.into_inner() {
-        match p.as_rule() {
-            Rule::comparison => {
-                let comp = parse_comparison(p)?;
-                expr = Some(merge_exprs(expr, comp, pending_op.take()));
+    fn precedence(op: BooleanOp) -> u8 {
+        op.precedence()
+    }
+
+    fn join(left: Self, op: BooleanOp, right: Self, span: SourceSpan) -> Self {
+        CompositeComparison::new(left, op, right)
+            .with_span(span)
+            .into()
+    }
+}
+
+impl<E: ChainOperand> Default for OperandChain<E> {
+    fn default() -> Self {
+        Self {
+            operands: Vec::new(),
+            ops: Vec::new(),
+        }
+    }
+}
+
+impl<E: ChainOperand> OperandChain<E> {
+    fn is_empty(&self) -> bool {
+        self.operands.is_empty()
+    }
+
+    fn push_operand(&mut self, expr: E, start: usize, end: usize) {
+        self.operands.push((expr, start, end));
+    }
+
+    fn push_op(&mut self, op: E::Op) {
+        while self
+            .ops
+            .last()
+            .is_some_and(|&top| E::precedence(top) >= E::precedence(op))
+        {
+            self.join_last();
+        }
+        self.ops.push(op);
+    }
+
+    fn finish(mut self) -> Option<E> {
+        while !self.ops.is_empty() {
+            self.join_last();
+        }
+        self.operands.pop().map(|(expr, _, _)| expr)
+    }
+
+    fn join_last(&mut self) {
+        let op = self.ops.pop().expect("an operator is pending");
+        let (right, _, end) = self
+            .operands
+            .pop()
+            .expect("an operator has a right operand");
+        let (left, start, _) = self.operands.pop().expect("an operator has a left operand");
+        let joined = E::join(left, op, right, SourceSpan::new(start, end));
+        self.operands.push((joined, start, end));
+    }
+}
+
+/// An `expression` being parsed, a chain of observations read from `pairs`.
+struct ExpressionFrame<'i> {
+    pairs: Pairs<'i, Rule>,
+    chain: OperandChain<PatternExpr>,
+    /// The start and end of the operand being parsed.
+    operand: (usize, usize),
+    /// For the expression of an `observation_group`, its qualifiers and
+    /// start.
+    group: Option<(Vec<Pair<'i, Rule>>, usize)>,
+}
+
+impl<'i> ExpressionFrame<'i> {
+    fn new(pair: Pair<'i, Rule>, group: Option<(Vec<Pair<'i, Rule>>, usize)>) -> Self {
+        Self {
+            pairs: pair.into_inner(),
+            chain: OperandChain::default(),
+            operand: (0, 0),
+            group,
+        }
+    }
+
+    fn push_operand(&mut self, operand: PatternExpr) {
+        let (start, end) = self.operand;
+        self.chain.push_operand(operand, start, end);
+    }
+}
+
+/// Parse an `expression`, with an explicit stack instead of recursion for
+/// parenthesized groups. Nesting depth is then only limited by the pest
+/// parser, which is recursive and needs less stack per level.
+fn parse_expression(cx: &mut Context, pair: Pair<Rule>) -> Result<PatternExpr> {
+    // Most patterns are a single observation, which needs no frames.
+    let mut operands = pair.clone().into_inner();
+    if let (Some(only), None) = (operands.next(), operands.next())
+        && only.as_rule() == Rule::observation
+    {
+        return parse_observation(cx, only);
+    }
+
+    let mut stack = vec![ExpressionFrame::new(pair, None)];
+
+    loop {
+        let frame = stack
+            .last_mut()
+            .expect("the stack holds the outer expression");
+        let operand = if frame.chain.is_empty() {
+            let first = frame
+                .pairs
+                .next()
+                .ok_or(ParseError::MissingElement("expression"))?;
+            Some(first)
+        } else if let Some(op_pair) = frame.pairs.next() {
+            frame.chain.push_op(parse_obs_op(&op_pair)?);
+            let right = frame
+                .pairs
+                .next()
+                .ok_or(ParseError::MissingElement("right operand"))?;
+            Some(right)
+        } else {
+            None
+        };
+
+        let Some(operand) = operand else {
+            let frame = stack.pop().expect("the stack holds the current frame");
+            let mut pattern = frame
+                .chain
+                .finish()
+                .expect("a finished frame has an operand");
+            if let Some((qualifiers, start)) = frame.group {
+                if let PatternExpr::Composite(c) = &mut pattern {
+                    c.parenthesized = true;
+                }
+                pattern = apply_qualifiers(cx, pattern, qualifiers, start)?;
             }
-            Rule::and => pending_op = Some(BooleanOp::And),
-            Rule::or => pending_op = Some(BooleanOp::Or),
-            Rule::qualifier => parse_qualifier(p, &mut qualifiers)?,
-            _ => {}
+            match stack.last_mut() {
+                Some(parent) => parent.push_operand(pattern),
+                None => return Ok(pattern),
+            }
+            continue;
+        };
+        frame.operand = (operand.as_span().start(), operand.as_span().end());
+
+        if operand.as_rule() == Rule::observation_group {
+            let start = operand.as_span().start();
+            let mut expression = None;
+            let mut qualifiers = Vec::new();
+            for p in operand.into_inner() {
+                match p.as_rule() {
+                    Rule::expression => expression = Some(p),
+                    Rule::qualifier => qualifiers.push(p),
+                    _ => {}
+                }
+            }
+            let expression = expression.ok_or(ParseError::MissingElement("expression"))?;
+            stack.push(ExpressionFrame::new(expression, Some((qualifiers, start))));
+        } else {
+            let pattern = parse_pair(cx, operand)?;
+            stack
+                .last_mut()
+                .expect("the stack holds the current frame")
+                .push_operand(pattern);
         }
     }
+}
+
+fn parse_observation(cx: &mut Context, pair: Pair<Rule>) -> Result<PatternExpr> {
+    let mut qualifiers = Vec::new();
+    let start = pair.as_span().start();
+    cx.start_observation(pair.as_span())?;
 
-    let pattern: PatternExpr = expr.ok_or(ParseError::MissingElement("comparison"))?.into();
-    Ok(qualifiers.apply_to(pattern))
+    // Likewise for an observation of one comparison without qualifiers.
+    let mut inner = pair.clone().into_inner();
+    if let (Some(only), None) = (inner.next(), inner.next())
+        && only.as_rule() == Rule::comparison
+        && !is_comparison_group(&only)
+    {
+        return Ok(parse_comparison(cx, only)?.into());
+    }
+
+    let pattern: PatternExpr = parse_comparisons(cx, pair.into_inner(), &mut qualifiers)?.into();
+    apply_qualifiers(cx, pattern, qualifiers, start)
 }
 
-fn parse_observation_group(pair: Pair<Rule>) -> Result<PatternExpr> {
-    let mut inner_pattern: Option<PatternExpr> = None;
-    let mut qualifiers = Qualifiers::default();
+/// A chain of comparisons being parsed, joined by `AND`/`OR`.
+struct ComparisonFrame<'i> {
+    pairs: Pairs<'i, Rule>,
+    chain: OperandChain<ComparisonExpr>,
+    /// For a parenthesized group, its start and end as an operand of the
+    /// enclosing chain.
+    bounds: (usize, usize),
+    /// Whether the group is preceded by `NOT`.
+    negated: bool,
+}
+
+impl<'i> ComparisonFrame<'i> {
+    fn new(pairs: Pairs<'i, Rule>, bounds: (usize, usize), negated: bool) -> Self {
+        Self {
+            pairs,
+            chain: OperandChain::default(),
+            bounds,
+            negated,
+        }
+    }
+}
+
+/// Parse the comparisons in `pairs`, collecting any qualifiers that follow
+/// them. Like [`parse_expression`], parenthesized groups use an explicit
+/// stack instead of recursion.
+fn parse_comparisons<'i>(
+    cx: &mut Context,
+    pairs: Pairs<'i, Rule>,
+    qualifiers: &mut Vec<Pair<'i, Rule>>,
+) -> Result<ComparisonExpr> {
+    let mut stack = vec![ComparisonFrame::new(pairs, (0, 0), false)];
+
+    loop {
+        let frame = stack.last_mut().expect("the stack holds the outer chain");
+        let Some(p) = frame.pairs.next() else {
+            let frame = stack.pop().expect("the stack holds the current frame");
+            let mut expr = frame
+                .chain
+                .finish()
+                .ok_or(ParseError::MissingElement("comparison"))?;
+            let Some(parent) = stack.last_mut() else {
+                return Ok(expr);
+            };
+            let (start, end) = frame.bounds;
+            match &mut expr {
+                ComparisonExpr::Composite(c) => {
+                    c.parenthesized = true;
+                    c.negated ^= frame.negated;
+                }
+                // `NOT (a:x = 1)` is `a:x NOT = 1`.
+                ComparisonExpr::Single(c) if frame.negated => {
+                    if matches!(c.op, ComparisonOperator::Unary(_)) {
+                        return Err(ParseError::NegatedExists(start));
+                    }
+                    c.negated = !c.negated;
+                }
+                ComparisonExpr::Single(_) => {}
+            }
+            parent.chain.push_operand(expr, start, end);
+            continue;
+        };
 
-    for p in pair.into_inner() {
         match p.as_rule() {
-            Rule::expression => inner_pattern = Some(parse_expression(p)?),
-            Rule::qualifier => parse_qualifier(p, &mut qualifiers)?,
+            Rule::comparison => {
+                let (start, end) = (p.as_span().start(), p.as_span().end());
+                if is_comparison_group(&p) {
+                    let negated = is_negated_group(&p);
+                    if negated && !cx.options.allow_grouped_negation {
+                        return Err(ParseError::GroupedNegation(start));
+                    }
+                    stack.push(ComparisonFrame::new(p.into_inner(), (start, end), negated));
+                } else {
+                    let comparison = parse_comparison(cx, p)?;
+                    frame.chain.push_operand(comparison, start, end);
+                }
+            }
+            Rule::and => frame.chain.push_op(BooleanOp::And),
+            Rule::or => frame.chain.push_op(BooleanOp::Or),
+            Rule::qualifier => qualifiers.push(p),
             _ => {}
         }
     }
+}
 
-    let pattern = inner_pattern.ok_or(ParseError::MissingElement("expression"))?;
-    Ok(qualifiers.apply_to(pattern))
+/// Whether a `comparison` is a parenthesized comparison expression,
+/// negated or not.
+fn is_comparison_group(pair: &Pair<Rule>) -> bool {
+    pair.clone()
+        .into_inner()
+        .find(|p| p.as_rule() != Rule::not)
+        .is_some_and(|p| p.as_rule() == Rule::comparison)
+}
+
+/// Whether a parenthesized comparison expression is preceded by `NOT`.
+fn is_negated_group(pair: &Pair<Rule>) -> bool {
+    pair.clone()
+        .into_inner()
+        .next()
+        .is_some_and(|p| p.as_rule() == Rule::not)
 }
 
-fn parse_comparison(pair: Pair<Rule>) -> Result<ComparisonExpr> {
-    let mut inner = pair.into_inner().peekable();
+fn parse_comparison(cx: &mut Context, pair: Pair<Rule>) -> Result<ComparisonExpr> {
+    let span = pair.as_span();
+    let pairs = pair.into_inner();
+    let mut inner = pairs.clone().peekable();
 
     // Check what kind of comparison this is
     match inner.peek().map(|p| p.as_rule()) {
         // Parenthesized comparison expression
         Some(Rule::comparison) => {
-            let mut expr: Option<ComparisonExpr> = None;
-            let mut pending_op: Option<BooleanOp> = None;
+            let mut expr = parse_comparisons(cx, pairs, &mut Vec::new())?;
+            if let ComparisonExpr::Composite(c) = &mut expr {
+                c.parenthesized = true;
+            }
+            Ok(expr)
+        }
 
-            for p in inner {
-                match p.as_rule() {
-                    Rule::comparison => {
-                        let comp = parse_comparison(p)?;
-                        expr = Some(merge_exprs(expr, comp, pending_op.take()));
-                    }
-                    Rule::and => pending_op = Some(BooleanOp::And),
-                    Rule::or => pending_op = Some(BooleanOp::Or),
-                    _ => {}
-                }
+        // Non-standard range: path BETWEEN low AND high
+        Some(Rule::comparison_between) => {
+            if !cx.options.allow_between {
+                return Err(ParseError::Between(span.start()));
             }
-            expr.ok_or(ParseError::MissingElement("comparison"))
+            let mut parts = inner.next().unwrap().into_inner();
+            let path_pair = parts.next().ok_or(ParseError::MissingElement("path"))?;
+            let path = parse_object_path(cx, path_pair)?;
+            let mut bounds = parts.filter(|p| p.as_rule() == Rule::value);
+            let mut bound = |op: ComparisonOp| -> Result<ComparisonExpr> {
+                let pair = bounds.next().ok_or(ParseError::MissingElement("bound"))?;
+                cx.add_comparison(span)?;
+                cx.check_operator(op, span.start())?;
+                let raw = raw_text(&pair);
+                let value = parse_value(cx, pair)?;
+                let comparison = Comparison::new(path.clone(), op, Some(value.into()), false)
+                    .with_span(span.into());
+                Ok(match raw {
+                    Some(raw) => comparison.with_raw(raw),
+                    None => comparison,
+                }
+                .into())
+            };
+            let low = bound(ComparisonOp::Ge)?;
+            let high = bound(ComparisonOp::Le)?;
+            cx.warn("BETWEEN expanded to >= and <=", span);
+            Ok(CompositeComparison::new(low, BooleanOp::And, high)
+                .with_span(span.into())
+                .into())
         }
 
+        // STIX only allows NOT before comparison operators
+        Some(Rule::not) => Err(ParseError::NegatedExists(span.start())),
+
         // EXISTS comparison
         Some(Rule::exists) => {
+            cx.add_comparison(span)?;
             inner.next(); // consume exists
             let path_pair = inner.next().ok_or(ParseError::MissingElement("path"))?;
-            let path = parse_object_path(path_pair)?;
-            Ok(Comparison::new(path, UnaryOp::Exists, None, false).into())
+            let path = parse_object_path(cx, path_pair)?;
+            Ok(Comparison::new(path, UnaryOp::Exists, None, false)
+                .with_span(span.into())
+                .into())
         }
 
         // Normal comparison: path [NOT] op value
         Some(Rule::path) => {
+            cx.add_comparison(span)?;
             let path_pair = inner.next().unwrap();
-            let path = parse_object_path(path_pair)?;
+            let path = parse_object_path(cx, path_pair)?;
 
             let mut negated = false;
             let mut op: Option<ComparisonOp> = None;
             let mut rhs: Option<ComparisonRhs> = None;
+            let mut raw = None;
+            let mut has_ranges = false;
+            let mut value_span = None;
+            let mut op_start = span.start();
+            let mut rhs_start = span.end();
 
             for p in inner {
                 match p.as_rule() {
                     Rule::not => negated = true,
-                    Rule::value => rhs = Some(parse_value(p)?.into()),
-                    Rule::list => rhs = Some(parse_list(p)?.into()),
+                    Rule::value => {
+                        raw = raw_text(&p);
+                        value_span = Some(p.as_span());
+                        rhs_start = p.as_span().start();
+                        rhs = Some(parse_value(cx, p)?.into());
+                    }
+                    Rule::list => {
+                        raw = raw_text(&p);
+                        has_ranges = p
+                            .clone()
+                            .into_inner()
+                            .any(|p| p.as_rule() == Rule::int_range);
+                        rhs_start = p.as_span().start();
+                        rhs = Some(parse_list(cx, p)?.into());
+                    }
+                    Rule::path => {
+                        if !cx.options.allow_path_rhs {
+                            return Err(ParseError::PathRhs(p.as_span().start()));
+                        }
+                        rhs_start = p.as_span().start();
+                        rhs = Some(parse_object_path(cx, p)?.into());
+                    }
                     rule => {
                         if let Some(parsed_op) = try_parse_comp_op(rule) {
                             op = Some(parsed_op);
+                            op_start = p.as_span().start();
                         }
                     }
                 }
             }
 
             let op = op.ok_or(ParseError::MissingElement("operator"))?;
-            Ok(Comparison::new(path, op, rhs, negated).into())
+            cx.check_operator(op, op_start)?;
+            let is_list = matches!(rhs, Some(ComparisonRhs::List(_)));
+            if is_list != op.takes_list() {
+                return Err(ParseError::OperatorArityMismatch {
+                    op: op.value(),
+                    expected: if is_list { "a single value" } else { "a list" },
+                    offset: rhs_start,
+                });
+            }
+            if op == ComparisonOp::Matches
+                && (cx.options.validate_regex || (cx.options.strict && cfg!(feature = "regex")))
+                && let Some(ComparisonRhs::Value(StixValue::String(regex))) = &rhs
+            {
+                validate_regex(regex)?;
+            }
+            if matches!(op, ComparisonOp::IsSubset | ComparisonOp::IsSuperset)
+                && let Some(ComparisonRhs::Value(StixValue::String(range))) = &rhs
+                && let Some(value_span) = value_span
+            {
+                check_cidr(cx, &path.object_type, range, value_span)?;
+            }
+            let mut comparison = Comparison::new(path, op, rhs, negated).with_span(span.into());
+            comparison.has_ranges = has_ranges;
+            Ok(match raw {
+                Some(raw) => comparison.with_raw(raw),
+                None => comparison,
+            }
+            .into())
+        }
+
+        // Reversed comparison: value [NOT] op path
+        Some(Rule::value) => {
+            if !cx.options.allow_reversed_comparison {
+                return Err(ParseError::ReversedComparison(span.start()));
+            }
+            cx.add_comparison(span)?;
+            let value_pair = inner.next().unwrap();
+            let raw = raw_text(&value_pair);
+            let value = parse_value(cx, value_pair)?;
+
+            let mut negated = false;
+            let mut op: Option<ComparisonOp> = None;
+            let mut op_start = span.start();
+            let mut path: Option<ObjectPath> = None;
+
+            for p in inner {
+                match p.as_rule() {
+                    Rule::not => negated = true,
+                    Rule::path => path = Some(parse_object_path(cx, p)?),
+                    rule => {
+                        if let Some(parsed_op) = try_parse_comp_op(rule) {
+                            op = Some(parsed_op);
+                            op_start = p.as_span().start();
+                        }
+                    }
+                }
+            }
+
+            let op = op.ok_or(ParseError::MissingElement("operator"))?;
+            let swapped = op.swapped().ok_or(ParseError::NotSwappable {
+                op: op.value(),
+                offset: span.start(),
+            })?;
+            cx.check_operator(swapped, op_start)?;
+            let path = path.ok_or(ParseError::MissingElement("path"))?;
+            cx.warn("reversed comparison canonicalized", span);
+            let comparison =
+                Comparison::new(path, swapped, Some(value.into()), negated).with_span(span.into());
+            Ok(match raw {
+                Some(raw) => comparison.with_raw(raw),
+                None => comparison,
+            }
+            .into())
         }
 
         _ => Err(ParseError::MissingElement("comparison content")),
     }
 }
 
+/// The source text of a constant, kept so `Display` reproduces it. Constants
+/// with double-quoted strings, ranges or comments have none, so they are
+/// written in the standard form.
+fn raw_text<'i>(pair: &Pair<'i, Rule>) -> Option<&'i str> {
+    let text = pair.as_str();
+    let start = pair.as_span().start();
+    // Comments are skipped without a pair, so they are looked for in the
+    // text outside quoted strings, which may hold `/*` themselves.
+    let mut unquoted = 0;
+    for p in pair.clone().into_inner().flatten() {
+        match p.as_rule() {
+            Rule::dq_string | Rule::int_range => return None,
+            Rule::string | Rule::time => {
+                if text[unquoted..p.as_span().start() - start].contains("/*") {
+                    return None;
+                }
+                unquoted = p.as_span().end() - start;
+            }
+            _ => {}
+        }
+    }
+    (!text[unquoted..].contains("/*")).then_some(text)
+}
+
+/// The `ISSUBSET` and `ISSUPERSET` operands of an address object must be
+/// addresses or ranges of its IP version.
+fn check_cidr(cx: &mut Context, object_type: &str, range: &str, span: Span<'_>) -> Result<()> {
+    let valid = match object_type {
+        "ipv4-addr" => matches!(parse_network(range), Ok(IpNet::V4(_))),
+        "ipv6-addr" => matches!(parse_network(range), Ok(IpNet::V6(_))),
+        _ => return Ok(()),
+    };
+    if !valid {
+        if cx.options.strict {
+            return Err(ParseError::InvalidCidr(range.to_owned()));
+        }
+        cx.warn(
+            format!("'{range}' is not an {object_type} CIDR range"),
+            span,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "regex")]
+fn validate_regex(pattern: &str) -> Result<()> {
+    regex::Regex::new(pattern)
+        .map(drop)
+        .map_err(|e| ParseError::InvalidRegex(e.to_string()))
+}
+
+#[cfg(not(feature = "regex"))]
+fn validate_regex(_pattern: &str) -> Result<()> {
+    Err(ParseError::InvalidRegex(
+        "regex validation requires the `regex` feature".to_owned(),
+    ))
+}
+
 fn try_parse_comp_op(rule: Rule) -> Option<ComparisonOp> {
     match rule {
         Rule::equal => Some(ComparisonOp::Eq),
@@ -216,42 +1542,89 @@ fn parse_obs_op(pair: &Pair<Rule>) -> Result<ObservationOp> {
     }
 }
 
-fn merge_exprs(
-    left: Option<ComparisonExpr>,
-    right: ComparisonExpr,
-    op: Option<BooleanOp>,
-) -> ComparisonExpr {
-    match left {
-        None => right,
-        Some(l) => CompositeComparison::new(l, op.unwrap_or_default(), right).into(),
-    }
-}
-
-fn parse_object_path(pair: Pair<Rule>) -> Result<ObjectPath> {
+fn parse_object_path(cx: &mut Context, pair: Pair<Rule>) -> Result<ObjectPath> {
+    let span = pair.as_span();
     let mut object_type = String::new();
-    let mut property_path = Vec::new();
+    let mut property_path: Vec<PathComponent> = Vec::new();
 
     for p in pair.into_inner() {
         match p.as_rule() {
-            Rule::object => object_type = p.as_str().to_owned(),
-            Rule::step => property_path.push(parse_step(p)?),
+            Rule::object => {
+                object_type = p.as_str().to_owned();
+                if cx.options.normalize_object_types
+                    && object_type.chars().any(|c| c.is_ascii_uppercase())
+                {
+                    let original =
+                        std::mem::replace(&mut object_type, p.as_str().to_ascii_lowercase());
+                    cx.warn(
+                        format!("object type '{original}' normalized to '{object_type}'"),
+                        p.as_span(),
+                    );
+                }
+                if !is_known_object_type(&object_type) {
+                    cx.warn(format!("unknown object type '{object_type}'"), p.as_span());
+                } else if object_type.starts_with("x-")
+                    && !is_valid_custom_object_type(&object_type)
+                {
+                    cx.warn(
+                        format!(
+                            "custom object type '{object_type}' should be lowercase \
+                             letters, digits and single hyphens"
+                        ),
+                        p.as_span(),
+                    );
+                }
+            }
+            Rule::step => {
+                let span = p.as_span();
+                let mut step = parse_step(cx, p)?;
+                if cx.options.normalize_hash_names
+                    && step.indices.is_empty()
+                    && property_path
+                        .last()
+                        .is_some_and(|prev| &*prev.property == "hashes")
+                    && let Some(name) = canonical_hash_name(&step.property)
+                    && *step.property != *name
+                {
+                    cx.warn(
+                        format!("hash name '{}' normalized to '{name}'", step.property),
+                        span,
+                    );
+                    step.property = cx.intern(name);
+                    step.kind = if name.contains('-') {
+                        PropertyKind::Quoted
+                    } else {
+                        PropertyKind::Plain
+                    };
+                }
+                property_path.push(step);
+            }
             _ => {}
         }
     }
 
-    Ok(ObjectPath::new(object_type, property_path))
+    Ok(ObjectPath::new(cx.intern(&object_type), property_path).with_span(span.into()))
 }
 
-fn parse_step(pair: Pair<Rule>) -> Result<PathComponent> {
+fn parse_step(cx: &Context, pair: Pair<Rule>) -> Result<PathComponent> {
     let mut property = String::new();
-    let mut index = None;
+    let mut kind = PropertyKind::Plain;
+    let mut indices = Vec::new();
 
     for p in pair.into_inner() {
         match p.as_rule() {
-            Rule::property => property = strip_quotes(p.as_str()),
+            Rule::property => {
+                let s = p.as_str();
+                if let Some(key) = s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+                    property = unescape_string(key, '\'')?;
+                    kind = PropertyKind::Quoted;
+                } else {
+                    property = s.to_owned();
+                }
+            }
             Rule::index => {
                 let idx_str = p.as_str();
-                index = Some(if idx_str == "*" {
+                indices.push(if idx_str == "*" {
                     ListIndex::Star
                 } else {
                     ListIndex::Index(idx_str.parse()?)
@@ -261,45 +1634,164 @@ fn parse_step(pair: Pair<Rule>) -> Result<PathComponent> {
         }
     }
 
-    Ok(PathComponent::new(property, index))
+    Ok(PathComponent {
+        property: cx.intern(&property),
+        indices,
+        kind,
+    })
 }
 
-fn strip_quotes(s: &str) -> String {
-    s.strip_prefix('\'')
-        .and_then(|s| s.strip_suffix('\''))
-        .unwrap_or(s)
-        .to_owned()
+/// Parse an integer literal, reporting `ParseError::IntOutOfRange` if it does
+/// not fit between `min` and `max`, the bounds of `T`.
+fn parse_int<T>(s: &str, min: T, max: T) -> Result<T>
+where
+    T: FromStr<Err = ParseIntError> + Into<i64>,
+{
+    s.parse().map_err(|e: ParseIntError| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => ParseError::IntOutOfRange {
+            literal: s.to_owned(),
+            min: min.into(),
+            max: max.into(),
+        },
+        _ => e.into(),
+    })
 }
 
-fn parse_value(pair: Pair<Rule>) -> Result<StixValue> {
+/// Parse a float literal, rejecting values too large to be finite. The
+/// grammar has no exponent, `inf` or `nan` syntax, but `str::parse` turns
+/// literals beyond `f64::MAX` into infinity.
+fn parse_float(s: &str) -> Result<f64> {
+    let value: f64 = s.parse()?;
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(ParseError::NonFiniteFloat(s.to_owned()))
+    }
+}
+
+fn parse_value(cx: &Context, pair: Pair<Rule>) -> Result<StixValue> {
     let inner = pair
         .into_inner()
         .next()
         .ok_or(ParseError::MissingElement("value content"))?;
 
     match inner.as_rule() {
-        Rule::string => Ok(StixValue::String(unescape_string(inner.as_str()))),
+        Rule::string => Ok(StixValue::String(unescape_string(inner.as_str(), '\'')?)),
+        Rule::dq_string if cx.options.allow_double_quotes => {
+            Ok(StixValue::String(unescape_string(inner.as_str(), '"')?))
+        }
+        Rule::dq_string => Err(ParseError::DoubleQuotes(inner.as_span().start() - 1)),
         Rule::bool => Ok(StixValue::Bool(inner.as_str() == "true")),
-        Rule::float => Ok(StixValue::Float(inner.as_str().parse()?)),
-        Rule::int => Ok(StixValue::Int(inner.as_str().parse()?)),
-        Rule::time => parse_timestamp(inner.as_str()).map(StixValue::Timestamp),
+        Rule::float
+            if (!cx.options.allow_exponent || cx.options.strict)
+                && inner.as_str().contains(['e', 'E']) =>
+        {
+            Err(ParseError::Exponent(inner.as_span().start()))
+        }
+        Rule::float => Ok(StixValue::Float(parse_float(inner.as_str())?)),
+        Rule::int => Ok(StixValue::Int(parse_int(
+            inner.as_str(),
+            i64::MIN,
+            i64::MAX,
+        )?)),
+        Rule::time => parse_timestamp(inner.as_str(), cx.options.strict).map(StixValue::Timestamp),
+        Rule::hex if cx.options.strict && !inner.as_str().len().is_multiple_of(2) => {
+            Err(ParseError::InvalidHex(inner.as_str().to_owned()))
+        }
         Rule::hex => Ok(StixValue::Hex(inner.as_str().to_owned())),
+        Rule::bin if cx.options.strict && !is_padded_base64(inner.as_str()) => {
+            Err(ParseError::InvalidBase64(inner.as_str().to_owned()))
+        }
         Rule::bin => Ok(StixValue::Binary(inner.as_str().to_owned())),
+        Rule::null if cx.options.allow_null => Ok(StixValue::Null),
+        Rule::null => Err(ParseError::NullConstant(inner.as_span().start())),
         _ => Err(ParseError::UnexpectedRule(inner.as_rule())),
     }
 }
 
-fn parse_list(pair: Pair<Rule>) -> Result<Vec<StixValue>> {
-    pair.into_inner()
-        .filter(|p| p.as_rule() == Rule::value)
-        .map(parse_value)
-        .collect()
+/// Most values the ranges of a list, such as `1..5`, may expand to in all.
+const MAX_RANGE_LEN: u64 = 1024;
+
+fn parse_list(cx: &mut Context, pair: Pair<Rule>) -> Result<Vec<StixValue>> {
+    let span = pair.as_span();
+    let mut values = Vec::new();
+    let mut range_budget = MAX_RANGE_LEN;
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::value => values.push(parse_value(cx, p)?),
+            Rule::int_range => values.extend(parse_range(cx, p, &mut range_budget)?),
+            _ => {}
+        }
+    }
+
+    if values.is_empty() {
+        if !cx.options.allow_empty_lists || cx.options.strict {
+            return Err(ParseError::EmptyList(span.start()));
+        }
+        cx.warn("empty list never matches", span);
+    }
+    if !cx.options.allow_mixed_lists {
+        check_list_types(&values)?;
+    }
+    Ok(values)
+}
+
+/// The integers of a `low..high` list element, both included, taken from
+/// the `budget` of values the list's ranges may still expand to.
+fn parse_range(cx: &mut Context, pair: Pair<Rule>, budget: &mut u64) -> Result<Vec<StixValue>> {
+    let span = pair.as_span();
+    if !cx.options.allow_ranges {
+        return Err(ParseError::Range(span.start()));
+    }
+    let mut bounds = pair.into_inner();
+    let mut bound = || {
+        let bound = bounds.next().ok_or(ParseError::MissingElement("bound"))?;
+        parse_int(bound.as_str(), i64::MIN, i64::MAX)
+    };
+    let (low, high) = (bound()?, bound()?);
+    if high < low || high.abs_diff(low) >= *budget {
+        return Err(ParseError::InvalidRange(span.as_str().to_owned()));
+    }
+    *budget -= high.abs_diff(low) + 1;
+    cx.warn(
+        format!(
+            "range {} expanded to {} values",
+            span.as_str(),
+            high - low + 1
+        ),
+        span,
+    );
+    Ok((low..=high).map(StixValue::Int).collect())
+}
+
+fn check_list_types(values: &[StixValue]) -> Result<()> {
+    let type_class = |v: &StixValue| match v {
+        StixValue::Int(_) | StixValue::Float(_) => "number",
+        other => other.type_name(),
+    };
+
+    let Some(first) = values.first() else {
+        return Ok(());
+    };
+    match values
+        .iter()
+        .enumerate()
+        .find(|(_, v)| type_class(v) != type_class(first))
+    {
+        Some((index, v)) => Err(ParseError::MixedList {
+            index,
+            found: v.type_name(),
+            expected: first.type_name(),
+        }),
+        None => Ok(()),
+    }
 }
 
 #[derive(Default)]
 struct Qualifiers {
     repeat: Option<u32>,
     within: Option<f64>,
+    within_raw: Option<String>,
     start: Option<DateTime<Utc>>,
     stop: Option<DateTime<Utc>>,
 }
@@ -312,16 +1804,43 @@ impl Qualifiers {
             && self.stop.is_none()
     }
 
-    fn apply_to(self, pattern: PatternExpr) -> PatternExpr {
+    fn apply_to(self, pattern: PatternExpr, span: SourceSpan) -> PatternExpr {
         if self.is_empty() {
             pattern
         } else {
-            QualifiedPattern::new(pattern, self.repeat, self.within, self.start, self.stop).into()
+            let q = QualifiedPattern::new(pattern, self.repeat, self.within, self.start, self.stop)
+                .with_span(span);
+            match self.within_raw {
+                Some(raw) => q.with_raw_within(raw),
+                None => q,
+            }
+            .into()
         }
     }
 }
 
-fn parse_qualifier(pair: Pair<Rule>, q: &mut Qualifiers) -> Result<()> {
+/// Qualifiers apply to everything on their left, so each one wraps the
+/// pattern in its own `QualifiedPattern`, the first qualifier innermost:
+/// `[a] REPEATS 2 TIMES WITHIN 5 SECONDS` bounds the two repetitions by five
+/// seconds, while `[a] WITHIN 5 SECONDS REPEATS 2 TIMES` repeats the bounded
+/// observation.
+fn apply_qualifiers(
+    cx: &Context,
+    mut pattern: PatternExpr,
+    qualifiers: Vec<Pair<Rule>>,
+    start: usize,
+) -> Result<PatternExpr> {
+    for pair in qualifiers {
+        let span = SourceSpan::new(start, pair.as_span().end());
+        let mut q = Qualifiers::default();
+        parse_qualifier(cx, pair, &mut q)?;
+        pattern = q.apply_to(pattern, span);
+    }
+    Ok(pattern)
+}
+
+fn parse_qualifier(cx: &Context, pair: Pair<Rule>, q: &mut Qualifiers) -> Result<()> {
+    let offset = pair.as_span().start();
     let inner = pair
         .into_inner()
         .next()
@@ -331,21 +1850,26 @@ fn parse_qualifier(pair: Pair<Rule>, q: &mut Qualifiers) -> Result<()> {
         Rule::repeat => {
             for p in inner.into_inner() {
                 if p.as_rule() == Rule::pos_int {
-                    q.repeat = Some(p.as_str().parse()?);
+                    let repeat = parse_int(p.as_str(), u32::MIN, u32::MAX)?;
+                    if cx.options.strict && repeat == 0 {
+                        return Err(ParseError::NonPositiveRepeat(offset));
+                    }
+                    q.repeat = Some(repeat);
                 }
             }
         }
         Rule::within => {
             for p in inner.into_inner() {
                 if matches!(p.as_rule(), Rule::pos_float | Rule::pos_int) {
-                    q.within = Some(p.as_str().parse()?);
+                    q.within = Some(parse_float(p.as_str())?);
+                    q.within_raw = Some(p.as_str().to_owned());
                 }
             }
         }
         Rule::interval => {
             for p in inner.into_inner() {
                 if p.as_rule() == Rule::time {
-                    let ts = parse_timestamp(p.as_str())?;
+                    let ts = parse_timestamp(p.as_str(), cx.options.strict)?;
                     if q.start.is_none() {
                         q.start = Some(ts);
                     } else {
@@ -353,6 +1877,9 @@ fn parse_qualifier(pair: Pair<Rule>, q: &mut Qualifiers) -> Result<()> {
                     }
                 }
             }
+            if cx.options.strict && q.start >= q.stop {
+                return Err(ParseError::EmptyInterval(offset));
+            }
         }
         _ => {}
     }
@@ -360,7 +1887,9 @@ fn parse_qualifier(pair: Pair<Rule>, q: &mut Qualifiers) -> Result<()> {
     Ok(())
 }
 
-fn unescape_string(s: &str) -> String {
+/// Resolve the escapes of a string quoted with `quote`, which is itself
+/// escapable.
+fn unescape_string(s: &str, quote: char) -> Result<String> {
     let mut result = String::with_capacity(s.len());
     let mut chars = s.chars();
 
@@ -370,7 +1899,12 @@ fn unescape_string(s: &str) -> String {
                 Some('n') => result.push('\n'),
                 Some('r') => result.push('\r'),
                 Some('t') => result.push('\t'),
-                Some(c @ ('\\' | '\'')) => result.push(c),
+                Some(c) if c == '\\' || c == quote => result.push(c),
+                Some('x') => {
+                    let code = read_hex_escape(&mut chars, 'x', 2)?;
+                    result.push(char::from_u32(code).expect("two hex digits fit in a char"));
+                }
+                Some('u') => result.push(read_unicode_escape(&mut chars)?),
                 Some(other) => {
                     result.push('\\');
                     result.push(other);
@@ -382,12 +1916,58 @@ fn unescape_string(s: &str) -> String {
         }
     }
 
-    result
+    Ok(result)
+}
+
+/// Reads the `digits` hex digits following `\<prefix>`.
+fn read_hex_escape(chars: &mut std::str::Chars<'_>, prefix: char, digits: usize) -> Result<u32> {
+    let hex: String = chars.clone().take(digits).collect();
+    if hex.len() != digits || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidEscape(format!("\\{prefix}{hex}")));
+    }
+    chars.nth(digits - 1);
+    Ok(u32::from_str_radix(&hex, 16).expect("validated hex digits"))
+}
+
+/// Decodes a `\uXXXX` escape, combining UTF-16 surrogate pairs written as
+/// two consecutive escapes.
+fn read_unicode_escape(chars: &mut std::str::Chars<'_>) -> Result<char> {
+    let high = read_hex_escape(chars, 'u', 4)?;
+    if let Some(c) = char::from_u32(high) {
+        return Ok(c);
+    }
+
+    let invalid = || ParseError::InvalidEscape(format!("\\u{high:04X}"));
+    if !(0xD800..0xDC00).contains(&high) || chars.as_str().get(..2) != Some("\\u") {
+        return Err(invalid());
+    }
+    chars.nth(1);
+    let low = read_hex_escape(chars, 'u', 4)?;
+    if !(0xDC00..0xE000).contains(&low) {
+        return Err(invalid());
+    }
+    char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or_else(invalid)
+}
+
+/// Base64 as in RFC 4648: the grammar restricts the alphabet, so only the
+/// length and the position of the `=` padding need checking.
+fn is_padded_base64(s: &str) -> bool {
+    let data = s.trim_end_matches('=');
+    s.len().is_multiple_of(4) && s.len() - data.len() <= 2 && !data.contains('=')
 }
 
-fn parse_timestamp(s: &str) -> Result<DateTime<Utc>> {
-    chrono::DateTime::parse_from_rfc3339(s)
-        .map(|dt| dt.with_timezone(&Utc))
+/// With `require_zone`, only RFC 3339 timestamps are accepted; otherwise a
+/// timestamp without a zone is read as UTC.
+fn parse_timestamp(s: &str, require_zone: bool) -> Result<DateTime<Utc>> {
+    let parsed = chrono::DateTime::parse_from_rfc3339(s).map(|dt| dt.with_timezone(&Utc));
+    if require_zone && parsed.is_err() {
+        let without_zone = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f");
+        return Err(match without_zone {
+            Ok(_) => ParseError::TimestampWithoutZone(s.to_owned()),
+            Err(_) => ParseError::InvalidTimestamp(s.to_owned()),
+        });
+    }
+    parsed
         .or_else(|_| {
             chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").map(|dt| dt.and_utc())
         })
@@ -421,8 +2001,1489 @@ mod tests {
         assert!(parse_pattern("[file:name = 'foo'] REPEATS 5 TIMES").is_ok());
     }
 
+    fn qualifier_chain(expr: &PatternExpr) -> Vec<(Option<u32>, Option<f64>, bool)> {
+        let mut chain = Vec::new();
+        let mut current = expr;
+        while let PatternExpr::Qualified(q) = current {
+            chain.push((q.repeat, q.within, q.start.is_some()));
+            current = &q.pattern;
+        }
+        chain
+    }
+
+    #[test]
+    fn test_chained_qualifiers_nest_in_source_order() {
+        let interval = "START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'";
+        let cases = [
+            (
+                "[a:x = 1] REPEATS 2 TIMES WITHIN 5 SECONDS".to_owned(),
+                vec![(None, Some(5.0), false), (Some(2), None, false)],
+            ),
+            (
+                "[a:x = 1] WITHIN 5 SECONDS REPEATS 2 TIMES".to_owned(),
+                vec![(Some(2), None, false), (None, Some(5.0), false)],
+            ),
+            (
+                format!("([a:x = 1] OR [a:y = 2]) REPEATS 2 TIMES {interval}"),
+                vec![(None, None, true), (Some(2), None, false)],
+            ),
+            (
+                format!("[a:x = 1] {interval} WITHIN 5 SECONDS"),
+                vec![(None, Some(5.0), false), (None, None, true)],
+            ),
+            (
+                "[a:x = 1] REPEATS 2 TIMES REPEATS 3 TIMES".to_owned(),
+                vec![(Some(3), None, false), (Some(2), None, false)],
+            ),
+        ];
+        for (input, expected) in cases {
+            let expr = parse_pattern(&input).unwrap();
+            assert_eq!(qualifier_chain(&expr), expected, "{input}");
+            assert_eq!(expr.to_string(), input);
+        }
+    }
+
     #[test]
     fn test_followedby() {
         assert!(parse_pattern("[file:name = 'a'] FOLLOWEDBY [file:name = 'b']").is_ok());
     }
+
+    #[test]
+    fn test_parse_stats() {
+        let input = "[file:name = 'a']";
+        let (expr, stats) = parse_pattern_with_stats(input, &ParseOptions::default()).unwrap();
+        assert!(matches!(expr, PatternExpr::Comparison(_)));
+        assert_eq!(stats.input_bytes, input.len());
+
+        let (_, larger) = parse_pattern_with_stats(
+            "[file:name = 'a' AND file:size = 1]",
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        assert!(stats.node_count > 1);
+        assert!(larger.node_count > stats.node_count);
+    }
+
+    #[test]
+    fn test_parse_from_multiple_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<PatternExpr>();
+        assert_send_sync::<ParseError>();
+
+        let options = ParseOptions::default();
+        let threads = 8;
+        // Released together, so the parses overlap.
+        let start = std::sync::Barrier::new(threads);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|i| {
+                    let (options, start) = (&options, &start);
+                    scope.spawn(move || {
+                        start.wait();
+                        for j in 0..100 {
+                            let input =
+                                format!("[file:size = {i}] FOLLOWEDBY [file:name = 'f{j}']");
+                            let expr = parse_pattern_with_options(&input, options).unwrap();
+                            assert_eq!(expr.to_string(), input);
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_unknown_object_type_warning() {
+        let opts = ParseOptions::default();
+        let (_, warnings) =
+            parse_pattern_with_diagnostics("[file:name = 'a' AND flie:name = 'b']", &opts).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "unknown object type 'flie'");
+        assert_eq!((warnings[0].start, warnings[0].end), (21, 25));
+
+        let (_, warnings) = parse_pattern_with_diagnostics("[x-acme:name = 'a']", &opts).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+
+    #[test]
+    fn test_empty_list_rejected_by_default() {
+        assert!(matches!(
+            parse_pattern("[file:name IN ()]"),
+            Err(ParseError::EmptyList(14))
+        ));
+    }
+
+    #[test]
+    fn test_empty_list_allowed_with_warning() {
+        let opts = ParseOptions {
+            allow_empty_lists: true,
+            ..Default::default()
+        };
+        let (_, warnings) = parse_pattern_with_diagnostics("[file:name IN ( )]", &opts).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "empty list never matches");
+    }
+
+    #[test]
+    fn test_mixed_list_rejected_by_default() {
+        let err = parse_pattern("[file:size IN (1, 'two', 3)]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Mixed list: element 1 is string, expected int"
+        );
+        assert!(parse_pattern("[file:size IN (1, 2.5, 3)]").is_ok());
+    }
+
+    #[test]
+    fn test_mixed_list_allowed() {
+        let opts = ParseOptions {
+            allow_mixed_lists: true,
+            ..Default::default()
+        };
+        assert!(parse_pattern_with_options("[file:size IN (1, 'two', 3)]", &opts).is_ok());
+    }
+
+    fn single_path(input: &str) -> ObjectPath {
+        match parse_pattern(input).unwrap() {
+            PatternExpr::Comparison(ComparisonExpr::Single(c)) => c.object_path,
+            other => panic!("expected a single comparison, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_reference_paths() {
+        let path = single_path("[network-traffic:src_ref.value = '10.0.0.1']");
+        assert_eq!(path.property_path.len(), 2);
+        assert_eq!(path.property_path[0], PathComponent::new("src_ref", None));
+        assert!(path.property_path[0].is_reference());
+        assert!(!path.property_path[1].is_reference());
+
+        let path = single_path("[domain-name:resolves_to_refs[*].value = '10.0.0.1']");
+        assert_eq!(
+            path.property_path,
+            [
+                PathComponent::new("resolves_to_refs", Some(ListIndex::Star)),
+                PathComponent::new("value", None),
+            ]
+        );
+        assert!(path.property_path[0].is_reference());
+    }
+
+    #[test]
+    fn test_chained_indices() {
+        let input = "[x-custom:matrix[0][*].value = 1]";
+        let path = single_path(input);
+        assert_eq!(
+            path.property_path[0],
+            PathComponent::new("matrix", None)
+                .with_indices(vec![ListIndex::Index(0), ListIndex::Star])
+        );
+        assert_eq!(path.property_path[1], PathComponent::new("value", None));
+        assert_eq!(parse_pattern(input).unwrap().to_string(), input);
+        assert!(parse_pattern("[x:a[0] [1] = 1]").is_err());
+    }
+
+    #[test]
+    fn test_quoted_dictionary_keys() {
+        let path = single_path("[file:hashes.MD5 = 'a']");
+        assert_eq!(path.property_path[1].kind, PropertyKind::Plain);
+
+        let cases = [
+            ("[file:hashes.'SHA-256' = 'a']", "SHA-256"),
+            ("[x:dict.'key with spaces' = 'a']", "key with spaces"),
+            ("[x:dict.'a.b[0]:c' = 'a']", "a.b[0]:c"),
+            (r"[x:dict.'it\'s \\ here' = 'a']", r"it's \ here"),
+            ("[x:dict.'MD5' = 'a']", "MD5"),
+        ];
+        for (input, key) in cases {
+            let expr = parse_pattern(input).unwrap();
+            let PatternExpr::Comparison(ComparisonExpr::Single(c)) = &expr else {
+                panic!("expected a single comparison");
+            };
+            let component = &c.object_path.property_path[1];
+            assert_eq!(&*component.property, key);
+            assert_eq!(component.kind, PropertyKind::Quoted);
+            assert_eq!(expr.to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_unicode_property_names() {
+        let err = parse_pattern("[x-custom:название = 'x']").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Grammar error at 1:11: expected property"),
+            "{err}"
+        );
+        assert!(parse_pattern("[x-custom:ext.ключ = 'x']").is_err());
+
+        let input = "[x-custom:'название'.'ключ'[0] = 'x']";
+        let path = single_path(input);
+        assert_eq!(&*path.property_path[0].property, "название");
+        assert_eq!(path.property_path[0].kind, PropertyKind::Quoted);
+        assert_eq!(&*path.property_path[1].property, "ключ");
+        assert_eq!(parse_pattern(input).unwrap().to_string(), input);
+
+        // Built without a kind, a non-ASCII name is still written quoted.
+        let c = Comparison::new(
+            ObjectPath::new("x-custom", vec![PathComponent::new("название", None)]),
+            ComparisonOp::Eq,
+            Some(StixValue::from("x").into()),
+            false,
+        );
+        assert_eq!(
+            PatternExpr::from(ComparisonExpr::from(c)).to_string(),
+            "[x-custom:'название' = 'x']"
+        );
+    }
+
+    #[test]
+    fn test_node_spans() {
+        let input = "[a:x = 1 AND (a:y = 2 OR EXISTS a:z)] FOLLOWEDBY [b:w = 'v'] WITHIN 5 SECONDS";
+        let text = |span: SourceSpan| &input[span.start().unwrap()..span.end().unwrap()];
+
+        let PatternExpr::Composite(pattern) = parse_pattern(input).unwrap() else {
+            panic!("expected a composite pattern");
+        };
+        assert_eq!(
+            text(pattern.span),
+            "[a:x = 1 AND (a:y = 2 OR EXISTS a:z)] FOLLOWEDBY [b:w = 'v'] WITHIN 5 SECONDS"
+        );
+        let PatternExpr::Qualified(qualified) = pattern.right.as_ref() else {
+            panic!("expected a qualified pattern");
+        };
+        assert_eq!(text(qualified.span), "[b:w = 'v'] WITHIN 5 SECONDS");
+        assert_eq!(text(qualified.pattern.span()), "b:w = 'v'");
+
+        let PatternExpr::Comparison(ComparisonExpr::Composite(observation)) = pattern.left.as_ref()
+        else {
+            panic!("expected a composite comparison");
+        };
+        assert_eq!(
+            text(observation.span),
+            "a:x = 1 AND (a:y = 2 OR EXISTS a:z)"
+        );
+        assert_eq!(text(observation.right.span()), "a:y = 2 OR EXISTS a:z");
+        let ComparisonExpr::Single(first) = observation.left.as_ref() else {
+            panic!("expected a comparison");
+        };
+        assert_eq!(text(first.span), "a:x = 1");
+        assert_eq!(text(first.object_path.span), "a:x");
+
+        let built = Comparison::new(first.object_path.clone(), ComparisonOp::Eq, None, false);
+        assert_eq!(built.span.start(), None);
+    }
+
+    #[test]
+    fn test_path_rhs_rejected_by_default() {
+        assert!(matches!(
+            parse_pattern("[file:name = file:parent_directory_ref]"),
+            Err(ParseError::PathRhs(13))
+        ));
+    }
+
+    #[test]
+    fn test_path_rhs_allowed() {
+        let options = ParseOptions {
+            allow_path_rhs: true,
+            ..Default::default()
+        };
+        let expr = parse_pattern_with_options("[a:x = b:y[0].z]", &options).unwrap();
+        let PatternExpr::Comparison(ComparisonExpr::Single(c)) = &expr else {
+            panic!("expected a single comparison");
+        };
+        let Some(ComparisonRhs::Path(path)) = &c.constant else {
+            panic!("expected a path RHS");
+        };
+        assert_eq!(&*path.object_type, "b");
+        assert_eq!(path.property_path.len(), 2);
+        assert_eq!(expr.to_string(), "[a:x = b:y[0].z]");
+    }
+
+    #[test]
+    fn test_max_operands() {
+        let options = ParseOptions {
+            max_operands: Some(2),
+            ..Default::default()
+        };
+        assert!(
+            parse_pattern_with_options("[a:x = 1 OR (a:y = 2)] AND [a:z = 3]", &options).is_ok()
+        );
+        assert!(matches!(
+            parse_pattern_with_options("[a:x = 1 OR (a:y = 2 OR a:z = 3)]", &options),
+            Err(ParseError::TooManyOperands {
+                limit: 2,
+                offset: 24
+            })
+        ));
+        assert!(matches!(
+            parse_pattern_with_options("[a:x = 1] AND ([a:y = 2] OR [a:z = 3])", &options),
+            Err(ParseError::TooManyOperands {
+                limit: 2,
+                offset: 28
+            })
+        ));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let pattern = vec!["[a:x = 1 AND a:y = 2]"; 100].join(" OR ");
+        let timeout = |ms| ParseOptions {
+            timeout_ms: Some(ms),
+            ..Default::default()
+        };
+        assert!(parse_pattern_with_options(&pattern, &timeout(60_000)).is_ok());
+        assert!(matches!(
+            parse_pattern_with_options(&pattern, &timeout(0)),
+            Err(ParseError::Timeout(0))
+        ));
+        assert!(matches!(
+            parse_comparison_expr_with_options("a:x = 1", &timeout(0)),
+            Err(ParseError::Timeout(0))
+        ));
+    }
+
+    #[test]
+    fn test_allowed_operators() {
+        let options = ParseOptions {
+            allowed_operators: Some(HashSet::from([ComparisonOp::Eq, ComparisonOp::In])),
+            allow_between: true,
+            allow_reversed_comparison: true,
+            ..Default::default()
+        };
+        let parse = |input| parse_pattern_with_options(input, &options);
+        assert!(parse("[a:x = 1 AND a:y NOT IN ('b') AND EXISTS a:z]").is_ok());
+        assert!(parse("[1 = a:x]").is_ok());
+        assert!(matches!(
+            parse("[a:x = 1 AND a:y MATCHES 'b']"),
+            Err(ParseError::DisallowedOperator {
+                op: "MATCHES",
+                offset: 17
+            })
+        ));
+        assert!(matches!(
+            parse("[a:x BETWEEN 1 AND 2]"),
+            Err(ParseError::DisallowedOperator {
+                op: ">=",
+                offset: 1
+            })
+        ));
+        assert!(matches!(
+            parse("[1 > a:x]"),
+            Err(ParseError::DisallowedOperator { op: "<", offset: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_null_constant() {
+        assert!(matches!(
+            parse_pattern("[file:size = null]"),
+            Err(ParseError::NullConstant(13))
+        ));
+
+        let options = ParseOptions {
+            allow_null: true,
+            ..Default::default()
+        };
+        let expr = parse_pattern_with_options("[file:size = null]", &options).unwrap();
+        let PatternExpr::Comparison(ComparisonExpr::Single(c)) = &expr else {
+            panic!("expected a single comparison");
+        };
+        assert_eq!(c.constant, Some(StixValue::Null.into()));
+        assert_eq!(expr.to_string(), "[file:size = null]");
+    }
+
+    #[test]
+    fn test_custom_object_types() {
+        let (expr, warnings) = parse_pattern_with_diagnostics(
+            "[x-acme-widget:name = 'a' AND x-Acme--Gadget-:name = 'b']",
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        let PatternExpr::Comparison(ComparisonExpr::Composite(c)) = expr else {
+            panic!("expected a composite comparison");
+        };
+        let ComparisonExpr::Single(left) = c.left.as_ref() else {
+            panic!("expected a comparison");
+        };
+        assert!(left.object_path.is_custom());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].message,
+            "custom object type 'x-Acme--Gadget-' should be lowercase letters, digits and \
+             single hyphens"
+        );
+        assert_eq!((warnings[0].start, warnings[0].end), (30, 45));
+
+        let file = parse_pattern("[file:name = 'a']").unwrap();
+        let PatternExpr::Comparison(ComparisonExpr::Single(c)) = file else {
+            panic!("expected a comparison");
+        };
+        assert!(!c.object_path.is_custom());
+    }
+
+    #[test]
+    fn test_normalize_object_types() {
+        let (expr, warnings) =
+            parse_pattern_with_diagnostics("[File:name = 'a']", &ParseOptions::default()).unwrap();
+        assert_eq!(expr.to_string(), "[File:name = 'a']");
+        assert_eq!(warnings[0].message, "unknown object type 'File'");
+
+        let options = ParseOptions {
+            normalize_object_types: true,
+            ..Default::default()
+        };
+        let (expr, warnings) =
+            parse_pattern_with_diagnostics("[File:name = 'a' AND file:size = 1]", &options)
+                .unwrap();
+        assert_eq!(expr.to_string(), "[file:name = 'a' AND file:size = 1]");
+        assert_eq!(
+            warnings,
+            [ParseWarning {
+                message: "object type 'File' normalized to 'file'".into(),
+                start: 1,
+                end: 5,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_normalize_hash_names() {
+        let options = ParseOptions {
+            normalize_hash_names: true,
+            ..Default::default()
+        };
+        let (expr, warnings) = parse_pattern_with_diagnostics(
+            "[file:hashes.sha256 = 'a' AND file:hashes.md5 = 'b' \
+             AND file:hashes.'SHA-1' = 'c' AND file:hashes.custom = 'd' \
+             AND file:sha256 = 'e']",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "[file:hashes.'SHA-256' = 'a' AND file:hashes.MD5 = 'b' \
+             AND file:hashes.'SHA-1' = 'c' AND file:hashes.custom = 'd' \
+             AND file:sha256 = 'e']"
+        );
+        assert_eq!(
+            warnings,
+            [
+                ParseWarning {
+                    message: "hash name 'sha256' normalized to 'SHA-256'".into(),
+                    start: 13,
+                    end: 19,
+                },
+                ParseWarning {
+                    message: "hash name 'md5' normalized to 'MD5'".into(),
+                    start: 42,
+                    end: 45,
+                },
+            ]
+        );
+        assert_eq!(canonical_hash_name("sha3_512"), Some("SHA3-512"));
+        assert_eq!(canonical_hash_name("Sha-1"), Some("SHA-1"));
+        assert_eq!(canonical_hash_name("sha384"), None);
+
+        let expr = parse_pattern("[file:hashes.sha256 = 'a']").unwrap();
+        assert_eq!(expr.to_string(), "[file:hashes.sha256 = 'a']");
+    }
+
+    #[test]
+    fn test_negated_exists() {
+        assert!(matches!(
+            parse_pattern("[file:name = 'x' AND NOT EXISTS file:size]"),
+            Err(ParseError::NegatedExists(21))
+        ));
+        assert!(matches!(
+            parse_pattern("[file:size NOT EXISTS]"),
+            Err(ParseError::Grammar(_))
+        ));
+    }
+
+    #[test]
+    fn test_intern_strings() {
+        let options = ParseOptions {
+            intern_strings: true,
+            ..Default::default()
+        };
+        let a = parse_pattern_with_options("[file:name = 'a']", &options).unwrap();
+        let b =
+            parse_pattern_with_options("[file:size > 1 AND file:name = 'b']", &options).unwrap();
+        let (a, b) = (a.comparisons()[0], b.comparisons()[1]);
+        assert!(Arc::ptr_eq(
+            &a.object_path.object_type,
+            &b.object_path.object_type
+        ));
+        assert!(Arc::ptr_eq(
+            &a.object_path.property_path[0].property,
+            &b.object_path.property_path[0].property
+        ));
+
+        let c = parse_pattern("[file:name = 'c']").unwrap();
+        let c = c.comparisons()[0];
+        assert!(!Arc::ptr_eq(
+            &a.object_path.object_type,
+            &c.object_path.object_type
+        ));
+        assert_eq!(a.object_path, c.object_path);
+
+        // Names of dropped patterns are pruned as the pool grows.
+        for i in 0..10 * MIN_PRUNE_AT {
+            parse_pattern_with_options(&format!("[x-{i}:name = 'a']"), &options).unwrap();
+        }
+        let len = INTERN_POOL.lock().unwrap().names.len();
+        assert!(len <= MIN_PRUNE_AT, "{len}");
+        let d = parse_pattern_with_options("[file:name = 'd']", &options).unwrap();
+        assert!(Arc::ptr_eq(
+            &a.object_path.object_type,
+            &d.comparisons()[0].object_path.object_type
+        ));
+
+        clear_intern_pool();
+        let e = parse_pattern_with_options("[file:name = 'e']", &options).unwrap();
+        assert!(!Arc::ptr_eq(
+            &a.object_path.object_type,
+            &e.comparisons()[0].object_path.object_type
+        ));
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "/* lead */ [file:name = '/* not */' /* c1 */ AND file:size > 3] /**/";
+        assert!(matches!(parse_pattern(input), Err(ParseError::Comment(0))));
+        assert!(parse_pattern("[file:name = '/* not a comment */']").is_ok());
+        assert!(parse_pattern("[file:name = '/* x'] /* open").is_err());
+
+        let options = ParseOptions {
+            allow_comments: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_pattern_with_options(input, &options).unwrap(),
+            parse_pattern("[file:name = '/* not */' AND file:size > 3]").unwrap()
+        );
+        assert_eq!(
+            find_comments(input),
+            [
+                PatternComment {
+                    text: "lead".into(),
+                    start: 0,
+                    end: 10,
+                },
+                PatternComment {
+                    text: "c1".into(),
+                    start: 36,
+                    end: 44,
+                },
+                PatternComment {
+                    text: String::new(),
+                    start: 64,
+                    end: 68,
+                },
+            ]
+        );
+        assert!(parse_pattern_with_options("[file:na/**/me = 'x']", &options).is_err());
+
+        // Constants holding comments are written without them, so the
+        // output parses without `allow_comments`.
+        for (input, expected) in [
+            ("[a:x IN (1, /* x */ 2)]", "[a:x IN (1, 2)]"),
+            ("[a:x = h /* x */ 'ff']", "[a:x = h'ff']"),
+            ("[a:x IN ('/*','b') /* x */]", "[a:x IN ('/*','b')]"),
+        ] {
+            let ast = parse_pattern_with_options(input, &options).unwrap();
+            assert_eq!(ast.to_string(), expected);
+            assert_eq!(parse_pattern(&ast.to_string()).unwrap(), ast);
+        }
+    }
+
+    #[test]
+    fn test_grammar_error_display() {
+        let err = parse_pattern("[file:name = 'a'] AND\n[b:y = ]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Grammar error at 2:8: expected value, list, or object\n  \
+               |\n\
+             2 | [b:y = ]\n  \
+               |        ^"
+        );
+    }
+
+    #[test]
+    fn test_observation_precedence() {
+        let shape = |input: &str| parse_pattern(input).unwrap().to_string();
+        assert_eq!(
+            shape("[a:x = 1] AND [b:x = 1] FOLLOWEDBY [c:x = 1]"),
+            "([a:x = 1] AND [b:x = 1]) FOLLOWEDBY [c:x = 1]"
+        );
+        assert_eq!(
+            shape("[a:x = 1] FOLLOWEDBY [b:x = 1] AND [c:x = 1]"),
+            "[a:x = 1] FOLLOWEDBY ([b:x = 1] AND [c:x = 1])"
+        );
+        assert_eq!(
+            shape("[a:x = 1] OR [b:x = 1] AND [c:x = 1] FOLLOWEDBY [d:x = 1] OR [e:x = 1]"),
+            "([a:x = 1] OR ([b:x = 1] AND [c:x = 1])) FOLLOWEDBY ([d:x = 1] OR [e:x = 1])"
+        );
+        assert_eq!(
+            shape("[a:x = 1] FOLLOWEDBY [b:x = 1] FOLLOWEDBY [c:x = 1]"),
+            "[a:x = 1] FOLLOWEDBY [b:x = 1] FOLLOWEDBY [c:x = 1]"
+        );
+        assert_eq!(
+            shape("([a:x = 1] FOLLOWEDBY [b:x = 1]) AND [c:x = 1]"),
+            "([a:x = 1] FOLLOWEDBY [b:x = 1]) AND [c:x = 1]"
+        );
+
+        let expr = parse_pattern("[a:x = 1] OR [b:x = 1] AND [c:x = 1]").unwrap();
+        let PatternExpr::Composite(or) = &expr else {
+            panic!("expected a composite, got {expr:?}");
+        };
+        assert_eq!(or.op, ObservationOp::Or);
+        assert_eq!((or.span.start(), or.span.end()), (Some(0), Some(36)));
+        let PatternExpr::Composite(and) = &*or.right else {
+            panic!("expected a composite, got {:?}", or.right);
+        };
+        assert_eq!(and.op, ObservationOp::And);
+        assert_eq!((and.span.start(), and.span.end()), (Some(13), Some(36)));
+    }
+
+    #[test]
+    fn test_comparison_precedence() {
+        let shape = |input: &str| parse_pattern(input).unwrap().to_string();
+        assert_eq!(
+            shape("[file:a = 1 OR file:b = 2 AND file:c = 3]"),
+            "[file:a = 1 OR (file:b = 2 AND file:c = 3)]"
+        );
+        assert_eq!(
+            shape("[file:a = 1 AND file:b = 2 OR file:c = 3 AND file:d = 4]"),
+            "[(file:a = 1 AND file:b = 2) OR (file:c = 3 AND file:d = 4)]"
+        );
+        assert_eq!(
+            shape("[(file:a = 1 OR file:b = 2) AND file:c = 3]"),
+            "[(file:a = 1 OR file:b = 2) AND file:c = 3]"
+        );
+        assert_eq!(
+            shape("[file:a = 1 OR file:b = 2 OR file:c = 3]"),
+            "[file:a = 1 OR file:b = 2 OR file:c = 3]"
+        );
+        assert_eq!(
+            parse_comparison_expr("file:a = 1 OR file:b = 2 AND file:c = 3")
+                .unwrap()
+                .to_string(),
+            "file:a = 1 OR (file:b = 2 AND file:c = 3)"
+        );
+
+        let expr = parse_pattern("[a:x = 1 OR a:y = 2 AND a:z = 3]").unwrap();
+        let PatternExpr::Comparison(ComparisonExpr::Composite(or)) = &expr else {
+            panic!("expected a composite, got {expr:?}");
+        };
+        assert_eq!(or.op, BooleanOp::Or);
+        assert_eq!((or.span.start(), or.span.end()), (Some(1), Some(31)));
+        let ComparisonExpr::Composite(and) = &*or.right else {
+            panic!("expected a composite, got {:?}", or.right);
+        };
+        assert_eq!(and.op, BooleanOp::And);
+        assert!(!and.parenthesized);
+        assert_eq!((and.span.start(), and.span.end()), (Some(12), Some(31)));
+    }
+
+    #[test]
+    fn test_parse_recover() {
+        let options = ParseOptions::default();
+        let input = "[a:x = 1] AND [b:y = ] OR ([c:z = 3] FOLLOWEDBY [d:w @ 4]) OR [e:v = 5]";
+        let (expr, errors) = parse_pattern_recover(input, &options);
+        assert_eq!(expr.unwrap().to_string(), "[a:x = 1] OR [e:v = 5]");
+        let offsets: Vec<_> = errors
+            .iter()
+            .map(|err| match err {
+                ParseError::Grammar(err) => match err.location {
+                    pest::error::InputLocation::Pos(pos) => pos,
+                    pest::error::InputLocation::Span((start, _)) => start,
+                },
+                _ => panic!("unexpected error {err}"),
+            })
+            .collect();
+        assert_eq!(offsets, [21, 53]);
+
+        let (expr, errors) = parse_pattern_recover("[a:x = 1] AND", &options);
+        assert_eq!(expr.unwrap().to_string(), "[a:x = 1]");
+        assert_eq!(errors.len(), 1);
+        let (expr, errors) = parse_pattern_recover("[a:x = 1 AND [b:y = 2]", &options);
+        assert!(expr.is_none());
+        assert_eq!(errors.len(), 1);
+        let (expr, errors) = parse_pattern_recover("[a:x = 'AND'] OR [b:y = 2]", &options);
+        assert!(expr.is_some() && errors.is_empty());
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let input = "\u{FEFF}# indicators\n[a:x = 1]\n\n  # skipped\n[a:x =\r\n  [b:y = 2]  \n";
+        let results = parse_lines(input, &ParseOptions::default());
+        let lines: Vec<_> = results.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 5, 6]);
+        assert_eq!(results[0].1.as_ref().unwrap().to_string(), "[a:x = 1]");
+        let err = results[1].1.as_ref().unwrap_err().to_string();
+        assert!(err.starts_with("Grammar error at 1:7:"), "{err}");
+        assert_eq!(results[2].1.as_ref().unwrap().to_string(), "[b:y = 2]");
+
+        let path = std::env::temp_dir().join(format!("stix-patterns-{}.txt", std::process::id()));
+        std::fs::write(&path, input).unwrap();
+        let from_file = parse_file(&path, &ParseOptions::default());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(from_file.unwrap().len(), 3);
+        assert!(parse_file(&path, &ParseOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let n = 1000;
+        let input = format!("[{}a:x = 1 OR a:y = 2{}]", "(".repeat(n), ")".repeat(n));
+        let ast = parse_pattern(&input).unwrap();
+        assert_eq!(ast, parse_pattern("[(a:x = 1 OR a:y = 2)]").unwrap());
+        assert_eq!(ast.to_string(), "[a:x = 1 OR a:y = 2]");
+
+        let n = 200;
+        let input = format!(
+            "{}[a:x = 1] AND [a:y = 2]{} WITHIN 5 SECONDS",
+            "(".repeat(n),
+            ")".repeat(n)
+        );
+        let ast = parse_pattern(&input).unwrap();
+        assert_eq!(
+            ast,
+            parse_pattern("([a:x = 1] AND [a:y = 2]) WITHIN 5 SECONDS").unwrap()
+        );
+        assert_eq!(ast.span().start(), Some(0));
+    }
+
+    #[test]
+    fn test_operator_arity() {
+        assert!(matches!(
+            parse_pattern("[file:name IN 'x']"),
+            Err(ParseError::OperatorArityMismatch {
+                op: "IN",
+                expected: "a list",
+                offset: 14,
+            })
+        ));
+        assert!(matches!(
+            parse_pattern("[file:size NOT = (1, 2)]"),
+            Err(ParseError::OperatorArityMismatch {
+                op: "=",
+                expected: "a single value",
+                offset: 17,
+            })
+        ));
+        assert!(matches!(
+            parse_pattern("[file:name LIKE ('a')]"),
+            Err(ParseError::OperatorArityMismatch { op: "LIKE", .. })
+        ));
+        let options = ParseOptions {
+            allow_path_rhs: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse_pattern_with_options("[file:name IN file:x]", &options),
+            Err(ParseError::OperatorArityMismatch { op: "IN", .. })
+        ));
+        assert_eq!(
+            parse_pattern("[file:name = 'x' OR file:size IN (1, 2)]")
+                .unwrap()
+                .to_string(),
+            "[file:name = 'x' OR file:size IN (1, 2)]"
+        );
+    }
+
+    #[test]
+    fn test_parse_comparison_expr() {
+        let expr =
+            parse_comparison_expr("file:size > 100 AND (file:name LIKE 'a%' OR EXISTS file:x)")
+                .unwrap();
+        assert_eq!(
+            PatternExpr::from(expr.clone()),
+            parse_pattern("[file:size > 100 AND (file:name LIKE 'a%' OR EXISTS file:x)]").unwrap()
+        );
+        assert_eq!(expr.span(), SourceSpan::new(0, 59));
+
+        for input in [
+            "[file:size > 100]",
+            "file:size > 100 REPEATS 2 TIMES",
+            "file:size > 100 FOLLOWEDBY file:size > 1",
+            "",
+        ] {
+            assert!(
+                matches!(parse_comparison_expr(input), Err(ParseError::Grammar(_))),
+                "{input}"
+            );
+        }
+        assert!(matches!(
+            parse_comparison_expr("file:size > 100 and file:x = 1"),
+            Err(ParseError::KeywordCase(_))
+        ));
+        let options = ParseOptions {
+            max_operands: Some(1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse_comparison_expr_with_options("a:x = 1 OR a:y = 2", &options),
+            Err(ParseError::TooManyOperands { .. })
+        ));
+    }
+
+    #[test]
+    fn test_string_whitespace() {
+        for text in ["  a b ", "/* c */", "\t"] {
+            let input = format!("[a:x = '{text}']");
+            let PatternExpr::Comparison(ComparisonExpr::Single(c)) = parse_pattern(&input).unwrap()
+            else {
+                panic!("expected a single comparison for {input}");
+            };
+            assert_eq!(
+                c.constant,
+                Some(StixValue::String(text.into()).into()),
+                "{input}"
+            );
+        }
+
+        // The prefix of a typed constant may still be spaced from its quote.
+        for constant in ["t'2020-01-01T00:00:00Z'", "h'ab'", "b'AQID'"] {
+            let expected = parse_pattern(&format!("[a:x = {constant}]")).unwrap();
+            let spaced = constant.replacen('\'', " \t'", 1);
+            assert_eq!(
+                parse_pattern(&format!("[a:x = {spaced}]")).unwrap(),
+                expected
+            );
+            let commented = constant.replacen('\'', " /* c */'", 1);
+            let input = format!("[a:x = {commented}]");
+            assert!(matches!(parse_pattern(&input), Err(ParseError::Comment(9))));
+            let options = ParseOptions {
+                allow_comments: true,
+                ..Default::default()
+            };
+            assert_eq!(
+                parse_pattern_with_options(&input, &options).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_chains() {
+        let n = 10_000;
+        let comparisons: Vec<String> = (0..n).map(|i| format!("a:x{i} = {i}")).collect();
+        let values: Vec<String> = (0..n).map(|i| i.to_string()).collect();
+        let input = format!(
+            "[{} OR a:y IN ({})]",
+            comparisons.join(" AND "),
+            values.join(", ")
+        );
+        let ast = parse_pattern(&input).unwrap();
+        let PatternExpr::Comparison(ComparisonExpr::Composite(c)) = &ast else {
+            panic!("expected a composite observation, got {ast:?}");
+        };
+        assert_eq!(c.op, BooleanOp::Or);
+        assert!(matches!(
+            c.right.as_ref(),
+            ComparisonExpr::Single(Comparison {
+                constant: Some(ComparisonRhs::List(values)),
+                ..
+            }) if values.len() == n
+        ));
+        assert_eq!(ast.comparisons().len(), n + 1);
+        assert_eq!(ast.span().end(), Some(input.len() - 1));
+
+        // Walks over the chain must not recurse once per operand.
+        assert!(crate::lint::lint(&ast).is_empty());
+        let json = crate::json::to_json(&ast);
+        assert!(json.starts_with(
+            r#"{"ast_version":2,"root":{"type":"CompositeComparison","left":{"type":"CompositeComparison","#
+        ));
+        assert!(json.ends_with(r#""parenthesized":false,"negated":false}}"#));
+        let simplified = crate::transform::simplify(ast.clone());
+        assert_eq!(simplified.comparisons().len(), n + 1);
+        let normalized = crate::transform::normalize(ast.clone());
+        assert_eq!(normalized.comparisons().len(), n + 1);
+        assert!(crate::transform::equivalent(&ast, &normalized));
+        assert_eq!(simplified.to_string(), ast.to_string());
+        let redacted = crate::transform::redact(ast.clone(), "x");
+        assert_eq!(redacted, ast);
+        let data = crate::eval::Data::Object(
+            [
+                ("type".to_owned(), crate::eval::Data::String("a".to_owned())),
+                ("y".to_owned(), crate::eval::Data::Int(5)),
+            ]
+            .into(),
+        );
+        assert!(crate::eval::matches_data(&ast, &data).unwrap());
+        let columns = (0..n)
+            .map(|i| (format!("a:x{i}"), format!("x{i}")))
+            .chain([("a:y".to_owned(), "y".to_owned())])
+            .collect();
+        let (sql, params) = crate::sql::to_sql(&ast, &columns).unwrap();
+        assert!(sql.starts_with("(x0 = ? AND x1 = ?"));
+        assert_eq!(params.len(), 2 * n);
+        let dot = crate::dot::to_dot(&ast);
+        assert_eq!(dot.matches(" -> ").count(), 2 * n + 1);
+
+        let observations: Vec<String> = (0..n).map(|i| format!("[a:x = '{i}']")).collect();
+        let input = format!("({}) WITHIN 5 SECONDS", observations.join(" AND "));
+        let ast = parse_pattern(&input).unwrap();
+        assert_eq!(ast.to_string(), input);
+        assert_eq!(ast, parse_pattern(&input).unwrap());
+        let redacted = crate::transform::redact(ast.clone(), "x");
+        let redacted_input = vec!["[a:x = 'x']"; n].join(" AND ");
+        assert_eq!(
+            redacted.to_string(),
+            format!("({redacted_input}) WITHIN 5 SECONDS")
+        );
+        let stripped = crate::transform::strip_qualifiers(ast.clone());
+        assert_eq!(stripped.to_string(), observations.join(" AND "));
+        let json = crate::json::to_json(&ast);
+        assert_eq!(json.matches(r#""type":"CompositePattern""#).count(), n - 1);
+        let dot = crate::dot::to_dot(&ast);
+        assert_eq!(dot.matches(" -> ").count(), 3 * n - 1);
+    }
+
+    #[test]
+    fn test_between() {
+        let input = "[file:size BETWEEN 1 AND 10.5 AND file:name = 'x']";
+        assert!(matches!(parse_pattern(input), Err(ParseError::Between(1))));
+
+        let options = ParseOptions {
+            allow_between: true,
+            ..Default::default()
+        };
+        let (ast, warnings) = parse_pattern_with_diagnostics(input, &options).unwrap();
+        assert_eq!(
+            ast,
+            parse_pattern("[file:size >= 1 AND file:size <= 10.5 AND file:name = 'x']").unwrap()
+        );
+        assert_eq!(warnings[0].message, "BETWEEN expanded to >= and <=");
+        assert_eq!(
+            parse_pattern_with_options("[file:name = 'x' OR file:size BETWEEN 1 AND 10]", &options)
+                .unwrap()
+                .to_string(),
+            "[file:name = 'x' OR (file:size >= 1 AND file:size <= 10)]"
+        );
+        assert!(matches!(
+            parse_pattern_with_options("[file:size between 1 and 10]", &options),
+            Err(ParseError::KeywordCase(_))
+        ));
+        let limited = ParseOptions {
+            max_operands: Some(1),
+            ..options
+        };
+        assert!(parse_pattern_with_options("[file:size BETWEEN 1 AND 10]", &limited).is_err());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let input = "[network-traffic:dst_port IN (-1..2, 8, 80 .. 81)]";
+        assert!(matches!(parse_pattern(input), Err(ParseError::Range(30))));
+
+        let options = ParseOptions {
+            allow_ranges: true,
+            ..Default::default()
+        };
+        let (ast, warnings) = parse_pattern_with_diagnostics(input, &options).unwrap();
+        assert_eq!(
+            ast.to_string(),
+            "[network-traffic:dst_port IN (-1, 0, 1, 2, 8, 80, 81)]"
+        );
+        assert!(ast.comparisons()[0].has_ranges);
+        // Equal to the enumeration it was expanded to.
+        let expanded = parse_pattern(&ast.to_string()).unwrap();
+        assert!(!expanded.comparisons()[0].has_ranges);
+        assert_eq!(expanded, ast);
+        let messages: Vec<_> = warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "range -1..2 expanded to 4 values",
+                "range 80 .. 81 expanded to 2 values"
+            ]
+        );
+        assert_eq!(
+            parse_pattern_with_options("[network-traffic:dst_port IN (1..1024)]", &options)
+                .unwrap()
+                .constants()
+                .len(),
+            1024
+        );
+        // The limit applies to the ranges of a list together.
+        let ranges = vec!["1..1024"; 1000].join(", ");
+        let too_many = format!("[network-traffic:dst_port IN ({ranges})]");
+        for range in [
+            "[network-traffic:dst_port IN (3..1)]",
+            "[network-traffic:dst_port IN (1..1025)]",
+            "[network-traffic:dst_port IN (1..1000, 2000..2024, 5)]",
+            &too_many,
+        ] {
+            assert!(matches!(
+                parse_pattern_with_options(range, &options),
+                Err(ParseError::InvalidRange(_))
+            ));
+        }
+        assert!(
+            parse_pattern_with_options("[network-traffic:dst_port IN (1.5..3)]", &options).is_err()
+        );
+        assert!(parse_pattern_with_options("[network-traffic:dst_port = 1..3]", &options).is_err());
+    }
+
+    #[test]
+    fn test_grouped_negation() {
+        let input = "[a:x = 1 AND NOT (a:y = 2 OR a:z = 3)]";
+        assert!(matches!(
+            parse_pattern(input),
+            Err(ParseError::GroupedNegation(13))
+        ));
+
+        let options = ParseOptions {
+            allow_grouped_negation: true,
+            ..Default::default()
+        };
+        let parse = |input: &str| parse_pattern_with_options(input, &options);
+        let ast = parse(input).unwrap();
+        let PatternExpr::Comparison(ComparisonExpr::Composite(and)) = &ast else {
+            panic!("expected a composite, got {ast:?}");
+        };
+        let ComparisonExpr::Composite(or) = &*and.right else {
+            panic!("expected a composite, got {:?}", and.right);
+        };
+        assert!(or.negated && or.parenthesized);
+        assert!(!and.negated);
+        assert_eq!(ast.to_string(), input);
+
+        let input = "[NOT (a:x = 1 AND a:y = 2) AND a:z = 3]";
+        assert_eq!(parse(input).unwrap().to_string(), input);
+        assert_eq!(
+            parse("[NOT (a:x = 1)]").unwrap().to_string(),
+            "[a:x NOT = 1]"
+        );
+        assert_eq!(
+            parse("[NOT (a:x NOT = 1)]").unwrap().to_string(),
+            "[a:x = 1]"
+        );
+        let input = "[NOT (a:x = 1 AND a:y = 2)]";
+        assert_eq!(
+            parse("[(NOT (a:x = 1 AND a:y = 2))]").unwrap().to_string(),
+            input
+        );
+        assert_eq!(
+            parse("[NOT ((a:x = 1 AND a:y = 2))]").unwrap().to_string(),
+            input
+        );
+        assert_eq!(
+            parse("[NOT (NOT (a:x = 1 AND a:y = 2))]")
+                .unwrap()
+                .to_string(),
+            "[a:x = 1 AND a:y = 2]"
+        );
+        assert_eq!(
+            parse("[NOT (NOT (a:x = 1))]").unwrap().to_string(),
+            "[a:x = 1]"
+        );
+        assert!(matches!(
+            parse("[NOT (EXISTS a:x)]"),
+            Err(ParseError::NegatedExists(1))
+        ));
+    }
+
+    #[test]
+    fn test_double_quotes() {
+        let input = r#"[file:name = "it's \"x\"" AND file:path IN ("a", 'b')]"#;
+        assert!(matches!(
+            parse_pattern(input),
+            Err(ParseError::DoubleQuotes(13))
+        ));
+
+        let options = ParseOptions {
+            allow_double_quotes: true,
+            ..Default::default()
+        };
+        let ast = parse_pattern_with_options(input, &options).unwrap();
+        assert_eq!(
+            ast,
+            parse_pattern(r#"[file:name = 'it\'s "x"' AND file:path IN ('a', 'b')]"#).unwrap()
+        );
+        assert_eq!(
+            ast.to_string(),
+            r#"[file:name = 'it\'s "x"' AND file:path IN ('a', 'b')]"#
+        );
+        let with_comment = r#"[file:name = "/* x */"] /* c */"#;
+        assert_eq!(find_comments(with_comment).len(), 1);
+    }
+
+    #[test]
+    fn test_int_out_of_range() {
+        let err = parse_pattern("[a:x = 99999999999999999999999]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Integer 99999999999999999999999 is out of range, expected \
+             -9223372036854775808 to 9223372036854775807"
+        );
+        assert!(parse_pattern("[a:x = -9223372036854775808]").is_ok());
+        assert!(matches!(
+            parse_pattern("[a:x = -9223372036854775809]"),
+            Err(ParseError::IntOutOfRange { min: i64::MIN, .. })
+        ));
+        assert!(matches!(
+            parse_pattern("[a:x = 1] REPEATS 4294967296 TIMES"),
+            Err(ParseError::IntOutOfRange {
+                min: 0,
+                max: 4294967295,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_float_literals() {
+        match parse_pattern("[a:x = -0.0]").unwrap() {
+            PatternExpr::Comparison(ComparisonExpr::Single(Comparison {
+                constant: Some(ComparisonRhs::Value(StixValue::Float(f))),
+                ..
+            })) => assert!(f == 0.0 && f.is_sign_negative()),
+            other => panic!("expected a float comparison, got {other:?}"),
+        }
+
+        assert!(matches!(
+            parse_pattern("[a:x = 1.5e3]"),
+            Err(ParseError::Exponent(7))
+        ));
+        for input in ["[a:x = inf]", "[a:x = NaN]", "[a:x = -infinity]"] {
+            assert!(
+                matches!(parse_pattern(input), Err(ParseError::Grammar(_))),
+                "{input}"
+            );
+        }
+
+        let huge = format!("[a:x = 1{}.0]", "0".repeat(400));
+        assert!(matches!(
+            parse_pattern(&huge),
+            Err(ParseError::NonFiniteFloat(_))
+        ));
+        let huge = format!("[a:x = 1] WITHIN {} SECONDS", "9".repeat(400));
+        assert!(matches!(
+            parse_pattern(&huge),
+            Err(ParseError::NonFiniteFloat(_))
+        ));
+    }
+
+    #[test]
+    fn test_reversed_comparison() {
+        assert!(matches!(
+            parse_pattern("[file:size > 1 AND 'x' = file:name]"),
+            Err(ParseError::ReversedComparison(19))
+        ));
+
+        let options = ParseOptions {
+            allow_reversed_comparison: true,
+            ..Default::default()
+        };
+        let (expr, warnings) = parse_pattern_with_diagnostics(
+            "[10 < file:size AND 'x' NOT = file:name AND 5 >= file:size]",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "[file:size > 10 AND file:name NOT = 'x' AND file:size <= 5]"
+        );
+        assert_eq!(warnings.len(), 3);
+        assert_eq!((warnings[0].start, warnings[0].end), (1, 15));
+
+        assert!(matches!(
+            parse_pattern_with_options("['a%' LIKE file:name]", &options),
+            Err(ParseError::NotSwappable {
+                op: "LIKE",
+                offset: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_bare_observation() {
+        let input = "file:name = 'x' AND file:size > 3";
+        assert!(matches!(parse_pattern(input), Err(ParseError::Grammar(_))));
+
+        let options = ParseOptions {
+            allow_bare_observation: true,
+            ..Default::default()
+        };
+        let (expr, warnings) = parse_pattern_with_diagnostics(input, &options).unwrap();
+        assert_eq!(expr, parse_pattern(&format!("[{input}]")).unwrap());
+        assert_eq!(expr.to_string(), format!("[{input}]"));
+        assert_eq!(
+            warnings,
+            [ParseWarning {
+                message: "observation without brackets".into(),
+                start: 0,
+                end: input.len(),
+            }]
+        );
+
+        let (_, warnings) =
+            parse_pattern_with_diagnostics("[file:name = 'x'] AND [file:size > 3]", &options)
+                .unwrap();
+        assert!(warnings.is_empty());
+        assert!(
+            parse_pattern_with_options("file:name = 'x' AND [file:size > 3]", &options).is_err()
+        );
+    }
+
+    #[test]
+    fn test_unicode_escapes() {
+        assert_eq!(unescape_string(r"caf\u00e9.exe", '\'').unwrap(), "café.exe");
+        assert_eq!(unescape_string(r"\x41\x42", '\'').unwrap(), "AB");
+        assert_eq!(unescape_string(r"\uD83D\uDE00", '\'').unwrap(), "\u{1F600}");
+        assert_eq!(unescape_string(r"C:\dir", '\'').unwrap(), r"C:\dir");
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        for input in [r"\u00g1", r"\u12", r"\xZ1", r"\uD83D", r"\uDE00"] {
+            assert!(
+                matches!(
+                    unescape_string(input, '\''),
+                    Err(ParseError::InvalidEscape(_))
+                ),
+                "{input}"
+            );
+        }
+        assert!(parse_pattern(r"[file:name = 'caf\u00e']").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn test_validate_regex() {
+        let opts = ParseOptions {
+            validate_regex: true,
+            ..Default::default()
+        };
+        assert!(parse_pattern_with_options("[file:name MATCHES '^a.*\\.exe$']", &opts).is_ok());
+        assert!(matches!(
+            parse_pattern_with_options("[file:name MATCHES 'a(b']", &opts),
+            Err(ParseError::InvalidRegex(_))
+        ));
+        assert!(parse_pattern("[file:name MATCHES 'a(b']").is_ok());
+    }
+
+    #[test]
+    fn test_strict() {
+        let lenient = ParseOptions {
+            allow_empty_lists: true,
+            allow_exponent: true,
+            ..Default::default()
+        };
+        let strict = ParseOptions {
+            strict: true,
+            ..lenient.clone()
+        };
+        let parse_strict = |input: &str| parse_pattern_with_options(input, &strict);
+
+        let inputs = [
+            "[a:t = t'2023-01-01T00:00:00']",
+            "[a:x IN ()]",
+            "[a:h = h'abc']",
+            "[a:b = b'YWJj=']",
+            "[a:x = 1] REPEATS 0 TIMES",
+            "[a:x = 1] START t'2023-01-02T00:00:00Z' STOP t'2023-01-01T00:00:00Z'",
+            "[a:x = 1.0e3]",
+        ];
+        for input in inputs {
+            assert!(
+                parse_pattern_with_options(input, &lenient).is_ok(),
+                "{input}"
+            );
+        }
+
+        assert!(matches!(
+            parse_strict(inputs[0]),
+            Err(ParseError::TimestampWithoutZone(ts)) if ts == "2023-01-01T00:00:00"
+        ));
+        assert!(matches!(
+            parse_strict(inputs[1]),
+            Err(ParseError::EmptyList(_))
+        ));
+        assert!(matches!(
+            parse_strict(inputs[2]),
+            Err(ParseError::InvalidHex(_))
+        ));
+        assert!(matches!(
+            parse_strict(inputs[3]),
+            Err(ParseError::InvalidBase64(_))
+        ));
+        assert!(matches!(
+            parse_strict(inputs[4]),
+            Err(ParseError::NonPositiveRepeat(10))
+        ));
+        assert!(matches!(
+            parse_strict(inputs[5]),
+            Err(ParseError::EmptyInterval(10))
+        ));
+        assert!(matches!(
+            parse_strict(inputs[6]),
+            Err(ParseError::Exponent(7))
+        ));
+        #[cfg(feature = "regex")]
+        assert!(matches!(
+            parse_strict("[a:x MATCHES 'a(b']"),
+            Err(ParseError::InvalidRegex(_))
+        ));
+        #[cfg(not(feature = "regex"))]
+        assert!(parse_strict("[a:x MATCHES 'a(b']").is_ok());
+
+        assert!(
+            parse_strict(
+                "[a:t = t'2023-01-01T00:00:00.5+02:00' AND a:h = h'abcd' AND a:b = b'YWI=' \
+                 AND a:x IN (1, 2)] REPEATS 2 TIMES \
+                 START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'"
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_cidr_operands() {
+        let warnings = |input: &str| {
+            let (_, warnings) =
+                parse_pattern_with_diagnostics(input, &ParseOptions::default()).unwrap();
+            warnings
+                .into_iter()
+                .map(|w| (w.message, w.start))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(warnings("[ipv4-addr:value ISSUBSET '198.51.100.0/24']"), []);
+        assert_eq!(warnings("[ipv6-addr:value ISSUPERSET '2001:db8::1']"), []);
+        assert_eq!(
+            warnings("[ipv4-addr:value ISSUBSET '198.51.100.0/33']"),
+            [(
+                "'198.51.100.0/33' is not an ipv4-addr CIDR range".to_owned(),
+                26
+            )]
+        );
+        assert_eq!(
+            warnings("[ipv4-addr:value ISSUBSET '2001:db8::/32']").len(),
+            1
+        );
+        assert_eq!(warnings("[x-foo:value ISSUBSET 'abc']"), []);
+
+        let strict = ParseOptions {
+            strict: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse_pattern_with_options("[ipv6-addr:value ISSUBSET 'abc']", &strict),
+            Err(ParseError::InvalidCidr(range)) if range == "abc"
+        ));
+    }
+
+    #[test]
+    fn test_surrounding_whitespace_and_bom() {
+        for input in [
+            "\u{feff}  [file:name='x']  ",
+            "\u{feff}[file:name = 'x']",
+            "\n\t  [file:name = 'x']\r\n\r\n",
+            "\u{feff}\r\n[file:name = 'x'\n AND\n file:size = 1]\n",
+        ] {
+            let expr = parse_pattern(input).unwrap_or_else(|e| panic!("{input:?}: {e}"));
+            assert!(expr.to_string().starts_with("[file:name = 'x'"));
+        }
+
+        let expr = parse_pattern("\u{feff} [file:name = 'x']").unwrap();
+        // Offsets count the three bytes of the BOM.
+        assert_eq!(expr.span().start(), Some(5));
+        assert!(parse_pattern("[file:name = 'x']\u{feff}").is_err());
+        assert!(parse_pattern("\u{feff}\u{feff}[file:name = 'x']").is_err());
+    }
+
+    #[test]
+    fn test_lowercase_keywords_rejected_by_default() {
+        assert!(matches!(
+            parse_pattern("[file:name like 'x']"),
+            Err(ParseError::KeywordCase(kw)) if kw == "like"
+        ));
+    }
+
+    #[test]
+    fn test_case_insensitive_keywords() {
+        let opts = ParseOptions {
+            case_insensitive_keywords: true,
+            ..Default::default()
+        };
+        for (input, canonical) in [
+            ("[a:b in (1, 2)]", "[a:b IN (1, 2)]"),
+            ("[a:b like 'x']", "[a:b LIKE 'x']"),
+            ("[a:b Matches 'x']", "[a:b MATCHES 'x']"),
+            ("[a:b issubset '10.0.0.0/8']", "[a:b ISSUBSET '10.0.0.0/8']"),
+            ("[a:b issuperset '10.0.0.1']", "[a:b ISSUPERSET '10.0.0.1']"),
+            ("[exists a:b]", "[EXISTS a:b]"),
+            ("[a:b = 1 and a:c = 2]", "[a:b = 1 AND a:c = 2]"),
+            ("[a:b = 1 or a:c = 2]", "[a:b = 1 OR a:c = 2]"),
+            ("[a:b not = 1]", "[a:b NOT = 1]"),
+            (
+                "[a:b = 1] followedby [a:c = 2]",
+                "[a:b = 1] FOLLOWEDBY [a:c = 2]",
+            ),
+            ("[a:b = 1] repeats 2 times", "[a:b = 1] REPEATS 2 TIMES"),
+            ("[a:b = 1] within 5 seconds", "[a:b = 1] WITHIN 5 SECONDS"),
+            (
+                "[a:b = 1] start t'2023-01-01T00:00:00Z' stop t'2023-01-02T00:00:00Z'",
+                "[a:b = 1] START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'",
+            ),
+        ] {
+            assert_eq!(
+                parse_pattern_with_options(input, &opts)
+                    .unwrap()
+                    .to_string(),
+                canonical
+            );
+            assert!(
+                matches!(parse_pattern(input), Err(ParseError::KeywordCase(_))),
+                "{input}"
+            );
+        }
+    }
+
+    fn single_constant(input: &str) -> Option<ComparisonRhs> {
+        match parse_pattern(input).unwrap() {
+            PatternExpr::Comparison(ComparisonExpr::Single(c)) => c.constant,
+            other => panic!("unexpected expression: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_timestamp_rhs_on_ordering_operators() {
+        for op in [">=", "<=", ">", "<"] {
+            let input = format!("[network-traffic:start {op} t'2023-01-01T00:00:00Z']");
+            let expected = DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc);
+            assert_eq!(
+                single_constant(&input),
+                Some(StixValue::Timestamp(expected).into())
+            );
+        }
+        assert!(matches!(
+            single_constant("[network-traffic:start >= t'2023-01-01T00:00:00.250+02:00']"),
+            Some(ComparisonRhs::Value(StixValue::Timestamp(_)))
+        ));
+    }
+
+    #[test]
+    fn test_malformed_timestamp_rhs() {
+        for input in [
+            "[network-traffic:start >= t'2023-13-01T00:00:00Z']",
+            "[network-traffic:start <= t'yesterday at noon']",
+        ] {
+            assert!(
+                matches!(parse_pattern(input), Err(ParseError::InvalidTimestamp(_))),
+                "{input}"
+            );
+        }
+    }
 }
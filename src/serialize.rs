@@ -0,0 +1,924 @@
+//! Serialization of the AST back into STIX pattern strings.
+//!
+//! The `Display` impls produce a canonical single-line pattern (the
+//! alternate form `{:#}` keeps the source's grouping parentheses), while
+//! `format_pattern` produces an indented multi-line layout. Both re-parse
+//! into an equal AST: only the grouping flags of composites can differ,
+//! and they do not take part in equality.
+
+use std::fmt::{self, Display, Formatter};
+
+use chrono::SecondsFormat;
+
+use crate::ast::{
+    Comparison, ComparisonExpr, ComparisonOperator, ComparisonRhs, CompositeComparison,
+    CompositePattern, ListIndex, ObjectPath, PathComponent, PatternExpr, PropertyKind,
+    QualifiedPattern, StixValue,
+};
+
+/// How floats are written when there is no source text to keep.
+///
+/// The default writes the shortest digits that re-parse to the same value,
+/// with at least one decimal so that `1.0` stays a float literal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatFormat {
+    /// Minimum number of digits after the decimal point, padded with
+    /// zeros. With 0, integral values are written without a fraction and
+    /// re-parse as integers.
+    pub min_decimals: usize,
+    /// Write values whose decimal exponent is at least this large in
+    /// magnitude in scientific notation, as in `1.5e20` or `2.0e-9`. STIX
+    /// has no exponent syntax, so these only re-parse with
+    /// `ParseOptions::allow_exponent`. Never when `None`.
+    pub scientific_threshold: Option<u32>,
+}
+
+impl Default for FloatFormat {
+    fn default() -> Self {
+        Self {
+            min_decimals: 1,
+            scientific_threshold: None,
+        }
+    }
+}
+
+impl FloatFormat {
+    /// Format `v` under this policy.
+    #[must_use]
+    pub fn format(&self, v: f64) -> String {
+        if let Some(threshold) = self.scientific_threshold {
+            let scientific = format!("{v:e}");
+            if let Some((mantissa, exponent)) = scientific.split_once('e')
+                && v != 0.0
+                && exponent
+                    .parse::<i32>()
+                    .is_ok_and(|e| e.unsigned_abs() >= threshold)
+            {
+                return format!(
+                    "{}e{exponent}",
+                    pad_decimals(mantissa, self.min_decimals.max(1))
+                );
+            }
+        }
+        pad_decimals(&v.to_string(), self.min_decimals)
+    }
+}
+
+/// Append zeros to the digits `s` until it has `min` decimals.
+fn pad_decimals(s: &str, min: usize) -> String {
+    let decimals = s.split_once('.').map_or(0, |(_, fraction)| fraction.len());
+    let mut out = s.to_owned();
+    if decimals < min {
+        if decimals == 0 {
+            out.push('.');
+        }
+        out.extend(std::iter::repeat_n('0', min - decimals));
+    }
+    out
+}
+
+/// A node written with a float format, so that the policy reaches every
+/// constant below it.
+struct Styled<'a, T: ?Sized> {
+    node: &'a T,
+    floats: FloatFormat,
+}
+
+impl<'a, T: ?Sized> Styled<'a, T> {
+    fn new(node: &'a T, floats: FloatFormat) -> Self {
+        Self { node, floats }
+    }
+
+    fn child<U: ?Sized>(&self, node: &'a U) -> Styled<'a, U> {
+        Styled::new(node, self.floats)
+    }
+}
+
+impl Display for Styled<'_, StixValue> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.node {
+            StixValue::String(s) => write!(f, "'{}'", escape_string(s)),
+            StixValue::Int(i) => write!(f, "{i}"),
+            StixValue::Float(v) => f.write_str(&self.floats.format(*v)),
+            StixValue::Bool(b) => write!(f, "{b}"),
+            StixValue::Timestamp(dt) => {
+                write!(f, "t'{}'", dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            StixValue::Hex(s) => write!(f, "h'{s}'"),
+            StixValue::Binary(s) => write!(f, "b'{s}'"),
+            StixValue::Null => f.write_str("null"),
+        }
+    }
+}
+
+impl Display for Styled<'_, ComparisonRhs> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.node {
+            ComparisonRhs::Value(v) => write!(f, "{}", self.child(v)),
+            ComparisonRhs::Path(path) => write!(f, "{path}"),
+            ComparisonRhs::List(values) => {
+                f.write_str("(")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", self.child(v))?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl Display for PathComponent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.kind == PropertyKind::Plain && is_plain_property(&self.property) {
+            f.write_str(&self.property)?;
+        } else {
+            write!(f, "'{}'", escape_string(&self.property))?;
+        }
+        for index in &self.indices {
+            match index {
+                ListIndex::Index(i) => write!(f, "[{i}]")?,
+                ListIndex::Star => f.write_str("[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for ObjectPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.object_type)?;
+        for (i, step) in self.property_path.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{step}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Styled<'_, Comparison> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let c = self.node;
+        match c.op {
+            ComparisonOperator::Unary(op) => write!(f, "{} {}", op.value(), c.object_path),
+            ComparisonOperator::Comparison(op) => {
+                write!(f, "{} ", c.object_path)?;
+                if c.negated {
+                    f.write_str("NOT ")?;
+                }
+                f.write_str(op.value())?;
+                if let Some(rhs) = &c.constant {
+                    match c.raw.text_for(rhs) {
+                        Some(raw) => write!(f, " {raw}")?,
+                        None => write!(f, " {}", self.child(rhs))?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for Styled<'_, CompositeComparison> {
+    /// Long chains nest on the left, so the composites down that side are
+    /// written in a loop: their openings first, then the first comparison,
+    /// then each operator and right operand from the innermost out.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let grouping = f.alternate();
+        let (spine, first) = self.node.left_spine();
+        // Whether each node needs parentheses as the left operand of its
+        // parent.
+        let parens: Vec<bool> = std::iter::once(false)
+            .chain(
+                spine
+                    .windows(2)
+                    .map(|pair| pair[0].needs_parens(&pair[0].left, false, grouping)),
+            )
+            .collect();
+        for (c, &parens) in spine.iter().zip(&parens) {
+            if parens {
+                f.write_str("(")?;
+            }
+            if c.negated {
+                f.write_str("NOT (")?;
+            }
+        }
+        Display::fmt(&self.child(first), f)?;
+        for (c, &parens) in spine.iter().zip(&parens).rev() {
+            write!(f, " {} ", c.op.value())?;
+            fmt_operand(
+                f,
+                &self.child(&*c.right),
+                c.needs_parens(&c.right, true, grouping),
+            )?;
+            if c.negated {
+                f.write_str(")")?;
+            }
+            if parens {
+                f.write_str(")")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Styled<'_, ComparisonExpr> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.node {
+            ComparisonExpr::Single(c) => Display::fmt(&self.child(c), f),
+            ComparisonExpr::Composite(c) => Display::fmt(&self.child(c), f),
+        }
+    }
+}
+
+impl Display for Styled<'_, CompositePattern> {
+    /// Written down the left side in a loop, like [`CompositeComparison`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let grouping = f.alternate();
+        let (spine, first) = self.node.left_spine();
+        // Whether each node needs parentheses as the left operand of its
+        // parent.
+        let parens: Vec<bool> = std::iter::once(false)
+            .chain(
+                spine
+                    .windows(2)
+                    .map(|pair| pair[0].needs_parens(&pair[0].left, false, grouping)),
+            )
+            .collect();
+        for _ in parens.iter().filter(|&&parens| parens) {
+            f.write_str("(")?;
+        }
+        Display::fmt(&self.child(first), f)?;
+        for (c, &parens) in spine.iter().zip(&parens).rev() {
+            write!(f, " {} ", c.op.value())?;
+            fmt_operand(
+                f,
+                &self.child(&*c.right),
+                c.needs_parens(&c.right, true, grouping),
+            )?;
+            if parens {
+                f.write_str(")")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Styled<'_, QualifiedPattern> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let q = self.node;
+        match q.pattern.as_ref() {
+            PatternExpr::Comparison(c) => fmt_observation(f, &self.child(c))?,
+            PatternExpr::Qualified(inner) => Display::fmt(&self.child(inner), f)?,
+            inner => fmt_delimited(f, "(", &self.child(inner), ")")?,
+        }
+        f.write_str(&qualifier_suffix(q))
+    }
+}
+
+impl Display for Styled<'_, PatternExpr> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.node {
+            PatternExpr::Comparison(c) => fmt_observation(f, &self.child(c)),
+            PatternExpr::Composite(c) => Display::fmt(&self.child(c), f),
+            PatternExpr::Qualified(q) => Display::fmt(&self.child(q), f),
+        }
+    }
+}
+
+/// A whole pattern. Groups are otherwise written by the composite holding
+/// them, so with grouping a parenthesized composite at the root writes its
+/// own.
+struct Root<'a>(Styled<'a, PatternExpr>);
+
+impl Display for Root<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0.node {
+            PatternExpr::Composite(c) if f.alternate() && c.parenthesized => {
+                fmt_delimited(f, "(", &self.0, ")")
+            }
+            _ => Display::fmt(&self.0, f),
+        }
+    }
+}
+
+/// Writes an observation in brackets, with the parentheses of a group
+/// directly inside them when keeping the source's grouping.
+fn fmt_observation(f: &mut Formatter<'_>, expr: &Styled<'_, ComparisonExpr>) -> fmt::Result {
+    match expr.node {
+        ComparisonExpr::Composite(c) if f.alternate() && c.parenthesized && !c.negated => {
+            f.write_str("[(")?;
+            Display::fmt(expr, f)?;
+            f.write_str(")]")
+        }
+        _ => fmt_delimited(f, "[", expr, "]"),
+    }
+}
+
+// The plain impls write floats with the default format.
+
+impl Display for StixValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Styled::new(self, FloatFormat::default()), f)
+    }
+}
+
+impl Display for ComparisonRhs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Styled::new(self, FloatFormat::default()), f)
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Styled::new(self, FloatFormat::default()), f)
+    }
+}
+
+impl Display for CompositeComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Styled::new(self, FloatFormat::default()), f)
+    }
+}
+
+impl Display for ComparisonExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Styled::new(self, FloatFormat::default()), f)
+    }
+}
+
+impl Display for CompositePattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Styled::new(self, FloatFormat::default()), f)
+    }
+}
+
+impl Display for QualifiedPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Styled::new(self, FloatFormat::default()), f)
+    }
+}
+
+impl Display for PatternExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Root(Styled::new(self, FloatFormat::default())), f)
+    }
+}
+
+impl CompositeComparison {
+    /// Operands are combined left to right, so a composite operand only
+    /// needs parentheses when it sits on the right or mixes operators with
+    /// its parent. With `grouping`, parentheses written in the source are
+    /// kept as well. A negated operand brings its own.
+    fn needs_parens(&self, operand: &ComparisonExpr, is_right: bool, grouping: bool) -> bool {
+        matches!(operand, ComparisonExpr::Composite(c)
+            if !c.negated && (is_right || c.op != self.op || (grouping && c.parenthesized)))
+    }
+}
+
+impl CompositePattern {
+    fn needs_parens(&self, operand: &PatternExpr, is_right: bool, grouping: bool) -> bool {
+        matches!(operand, PatternExpr::Composite(c)
+            if is_right || c.op != self.op || (grouping && c.parenthesized))
+    }
+}
+
+/// Writes `operand` with the caller's formatter so flags such as `{:#}`
+/// apply to the whole subtree.
+fn fmt_operand<T: Display>(f: &mut Formatter<'_>, operand: &T, parens: bool) -> fmt::Result {
+    if parens {
+        fmt_delimited(f, "(", operand, ")")
+    } else {
+        Display::fmt(operand, f)
+    }
+}
+
+fn fmt_delimited<T: Display>(
+    f: &mut Formatter<'_>,
+    open: &str,
+    inner: &T,
+    close: &str,
+) -> fmt::Result {
+    f.write_str(open)?;
+    Display::fmt(inner, f)?;
+    f.write_str(close)
+}
+
+/// Serialize a pattern on a single line.
+///
+/// Only the parentheses required to reproduce the AST are emitted unless
+/// `preserve_grouping` is set, in which case every group written in the
+/// source is kept. This is also available as `format!("{expr:#}")`.
+#[must_use]
+pub fn to_stix_string(expr: &PatternExpr, preserve_grouping: bool) -> String {
+    to_stix_string_with(expr, preserve_grouping, FloatFormat::default())
+}
+
+/// [`to_stix_string`] writing floats without source text with `floats`.
+#[must_use]
+pub fn to_stix_string_with(
+    expr: &PatternExpr,
+    preserve_grouping: bool,
+    floats: FloatFormat,
+) -> String {
+    let styled = Root(Styled::new(expr, floats));
+    if preserve_grouping {
+        format!("{styled:#}")
+    } else {
+        styled.to_string()
+    }
+}
+
+pub(crate) fn qualifier_suffix(q: &QualifiedPattern) -> String {
+    let mut out = String::new();
+    if let Some(n) = q.repeat {
+        out.push_str(&format!(" REPEATS {n} TIMES"));
+    }
+    if let Some(secs) = q.within {
+        match q.within_raw.text_for(&secs) {
+            Some(raw) => out.push_str(&format!(" WITHIN {raw} SECONDS")),
+            None => out.push_str(&format!(" WITHIN {secs} SECONDS")),
+        }
+    }
+    if let (Some(start), Some(stop)) = (&q.start, &q.stop) {
+        out.push_str(&format!(
+            " START {} STOP {}",
+            StixValue::Timestamp(*start),
+            StixValue::Timestamp(*stop)
+        ));
+    }
+    out
+}
+
+fn is_plain_property(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn escape_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Format a pattern over multiple lines, breaking at every `AND`, `OR` and
+/// `FOLLOWEDBY` and indenting nested groups by `indent` spaces.
+///
+/// Leaf comparisons stay on one line and qualifiers follow the closing
+/// bracket or parenthesis of the observation they apply to.
+#[must_use]
+pub fn format_pattern(expr: &PatternExpr, indent: usize) -> String {
+    let pad = " ".repeat(indent);
+    pattern_lines(expr, &pad).join("\n")
+}
+
+fn pattern_lines(expr: &PatternExpr, pad: &str) -> Vec<String> {
+    match expr {
+        PatternExpr::Comparison(c) => observation_lines(c, pad),
+        PatternExpr::Composite(c) => {
+            let mut operands = vec![(c.right.as_ref(), true)];
+            let mut current = c;
+            while let PatternExpr::Composite(left) = current.left.as_ref() {
+                if left.op != c.op {
+                    break;
+                }
+                operands.push((left.right.as_ref(), true));
+                current = left;
+            }
+            operands.push((current.left.as_ref(), false));
+            operands.reverse();
+
+            let mut lines = Vec::new();
+            for (i, (operand, is_right)) in operands.into_iter().enumerate() {
+                let mut block = if c.needs_parens(operand, is_right, false) {
+                    wrap("(", pattern_lines(operand, pad), ")", pad)
+                } else {
+                    pattern_lines(operand, pad)
+                };
+                if i > 0 {
+                    block[0] = format!("{} {}", c.op.value(), block[0]);
+                }
+                lines.extend(block);
+            }
+            lines
+        }
+        PatternExpr::Qualified(q) => {
+            let mut lines = match q.pattern.as_ref() {
+                PatternExpr::Comparison(c) => observation_lines(c, pad),
+                inner @ PatternExpr::Qualified(_) => pattern_lines(inner, pad),
+                inner => wrap("(", pattern_lines(inner, pad), ")", pad),
+            };
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&qualifier_suffix(q));
+            }
+            lines
+        }
+    }
+}
+
+fn observation_lines(expr: &ComparisonExpr, pad: &str) -> Vec<String> {
+    match expr {
+        ComparisonExpr::Single(c) => vec![format!("[{c}]")],
+        ComparisonExpr::Composite(_) => wrap("[", comparison_lines(expr, pad), "]", pad),
+    }
+}
+
+fn comparison_lines(expr: &ComparisonExpr, pad: &str) -> Vec<String> {
+    let c = match expr {
+        ComparisonExpr::Single(c) => return vec![c.to_string()],
+        ComparisonExpr::Composite(c) if c.negated => {
+            let mut inner = c.clone();
+            inner.negated = false;
+            return wrap("NOT (", comparison_lines(&inner.into(), pad), ")", pad);
+        }
+        ComparisonExpr::Composite(c) => c,
+    };
+
+    let mut operands = vec![(c.right.as_ref(), true)];
+    let mut current = c;
+    while let ComparisonExpr::Composite(left) = current.left.as_ref() {
+        if left.op != c.op || left.negated {
+            break;
+        }
+        operands.push((left.right.as_ref(), true));
+        current = left;
+    }
+    operands.push((current.left.as_ref(), false));
+    operands.reverse();
+
+    let mut lines = Vec::new();
+    for (i, (operand, is_right)) in operands.into_iter().enumerate() {
+        let mut block = if c.needs_parens(operand, is_right, false) {
+            wrap("(", comparison_lines(operand, pad), ")", pad)
+        } else {
+            comparison_lines(operand, pad)
+        };
+        if i > 0 {
+            block[0] = format!("{} {}", c.op.value(), block[0]);
+        }
+        lines.extend(block);
+    }
+    lines
+}
+
+fn wrap(open: &str, inner: Vec<String>, close: &str, pad: &str) -> Vec<String> {
+    let mut lines = Vec::with_capacity(inner.len() + 2);
+    lines.push(open.to_owned());
+    lines.extend(inner.into_iter().map(|line| format!("{pad}{line}")));
+    lines.push(close.to_owned());
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParseError, ParseOptions, parse_pattern, parse_pattern_with_options};
+
+    fn roundtrip(input: &str) -> String {
+        let expr = parse_pattern(input).unwrap();
+        let output = expr.to_string();
+        assert_eq!(parse_pattern(&output).unwrap().to_string(), output);
+        output
+    }
+
+    #[test]
+    fn test_display_comparisons() {
+        assert_eq!(
+            roundtrip("[file:hashes.'SHA-256' = 'ab\\'c' AND file:size NOT > 1.0]"),
+            "[file:hashes.'SHA-256' = 'ab\\'c' AND file:size NOT > 1.0]"
+        );
+        assert_eq!(
+            roundtrip("[EXISTS x:a[*].b OR x:c[0] IN (1, 2) OR x:d = h'ff' OR x:e = b'YQ==']"),
+            "[EXISTS x:a[*].b OR x:c[0] IN (1, 2) OR x:d = h'ff' OR x:e = b'YQ==']"
+        );
+    }
+
+    #[test]
+    fn test_display_keeps_raw_constants() {
+        assert_eq!(
+            roundtrip("[a:x = 1.50 AND a:y IN (2.50, 3) AND a:t > t'2023-01-01T00:00:00+02:00']"),
+            "[a:x = 1.50 AND a:y IN (2.50, 3) AND a:t > t'2023-01-01T00:00:00+02:00']"
+        );
+
+        let PatternExpr::Comparison(ComparisonExpr::Single(mut c)) =
+            parse_pattern("[a:x = 1.50]").unwrap()
+        else {
+            unreachable!()
+        };
+        c.constant = Some(StixValue::Float(2.5).into());
+        assert_eq!(c.to_string(), "a:x = 2.5");
+    }
+
+    #[test]
+    fn test_float_format() {
+        let default = FloatFormat::default();
+        assert_eq!(default.format(1.0), "1.0");
+        assert_eq!(default.format(-2.5), "-2.5");
+        assert_eq!(default.format(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(default.format(1e21), "1000000000000000000000.0");
+
+        let padded = FloatFormat {
+            min_decimals: 3,
+            ..Default::default()
+        };
+        assert_eq!(padded.format(1.0), "1.000");
+        assert_eq!(padded.format(1.2345), "1.2345");
+
+        let bare = FloatFormat {
+            min_decimals: 0,
+            ..Default::default()
+        };
+        assert_eq!(bare.format(1.0), "1");
+
+        let scientific = FloatFormat {
+            scientific_threshold: Some(6),
+            ..Default::default()
+        };
+        assert_eq!(scientific.format(1e21), "1.0e21");
+        assert_eq!(scientific.format(-1.5e-7), "-1.5e-7");
+        assert_eq!(scientific.format(123456.0), "123456.0");
+        assert_eq!(scientific.format(0.0), "0.0");
+    }
+
+    #[test]
+    fn test_float_format_roundtrip() {
+        let PatternExpr::Comparison(ComparisonExpr::Single(mut c)) =
+            parse_pattern("[a:x IN (1.0)]").unwrap()
+        else {
+            unreachable!()
+        };
+        c.constant = Some(ComparisonRhs::List(vec![
+            StixValue::Float(3.0),
+            StixValue::Float(2.5e-9),
+        ]));
+        let expr = PatternExpr::from(ComparisonExpr::from(c));
+        assert_eq!(expr.to_string(), "[a:x IN (3.0, 0.0000000025)]");
+
+        let floats = FloatFormat {
+            min_decimals: 2,
+            scientific_threshold: Some(6),
+        };
+        let output = to_stix_string_with(&expr, false, floats);
+        assert_eq!(output, "[a:x IN (3.00, 2.50e-9)]");
+        assert!(matches!(
+            parse_pattern(&output),
+            Err(ParseError::Exponent(15))
+        ));
+        let options = ParseOptions {
+            allow_exponent: true,
+            ..Default::default()
+        };
+        assert_eq!(parse_pattern_with_options(&output, &options).unwrap(), expr);
+    }
+
+    #[test]
+    fn test_display_keeps_grouping() {
+        assert_eq!(
+            roundtrip("[a:x=1 AND (a:y=2 OR a:z=3)]"),
+            "[a:x = 1 AND (a:y = 2 OR a:z = 3)]"
+        );
+        assert_eq!(
+            roundtrip("([a:x=1] OR [a:y=2]) FOLLOWEDBY [a:z=3]"),
+            "([a:x = 1] OR [a:y = 2]) FOLLOWEDBY [a:z = 3]"
+        );
+    }
+
+    #[test]
+    fn test_display_qualifiers() {
+        assert_eq!(
+            roundtrip(
+                "([a:x=1] AND [a:y=2]) WITHIN 5 SECONDS START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'"
+            ),
+            "([a:x = 1] AND [a:y = 2]) WITHIN 5 SECONDS START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'"
+        );
+    }
+
+    #[test]
+    fn test_display_keeps_raw_within() {
+        for secs in ["0.1", "0.10", "5", "1.00000000000000000001"] {
+            let input = format!("[a:x = 1] WITHIN {secs} SECONDS");
+            assert_eq!(roundtrip(&input), input);
+        }
+
+        let PatternExpr::Qualified(mut q) = parse_pattern("[a:x = 1] WITHIN 0.10 SECONDS").unwrap()
+        else {
+            unreachable!()
+        };
+        q.within = Some(0.2);
+        assert_eq!(q.to_string(), "[a:x = 1] WITHIN 0.2 SECONDS");
+    }
+
+    #[test]
+    fn test_format_pattern() {
+        let expr = parse_pattern(
+            "[file:name = 'a' AND (file:size > 10 OR file:size < 2)] FOLLOWEDBY ([b:x = 1] OR [c:y = 2]) WITHIN 5 SECONDS",
+        )
+        .unwrap();
+        let formatted = format_pattern(&expr, 2);
+        assert_eq!(
+            formatted,
+            "[\n  file:name = 'a'\n  AND (\n    file:size > 10\n    OR file:size < 2\n  )\n]\nFOLLOWEDBY (\n  [b:x = 1]\n  OR [c:y = 2]\n) WITHIN 5 SECONDS"
+        );
+        assert_eq!(
+            parse_pattern(&formatted).unwrap().to_string(),
+            expr.to_string()
+        );
+    }
+
+    #[test]
+    fn test_preserve_grouping() {
+        let expr = parse_pattern("[(a:x = 1 AND a:y = 2) AND a:z = 3] OR ([b:x = 1] OR [b:y = 2])")
+            .unwrap();
+        assert_eq!(
+            to_stix_string(&expr, false),
+            "[a:x = 1 AND a:y = 2 AND a:z = 3] OR ([b:x = 1] OR [b:y = 2])"
+        );
+        assert_eq!(
+            to_stix_string(&expr, true),
+            "[(a:x = 1 AND a:y = 2) AND a:z = 3] OR ([b:x = 1] OR [b:y = 2])"
+        );
+
+        // Groups at the root of a pattern or of an observation.
+        for input in [
+            "[(a:x = 1 AND a:y = 2)]",
+            "([a:x = 1] OR [a:y = 2])",
+            "(([a:x = 1] OR [a:y = 2]) AND [(b:x = 1 OR b:y = 2)])",
+            "([a:x = 1] OR [(a:y = 2 AND a:z = 3)]) REPEATS 2 TIMES",
+        ] {
+            let expr = parse_pattern(input).unwrap();
+            assert_eq!(to_stix_string(&expr, true), input);
+            assert_eq!(format!("{expr:#}"), input);
+        }
+        let expr = parse_pattern("([a:x = 1] OR [(a:y = 2 AND a:z = 3)])").unwrap();
+        assert_eq!(
+            to_stix_string(&expr, false),
+            "[a:x = 1] OR [a:y = 2 AND a:z = 3]"
+        );
+        // The grouping is not part of equality.
+        assert_eq!(parse_pattern(&to_stix_string(&expr, false)).unwrap(), expr);
+    }
+
+    mod roundtrip {
+        //! Random ASTs must re-parse from their serialization unchanged.
+
+        use chrono::DateTime;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+
+        use super::*;
+        use crate::ast::{
+            BooleanOp, ComparisonOp, ObservationOp, PathComponent, PropertyKind, UnaryOp,
+        };
+
+        fn timestamp() -> impl Strategy<Value = chrono::DateTime<chrono::Utc>> {
+            // Up to the end of year 9999, the last one RFC 3339 can write.
+            (0_i64..253_402_300_800, 0_u32..1_000_000_000)
+                .prop_map(|(secs, nanos)| DateTime::from_timestamp(secs, nanos).unwrap())
+        }
+
+        fn value() -> impl Strategy<Value = StixValue> {
+            prop_oneof![
+                any::<String>().prop_map(StixValue::String),
+                any::<i64>().prop_map(StixValue::Int),
+                any::<f64>()
+                    .prop_filter("finite", |f| f.is_finite())
+                    .prop_map(StixValue::Float),
+                any::<bool>().prop_map(StixValue::Bool),
+                timestamp().prop_map(StixValue::Timestamp),
+                "([0-9a-fA-F]{2}){1,8}".prop_map(StixValue::Hex),
+                "([A-Za-z0-9+/]{4}){1,4}".prop_map(StixValue::Binary),
+            ]
+        }
+
+        /// A non-empty list whose elements have the same type.
+        fn list() -> impl Strategy<Value = Vec<StixValue>> {
+            prop_oneof![
+                vec(any::<String>().prop_map(StixValue::String), 1..4),
+                vec(any::<i64>().prop_map(StixValue::Int), 1..4),
+                vec(timestamp().prop_map(StixValue::Timestamp), 1..4),
+            ]
+        }
+
+        fn object_path() -> impl Strategy<Value = ObjectPath> {
+            let index = prop_oneof![
+                any::<u32>().prop_map(ListIndex::Index),
+                Just(ListIndex::Star)
+            ];
+            let property = prop_oneof![
+                "[a-z_][a-z0-9_]{0,6}".prop_map(|p| (p, PropertyKind::Plain)),
+                ".+".prop_map(|p| (p, PropertyKind::Quoted)),
+            ];
+            let component = (property, vec(index, 0..3)).prop_map(|((property, kind), indices)| {
+                PathComponent {
+                    property: property.into(),
+                    indices,
+                    kind,
+                }
+            });
+            ("[a-z0-9][a-z0-9-]{0,8}", vec(component, 1..4))
+                .prop_map(|(object_type, path)| ObjectPath::new(object_type, path))
+        }
+
+        fn comparison() -> impl Strategy<Value = Comparison> {
+            let op = prop_oneof![
+                Just(ComparisonOp::Eq),
+                Just(ComparisonOp::Neq),
+                Just(ComparisonOp::Gt),
+                Just(ComparisonOp::Lt),
+                Just(ComparisonOp::Ge),
+                Just(ComparisonOp::Le),
+                Just(ComparisonOp::Like),
+                Just(ComparisonOp::Matches),
+                Just(ComparisonOp::IsSubset),
+                Just(ComparisonOp::IsSuperset),
+            ];
+            prop_oneof![
+                (object_path(), op, value(), any::<bool>()).prop_map(|(path, op, v, negated)| {
+                    Comparison::new(path, op, Some(v.into()), negated)
+                }),
+                (object_path(), list(), any::<bool>()).prop_map(|(path, values, negated)| {
+                    Comparison::new(path, ComparisonOp::In, Some(values.into()), negated)
+                }),
+                object_path().prop_map(|path| Comparison::new(path, UnaryOp::Exists, None, false)),
+            ]
+        }
+
+        fn comparison_expr() -> impl Strategy<Value = ComparisonExpr> {
+            let op = prop_oneof![Just(BooleanOp::And), Just(BooleanOp::Or)];
+            comparison()
+                .prop_map(ComparisonExpr::from)
+                .prop_recursive(4, 16, 2, move |inner| {
+                    (inner.clone(), op.clone(), inner, any::<bool>()).prop_map(
+                        |(l, op, r, parenthesized)| {
+                            let mut c = CompositeComparison::new(l, op, r);
+                            c.parenthesized = parenthesized;
+                            c.into()
+                        },
+                    )
+                })
+        }
+
+        fn pattern_expr() -> impl Strategy<Value = PatternExpr> {
+            let op = prop_oneof![
+                Just(ObservationOp::And),
+                Just(ObservationOp::Or),
+                Just(ObservationOp::FollowedBy),
+            ];
+            let qualifier = prop_oneof![
+                (1_u32..1000).prop_map(|n| (Some(n), None, None)),
+                (0.001_f64..1e12).prop_map(|secs| (None, Some(secs), None)),
+                (timestamp(), timestamp())
+                    .prop_filter("distinct", |(a, b)| a != b)
+                    .prop_map(|(a, b)| (None, None, Some((a.min(b), a.max(b))))),
+            ];
+            comparison_expr()
+                .prop_map(PatternExpr::from)
+                .prop_recursive(4, 12, 2, move |inner| {
+                    prop_oneof![
+                        (inner.clone(), op.clone(), inner.clone(), any::<bool>()).prop_map(
+                            |(l, op, r, parenthesized)| {
+                                let mut c = CompositePattern::new(l, op, r);
+                                c.parenthesized = parenthesized;
+                                c.into()
+                            },
+                        ),
+                        (inner, qualifier.clone()).prop_map(|(p, (repeat, within, interval))| {
+                            let (start, stop) = interval.unzip();
+                            QualifiedPattern::new(p, repeat, within, start, stop).into()
+                        }),
+                    ]
+                })
+        }
+
+        proptest! {
+            #[test]
+            fn test_roundtrip(expr in pattern_expr()) {
+                let output = expr.to_string();
+                let reparsed = parse_pattern(&output)
+                    .map_err(|err| TestCaseError::fail(format!("{output}: {err}")))?;
+                prop_assert_eq!(reparsed, expr, "{}", output);
+            }
+
+            #[test]
+            fn test_roundtrip_preserve_grouping(expr in pattern_expr()) {
+                let output = to_stix_string(&expr, true);
+                let reparsed = parse_pattern(&output)
+                    .map_err(|err| TestCaseError::fail(format!("{output}: {err}")))?;
+                prop_assert_eq!(&to_stix_string(&reparsed, true), &output);
+                prop_assert_eq!(reparsed, expr, "{}", output);
+            }
+        }
+    }
+}
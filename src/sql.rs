@@ -0,0 +1,312 @@
+//! Translation of an observation into a parameterized SQL `WHERE` clause.
+//!
+//! Object paths become columns through a map keyed by the path as written
+//! in a pattern, such as `file:name` or `file:hashes.'SHA-256'`, and
+//! constants become `?` placeholders, returned in order:
+//!
+//! ```text
+//! [file:name LIKE '%.exe' AND file:size NOT IN (1, 2)]
+//! files.name LIKE ? ESCAPE '!' AND NOT (files.size IN (?, ?))
+//! ```
+//!
+//! `LIKE` keeps its wildcards, which are the same in SQL. The backslash
+//! escapes of a string operand are rewritten with `!` as the escape
+//! character, since `ESCAPE '\'` is a syntax error in MySQL, so
+//! `'50\\%'` binds `50!%`. `EXISTS` becomes `IS NOT NULL` and a `null`
+//! constant compared with `=` or `!=` becomes `IS NULL` or `IS NOT NULL`.
+//! `MATCHES`, `ISSUBSET` and `ISSUPERSET` have no portable SQL form, and
+//! observation operators and qualifiers relate several observations, so
+//! they are reported as `SqlError::Unsupported`.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::ast::{
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, ComparisonRhs,
+    ObjectPath, PatternExpr, StixValue,
+};
+
+/// Errors that can occur while translating a pattern to SQL.
+#[derive(Error, Debug)]
+pub enum SqlError {
+    #[error("Cannot translate {0} to SQL")]
+    Unsupported(&'static str),
+
+    #[error("No column for object path {0}")]
+    UnmappedPath(String),
+}
+
+pub type Result<T> = std::result::Result<T, SqlError>;
+
+/// Translate the observation `expr` into a `WHERE` clause and the values of
+/// its placeholders, looking columns up in `columns`.
+pub fn to_sql(
+    expr: &PatternExpr,
+    columns: &HashMap<String, String>,
+) -> Result<(String, Vec<StixValue>)> {
+    let expr = match expr {
+        PatternExpr::Comparison(c) => c,
+        PatternExpr::Composite(_) => return Err(SqlError::Unsupported("observation operators")),
+        PatternExpr::Qualified(_) => return Err(SqlError::Unsupported("qualifiers")),
+    };
+    let mut query = Query {
+        columns,
+        sql: String::new(),
+        params: Vec::new(),
+    };
+    query.expr(expr, None)?;
+    Ok((query.sql, query.params))
+}
+
+struct Query<'c> {
+    columns: &'c HashMap<String, String>,
+    sql: String,
+    params: Vec<StixValue>,
+}
+
+impl<'c> Query<'c> {
+    /// Write `expr`, parenthesized when it joins its operands with another
+    /// operator than its parent's. What is left to write is kept on a stack,
+    /// as chains nest too deeply to recurse down.
+    fn expr(&mut self, expr: &ComparisonExpr, parent: Option<BooleanOp>) -> Result<()> {
+        enum Step<'e> {
+            Expr(&'e ComparisonExpr, Option<BooleanOp>),
+            Text(&'static str),
+        }
+
+        let mut pending = vec![Step::Expr(expr, parent)];
+        while let Some(step) = pending.pop() {
+            match step {
+                Step::Text(text) => self.sql.push_str(text),
+                Step::Expr(ComparisonExpr::Single(c), _) => self.comparison(c)?,
+                Step::Expr(ComparisonExpr::Composite(c), parent) => {
+                    let grouped = parent.is_some_and(|op| op != c.op);
+                    if c.negated {
+                        self.sql.push_str("NOT (");
+                    } else if grouped {
+                        self.sql.push('(');
+                    }
+                    if c.negated || grouped {
+                        pending.push(Step::Text(")"));
+                    }
+                    pending.extend([
+                        Step::Expr(&c.right, Some(c.op)),
+                        Step::Text(" "),
+                        Step::Text(c.op.value()),
+                        Step::Text(" "),
+                        Step::Expr(&c.left, Some(c.op)),
+                    ]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn comparison(&mut self, c: &Comparison) -> Result<()> {
+        let column = self.column(&c.object_path)?;
+        let op = match c.op {
+            ComparisonOperator::Unary(_) => {
+                self.sql.push_str(column);
+                self.sql.push_str(" IS NOT NULL");
+                return Ok(());
+            }
+            ComparisonOperator::Comparison(op) => op,
+        };
+        let rhs = c
+            .constant
+            .as_ref()
+            .ok_or(SqlError::Unsupported("a comparison without a constant"))?;
+
+        if c.negated {
+            self.sql.push_str("NOT (");
+        }
+        if matches!(rhs, ComparisonRhs::List(values) if values.is_empty()) {
+            // `IN ()` is not valid SQL; it never matches.
+            self.sql.push_str("1 = 0");
+        } else {
+            self.sql.push_str(column);
+            self.operation(op, rhs)?;
+        }
+        if c.negated {
+            self.sql.push(')');
+        }
+        Ok(())
+    }
+
+    /// Write the operator and right-hand side of a comparison.
+    fn operation(&mut self, op: ComparisonOp, rhs: &ComparisonRhs) -> Result<()> {
+        match (op, rhs) {
+            (ComparisonOp::Eq, ComparisonRhs::Value(StixValue::Null)) => {
+                self.sql.push_str(" IS NULL");
+            }
+            (ComparisonOp::Neq, ComparisonRhs::Value(StixValue::Null)) => {
+                self.sql.push_str(" IS NOT NULL");
+            }
+            (ComparisonOp::In, ComparisonRhs::List(values)) => {
+                self.sql.push_str(" IN (");
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        self.sql.push_str(", ");
+                    }
+                    self.param(value);
+                }
+                self.sql.push(')');
+            }
+            (ComparisonOp::Matches | ComparisonOp::IsSubset | ComparisonOp::IsSuperset, _) => {
+                return Err(SqlError::Unsupported(op.value()));
+            }
+            (ComparisonOp::Like, ComparisonRhs::Value(StixValue::String(pattern))) => {
+                self.sql.push_str(" LIKE ?");
+                self.params.push(StixValue::String(like_pattern(pattern)));
+                self.sql.push_str(" ESCAPE '!'");
+            }
+            (_, ComparisonRhs::List(_)) => {
+                return Err(SqlError::Unsupported("a list outside IN"));
+            }
+            (_, rhs) => {
+                self.sql.push(' ');
+                self.sql.push_str(match op {
+                    ComparisonOp::Neq => "<>",
+                    op => op.value(),
+                });
+                self.sql.push(' ');
+                match rhs {
+                    ComparisonRhs::Path(path) => {
+                        let column = self.column(path)?;
+                        self.sql.push_str(column);
+                    }
+                    ComparisonRhs::Value(value) => self.param(value),
+                    ComparisonRhs::List(_) => unreachable!("lists are handled above"),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn column(&self, path: &ObjectPath) -> Result<&'c str> {
+        let key = path.to_string();
+        self.columns
+            .get(&key)
+            .map(String::as_str)
+            .ok_or(SqlError::UnmappedPath(key))
+    }
+
+    fn param(&mut self, value: &StixValue) {
+        self.sql.push('?');
+        self.params.push(value.clone());
+    }
+}
+
+/// The `LIKE` operand `pattern` with its backslash escapes written with
+/// `!`, and `!` itself escaped.
+fn like_pattern(pattern: &str) -> String {
+    let mut escaped = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) => {
+                    escaped.push('!');
+                    escaped.push(next);
+                }
+                // A trailing backslash escapes nothing.
+                None => escaped.push('\\'),
+            },
+            '!' => escaped.push_str("!!"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParseOptions, parse_pattern, parse_pattern_with_options};
+
+    fn columns() -> HashMap<String, String> {
+        [
+            ("file:name", "files.name"),
+            ("file:size", "files.size"),
+            ("file:hashes.'SHA-256'", "files.sha256"),
+            ("file:parent_directory_ref.path", "dirs.path"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect()
+    }
+
+    fn sql(pattern: &str) -> (String, Vec<StixValue>) {
+        to_sql(&parse_pattern(pattern).unwrap(), &columns()).unwrap()
+    }
+
+    #[test]
+    fn test_to_sql() {
+        assert_eq!(
+            sql("[file:name LIKE '%.exe' AND file:size NOT IN (1, 2)]"),
+            (
+                "files.name LIKE ? ESCAPE '!' AND NOT (files.size IN (?, ?))".to_owned(),
+                vec![
+                    StixValue::String("%.exe".to_owned()),
+                    StixValue::Int(1),
+                    StixValue::Int(2)
+                ]
+            )
+        );
+        assert_eq!(
+            sql(r"[file:name LIKE '50\\%!\\_a\\']").1,
+            vec![StixValue::String("50!%!!!_a\\".to_owned())]
+        );
+        assert_eq!(
+            sql("[(file:name = 'a' OR file:name != 'b') AND EXISTS file:hashes.'SHA-256']").0,
+            "(files.name = ? OR files.name <> ?) AND files.sha256 IS NOT NULL"
+        );
+        assert_eq!(
+            sql("[file:name = 'a' OR file:size > 1 OR file:parent_directory_ref.path = 'c']").0,
+            "files.name = ? OR files.size > ? OR dirs.path = ?"
+        );
+    }
+
+    #[test]
+    fn test_to_sql_special_constants() {
+        let options = ParseOptions {
+            allow_null: true,
+            allow_empty_lists: true,
+            allow_path_rhs: true,
+            ..Default::default()
+        };
+        let sql = |pattern: &str| {
+            let expr = parse_pattern_with_options(pattern, &options).unwrap();
+            to_sql(&expr, &columns()).unwrap()
+        };
+        assert_eq!(
+            sql("[file:name = null]"),
+            ("files.name IS NULL".to_owned(), vec![])
+        );
+        assert_eq!(sql("[file:name NOT IN ()]").0, "NOT (1 = 0)");
+        assert_eq!(sql("[file:name = file:size]").0, "files.name = files.size");
+    }
+
+    #[test]
+    fn test_to_sql_unsupported() {
+        let error =
+            |pattern: &str| to_sql(&parse_pattern(pattern).unwrap(), &columns()).unwrap_err();
+        assert!(matches!(
+            error("[file:name = 'a'] FOLLOWEDBY [file:name = 'b']"),
+            SqlError::Unsupported("observation operators")
+        ));
+        assert!(matches!(
+            error("[file:name = 'a'] WITHIN 5 SECONDS"),
+            SqlError::Unsupported("qualifiers")
+        ));
+        assert!(matches!(
+            error("[file:name MATCHES 'a']"),
+            SqlError::Unsupported("MATCHES")
+        ));
+        assert!(matches!(
+            error("[file:ctime = t'2023-01-01T00:00:00Z']"),
+            SqlError::UnmappedPath(path) if path == "file:ctime"
+        ));
+    }
+}
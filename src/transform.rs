@@ -0,0 +1,759 @@
+//! AST rewrites that produce an equivalent, normalized expression.
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+
+use crate::analysis::{flatten_comparison, flatten_pattern};
+use crate::ast::{
+    BooleanOp, Comparison, ComparisonExpr, ComparisonOp, ComparisonOperator, ComparisonRhs,
+    CompositeComparison, CompositePattern, ObservationOp, PatternExpr, QualifiedPattern, StixValue,
+};
+use crate::serialize::qualifier_suffix;
+
+/// Push negations down to the leaves and remove them where possible.
+///
+/// A negated comparison whose operator has a complement is rewritten to use
+/// it, e.g. `NOT =` becomes `!=` and `NOT >` becomes `<=`. `IN`, `LIKE`,
+/// `MATCHES`, `ISSUBSET` and `ISSUPERSET` have no complement and stay
+/// negated. A negated group, as in `NOT (a AND b)`, is rewritten with De
+/// Morgan's laws to `NOT a OR NOT b`, which is standard STIX, unless it
+/// holds an `EXISTS`, which cannot be negated: the group then stays negated.
+#[must_use]
+pub fn push_negations(expr: ComparisonExpr) -> ComparisonExpr {
+    match expr {
+        ComparisonExpr::Single(c) => complement_comparison(c).into(),
+        ComparisonExpr::Composite(c) if c.negated => {
+            let mut group = c.clone();
+            group.negated = false;
+            negation(group.into()).unwrap_or_else(|| c.map_chain(push_negations).into())
+        }
+        ComparisonExpr::Composite(c) => c.map_chain(push_negations).into(),
+    }
+}
+
+/// The negation of `expr` with negations pushed down, or `None` when that
+/// would negate an `EXISTS`.
+fn negation(expr: ComparisonExpr) -> Option<ComparisonExpr> {
+    match expr {
+        ComparisonExpr::Single(mut c) => {
+            if matches!(c.op, ComparisonOperator::Unary(_)) {
+                return None;
+            }
+            c.negated = !c.negated;
+            Some(complement_comparison(c).into())
+        }
+        ComparisonExpr::Composite(mut c) if c.negated => {
+            c.negated = false;
+            Some(push_negations(c.into()))
+        }
+        ComparisonExpr::Composite(c) => {
+            let op = match c.op {
+                BooleanOp::And => BooleanOp::Or,
+                BooleanOp::Or => BooleanOp::And,
+            };
+            let (span, parenthesized) = (c.span, c.parenthesized);
+            let (left, right) = c.into_operands();
+            let mut negated =
+                CompositeComparison::new(negation(left)?, op, negation(right)?).with_span(span);
+            negated.parenthesized = parenthesized;
+            Some(negated.into())
+        }
+    }
+}
+
+/// Rewrite the group a negated composite negates with `f`, then negate the
+/// result again. If `f` leaves a single `EXISTS`, which cannot be negated,
+/// `c` is kept as it is.
+fn rewrite_negated_group(
+    c: CompositeComparison,
+    f: fn(ComparisonExpr) -> ComparisonExpr,
+) -> ComparisonExpr {
+    let mut group = c.clone();
+    group.negated = false;
+    match f(group.into()) {
+        ComparisonExpr::Composite(mut group) => {
+            group.negated = true;
+            group.into()
+        }
+        single => negation(single).unwrap_or_else(|| c.into()),
+    }
+}
+
+fn complement_comparison(mut c: Comparison) -> Comparison {
+    if let (true, ComparisonOperator::Comparison(op)) = (c.negated, c.op)
+        && let Some(complement) = op.complement()
+    {
+        c.op = complement.into();
+        c.negated = false;
+    }
+    c
+}
+
+/// Rewrite a pattern into a canonical form: negations are pushed down,
+/// chains of the same operator are flattened, the operands of `AND` and `OR`
+/// are sorted, as are the values of `IN` lists, and source parentheses are
+/// dropped. `FOLLOWEDBY` operands keep their order.
+#[must_use]
+pub fn normalize(expr: PatternExpr) -> PatternExpr {
+    match expr {
+        PatternExpr::Comparison(c) => normalize_comparison(push_negations(c)).into(),
+        PatternExpr::Composite(c) => {
+            let op = c.op;
+            let mut operands = Vec::new();
+            flatten_pattern_owned(PatternExpr::Composite(c), op, &mut operands);
+            let mut operands: Vec<_> = operands.into_iter().map(normalize).collect();
+            if op != ObservationOp::FollowedBy {
+                operands.sort_by_cached_key(ToString::to_string);
+            }
+            operands
+                .into_iter()
+                .reduce(|left, right| CompositePattern::new(left, op, right).into())
+                .expect("a composite has at least two operands")
+        }
+        PatternExpr::Qualified(q) => QualifiedPattern {
+            pattern: Arc::new(normalize(Arc::unwrap_or_clone(q.pattern))),
+            ..q
+        }
+        .into(),
+    }
+}
+
+/// Whether two patterns have the same [`normalize`]d form.
+///
+/// This is structural equivalence modulo operand order, grouping and
+/// complementable negation, not a semantic check: `[a:x > 1 OR a:x > 2]` and
+/// `[a:x > 1]` match the same data but are not equivalent here, and neither
+/// are `1` and `1.0`.
+#[must_use]
+pub fn equivalent(a: &PatternExpr, b: &PatternExpr) -> bool {
+    normalize(a.clone()) == normalize(b.clone())
+}
+
+/// Describe how `b` differs from `a`, both [`normalize`]d first, with
+/// messages such as `operator changed = → != at file:name` or
+/// `added comparison 'file:size > 100'`.
+///
+/// The trees are walked in parallel. Operands equal on both sides are
+/// matched first; the rest are compared in order when each side has as
+/// many, and otherwise through their leaf comparisons, paired by object
+/// path. [`equivalent`] patterns have no differences.
+#[must_use]
+pub fn diff(a: &PatternExpr, b: &PatternExpr) -> Vec<String> {
+    let mut out = Vec::new();
+    diff_patterns(&normalize(a.clone()), &normalize(b.clone()), &mut out);
+    out
+}
+
+fn diff_patterns(a: &PatternExpr, b: &PatternExpr, out: &mut Vec<String>) {
+    if a == b {
+        return;
+    }
+    match (a, b) {
+        (PatternExpr::Comparison(a), PatternExpr::Comparison(b)) => diff_comparisons(a, b, out),
+        (PatternExpr::Qualified(qa), PatternExpr::Qualified(qb)) => {
+            let (from, to) = (qualifier_suffix(qa), qualifier_suffix(qb));
+            if from != to {
+                out.push(format!(
+                    "qualifier changed '{}' → '{}'",
+                    from.trim_start(),
+                    to.trim_start()
+                ));
+            }
+            diff_patterns(&qa.pattern, &qb.pattern, out);
+        }
+        (PatternExpr::Qualified(q), b) => {
+            let qualifier = qualifier_suffix(q);
+            out.push(format!("removed qualifier '{}'", qualifier.trim_start()));
+            diff_patterns(&q.pattern, b, out);
+        }
+        (a, PatternExpr::Qualified(q)) => {
+            let qualifier = qualifier_suffix(q);
+            out.push(format!("added qualifier '{}'", qualifier.trim_start()));
+            diff_patterns(a, &q.pattern, out);
+        }
+        _ => {
+            let (op_a, operands_a) = flatten_pattern(a);
+            let (op_b, operands_b) = flatten_pattern(b);
+            if let (Some(from), Some(to)) = (op_a, op_b)
+                && from != to
+            {
+                out.push(format!(
+                    "observation operator changed {} → {}",
+                    from.value(),
+                    to.value()
+                ));
+            }
+            diff_operands(
+                operands_a,
+                operands_b,
+                diff_patterns,
+                PatternExpr::comparisons,
+                out,
+            );
+        }
+    }
+}
+
+fn diff_comparisons(a: &ComparisonExpr, b: &ComparisonExpr, out: &mut Vec<String>) {
+    if a == b {
+        return;
+    }
+    // Compare the groups under a negation, so each step removes one.
+    let (group_a, group_b) = (negated_group(a), negated_group(b));
+    if group_a.is_some() || group_b.is_some() {
+        match (&group_a, &group_b) {
+            (None, Some(group)) => out.push(format!("added negation of '({group})'")),
+            (Some(group), None) => out.push(format!("removed negation of '({group})'")),
+            _ => {}
+        }
+        diff_comparisons(
+            group_a.as_ref().unwrap_or(a),
+            group_b.as_ref().unwrap_or(b),
+            out,
+        );
+        return;
+    }
+    match (a, b) {
+        (ComparisonExpr::Single(a), ComparisonExpr::Single(b)) => {
+            diff_leaves(vec![a], vec![b], out);
+        }
+        _ => {
+            let (op_a, operands_a) = flatten_comparison(a);
+            let (op_b, operands_b) = flatten_comparison(b);
+            if let (Some(from), Some(to)) = (op_a, op_b)
+                && from != to
+            {
+                out.push(format!(
+                    "boolean operator changed {} → {}",
+                    from.value(),
+                    to.value()
+                ));
+            }
+            diff_operands(
+                operands_a,
+                operands_b,
+                diff_comparisons,
+                ComparisonExpr::comparisons,
+                out,
+            );
+        }
+    }
+}
+
+/// The group `expr` negates, if it is a negated composite.
+fn negated_group(expr: &ComparisonExpr) -> Option<ComparisonExpr> {
+    match expr {
+        ComparisonExpr::Composite(c) if c.negated => {
+            let mut group = c.clone();
+            group.negated = false;
+            Some(group.into())
+        }
+        _ => None,
+    }
+}
+
+fn diff_operands<'a, T: PartialEq>(
+    mut a: Vec<&'a T>,
+    mut b: Vec<&'a T>,
+    diff: fn(&T, &T, &mut Vec<String>),
+    leaves: fn(&T) -> Vec<&Comparison>,
+    out: &mut Vec<String>,
+) {
+    remove_common(&mut a, &mut b);
+    if a.len() == b.len() {
+        for (a, b) in a.into_iter().zip(b) {
+            diff(a, b, out);
+        }
+    } else {
+        let leaves_a = a.into_iter().flat_map(leaves).collect();
+        let leaves_b = b.into_iter().flat_map(leaves).collect();
+        diff_leaves(leaves_a, leaves_b, out);
+    }
+}
+
+fn diff_leaves<'a>(mut a: Vec<&'a Comparison>, mut b: Vec<&'a Comparison>, out: &mut Vec<String>) {
+    remove_common(&mut a, &mut b);
+    for from in a {
+        match b.iter().position(|to| to.object_path == from.object_path) {
+            Some(i) => diff_leaf(from, b.remove(i), out),
+            None => out.push(format!("removed comparison '{from}'")),
+        }
+    }
+    out.extend(b.into_iter().map(|to| format!("added comparison '{to}'")));
+}
+
+/// Differences between two comparisons of the same object path.
+fn diff_leaf(a: &Comparison, b: &Comparison, out: &mut Vec<String>) {
+    let operator = |c: &Comparison| match c.op {
+        ComparisonOperator::Comparison(op) if c.negated => format!("NOT {}", op.value()),
+        ComparisonOperator::Comparison(op) => op.value().to_owned(),
+        ComparisonOperator::Unary(op) => op.value().to_owned(),
+    };
+    let path = &a.object_path;
+    let (from, to) = (operator(a), operator(b));
+    if from != to {
+        out.push(format!("operator changed {from} → {to} at {path}"));
+    }
+    if let (Some(from), Some(to)) = (&a.constant, &b.constant)
+        && from != to
+    {
+        out.push(format!("value changed {from} → {to} at {path}"));
+    }
+}
+
+/// Remove the items found on both sides, each matched once.
+fn remove_common<T: PartialEq>(a: &mut Vec<T>, b: &mut Vec<T>) {
+    a.retain(|x| match b.iter().position(|y| y == x) {
+        Some(i) => {
+            b.remove(i);
+            false
+        }
+        None => true,
+    });
+}
+
+/// Replace the string, hex and binary constants of a pattern with
+/// `placeholder`, keeping operators, paths and structure.
+///
+/// Hex and binary constants get the hex and base64 encoding of the
+/// placeholder so the result still parses. Numbers, booleans, timestamps and
+/// qualifiers are left as they are.
+#[must_use]
+pub fn redact(expr: PatternExpr, placeholder: &str) -> PatternExpr {
+    match expr {
+        PatternExpr::Comparison(c) => redact_comparison(c, placeholder).into(),
+        PatternExpr::Composite(c) => c.map_chain(|e| redact(e, placeholder)).into(),
+        PatternExpr::Qualified(q) => QualifiedPattern {
+            pattern: Arc::new(redact(Arc::unwrap_or_clone(q.pattern), placeholder)),
+            ..q
+        }
+        .into(),
+    }
+}
+
+/// Remove every `WITHIN`, `REPEATS` and `START`/`STOP` qualifier, keeping
+/// the patterns they apply to.
+#[must_use]
+pub fn strip_qualifiers(expr: PatternExpr) -> PatternExpr {
+    match expr {
+        PatternExpr::Comparison(_) => expr,
+        PatternExpr::Composite(c) => c.map_chain(strip_qualifiers).into(),
+        PatternExpr::Qualified(q) => strip_qualifiers(Arc::unwrap_or_clone(q.pattern)),
+    }
+}
+
+/// Remove redundant structure, keeping the order of operands.
+///
+/// `NOT !=` becomes `=`, an operand repeated in a chain of `AND` or `OR`
+/// comparisons, or of `OR` observations, is dropped, and a chain left with
+/// a single operand is replaced by it. Source parentheses are dropped and
+/// chains of the same operator regrouped from the left, so only the
+/// parentheses the operators need are written. Observations joined by `AND`
+/// or `FOLLOWEDBY` are kept even when repeated, since each must match its
+/// own observed data.
+#[must_use]
+pub fn simplify(expr: PatternExpr) -> PatternExpr {
+    match expr {
+        PatternExpr::Comparison(c) => simplify_comparison(c).into(),
+        PatternExpr::Composite(c) => {
+            let op = c.op;
+            let mut operands = Vec::new();
+            flatten_pattern_owned(PatternExpr::Composite(c), op, &mut operands);
+            let operands = operands.into_iter().map(simplify);
+            let operands = if op == ObservationOp::Or {
+                dedup(operands, |p| first_comparison_hash(p.observations()[0]))
+            } else {
+                operands.collect()
+            };
+            operands
+                .into_iter()
+                .reduce(|left, right| CompositePattern::new(left, op, right).into())
+                .expect("a composite has at least one operand")
+        }
+        PatternExpr::Qualified(q) => QualifiedPattern {
+            pattern: Arc::new(simplify(Arc::unwrap_or_clone(q.pattern))),
+            ..q
+        }
+        .into(),
+    }
+}
+
+fn simplify_comparison(expr: ComparisonExpr) -> ComparisonExpr {
+    match expr {
+        ComparisonExpr::Single(mut c) => {
+            if c.negated && c.op == ComparisonOperator::Comparison(ComparisonOp::Neq) {
+                c.op = ComparisonOp::Eq.into();
+                c.negated = false;
+            }
+            c.into()
+        }
+        ComparisonExpr::Composite(c) if c.negated => rewrite_negated_group(c, simplify_comparison),
+        ComparisonExpr::Composite(c) => {
+            let op = c.op;
+            let mut operands = Vec::new();
+            flatten_comparison_owned(ComparisonExpr::Composite(c), op, &mut operands);
+            dedup(
+                operands.into_iter().map(simplify_comparison),
+                first_comparison_hash,
+            )
+            .into_iter()
+            .reduce(|left, right| CompositeComparison::new(left, op, right).into())
+            .expect("a composite has at least one operand")
+        }
+    }
+}
+
+/// The items without repetitions, each kept where it first appears. Only
+/// items with the same `key` are compared, so that long chains do not take
+/// quadratic time.
+fn dedup<T: PartialEq, K: Hash + Eq>(
+    items: impl IntoIterator<Item = T>,
+    key: impl Fn(&T) -> K,
+) -> Vec<T> {
+    let mut unique: Vec<T> = Vec::new();
+    let mut by_key: HashMap<K, Vec<usize>> = HashMap::new();
+    for item in items {
+        let same_key = by_key.entry(key(&item)).or_default();
+        if !same_key.iter().any(|&i| unique[i] == item) {
+            same_key.push(unique.len());
+            unique.push(item);
+        }
+    }
+    unique
+}
+
+/// A hash of the first comparison of `expr`, which equal expressions share.
+fn first_comparison_hash(expr: &ComparisonExpr) -> u64 {
+    let c = match expr {
+        ComparisonExpr::Single(c) => c,
+        ComparisonExpr::Composite(c) => c.left_spine().1,
+    };
+    let mut hasher = DefaultHasher::new();
+    (&c.object_path, c.op, c.negated).hash(&mut hasher);
+    // Equal constants are written the same.
+    c.constant
+        .as_ref()
+        .map(ToString::to_string)
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+fn redact_comparison(expr: ComparisonExpr, placeholder: &str) -> ComparisonExpr {
+    match expr {
+        ComparisonExpr::Single(mut c) => {
+            match &mut c.constant {
+                Some(ComparisonRhs::Value(value)) => redact_value(value, placeholder),
+                Some(ComparisonRhs::List(values)) => {
+                    values.iter_mut().for_each(|v| redact_value(v, placeholder));
+                }
+                Some(ComparisonRhs::Path(_)) | None => {}
+            }
+            c.into()
+        }
+        ComparisonExpr::Composite(c) => c.map_chain(|e| redact_comparison(e, placeholder)).into(),
+    }
+}
+
+fn redact_value(value: &mut StixValue, placeholder: &str) {
+    match value {
+        StixValue::String(s) => *s = placeholder.to_string(),
+        StixValue::Hex(s) => *s = placeholder.bytes().map(|b| format!("{b:02x}")).collect(),
+        StixValue::Binary(s) => *s = base64_encode(placeholder.as_bytes()),
+        _ => {}
+    }
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn normalize_comparison(expr: ComparisonExpr) -> ComparisonExpr {
+    match expr {
+        ComparisonExpr::Single(mut c) => {
+            if c.op == ComparisonOperator::Comparison(ComparisonOp::In)
+                && let Some(ComparisonRhs::List(values)) = &mut c.constant
+            {
+                values.sort();
+            }
+            c.into()
+        }
+        ComparisonExpr::Composite(c) if c.negated => rewrite_negated_group(c, normalize_comparison),
+        ComparisonExpr::Composite(c) => {
+            let op = c.op;
+            let mut operands = Vec::new();
+            flatten_comparison_owned(ComparisonExpr::Composite(c), op, &mut operands);
+            let mut operands: Vec<_> = operands.into_iter().map(normalize_comparison).collect();
+            operands.sort_by_cached_key(ToString::to_string);
+            operands
+                .into_iter()
+                .reduce(|left, right| CompositeComparison::new(left, op, right).into())
+                .expect("a composite has at least two operands")
+        }
+    }
+}
+
+/// The operands of a chain of `op`, in document order. Composites waiting
+/// to be split are kept on a stack, as chains can be too long to recurse
+/// through.
+fn flatten_comparison_owned(expr: ComparisonExpr, op: BooleanOp, out: &mut Vec<ComparisonExpr>) {
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        match expr {
+            ComparisonExpr::Composite(c) if c.op == op && !c.negated => {
+                let (left, right) = c.into_operands();
+                pending.extend([right, left]);
+            }
+            other => out.push(other),
+        }
+    }
+}
+
+fn flatten_pattern_owned(expr: PatternExpr, op: ObservationOp, out: &mut Vec<PatternExpr>) {
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        match expr {
+            PatternExpr::Composite(c) if c.op == op => {
+                let (left, right) = c.into_operands();
+                pending.extend([right, left]);
+            }
+            other => out.push(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ParseOptions, parse_pattern, parse_pattern_with_options};
+
+    fn push(input: &str) -> String {
+        match parse_pattern(input).unwrap() {
+            PatternExpr::Comparison(c) => format!("[{}]", push_negations(c)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_push_negations() {
+        assert_eq!(
+            push("[a:x NOT = 1 AND (a:y NOT > 2 OR a:z NOT <= 3)]"),
+            "[a:x != 1 AND (a:y <= 2 OR a:z > 3)]"
+        );
+    }
+
+    #[test]
+    fn test_push_grouped_negations() {
+        let options = ParseOptions {
+            allow_grouped_negation: true,
+            ..Default::default()
+        };
+        let push = |input: &str| match parse_pattern_with_options(input, &options).unwrap() {
+            PatternExpr::Comparison(c) => format!("[{}]", push_negations(c)),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            push("[NOT (a:x = 1 AND NOT (a:y > 2 OR a:z LIKE 'b'))]"),
+            "[a:x != 1 OR (a:y > 2 OR a:z LIKE 'b')]"
+        );
+        assert_eq!(
+            push("[NOT (a:x = 1 OR a:y IN (1, 2))]"),
+            "[a:x != 1 AND a:y NOT IN (1, 2)]"
+        );
+        // `EXISTS` cannot be negated, so its group stays negated.
+        assert_eq!(
+            push("[NOT (EXISTS a:x OR a:y NOT = 2)]"),
+            "[NOT (EXISTS a:x OR a:y != 2)]"
+        );
+        assert!(equiv_with(
+            "[NOT (a:x = 1 AND a:y = 2)]",
+            "[a:y != 2 OR a:x != 1]",
+            &options
+        ));
+    }
+
+    fn equiv(a: &str, b: &str) -> bool {
+        equiv_with(a, b, &ParseOptions::default())
+    }
+
+    fn equiv_with(a: &str, b: &str, options: &ParseOptions) -> bool {
+        let parse = |input| parse_pattern_with_options(input, options).unwrap();
+        equivalent(&parse(a), &parse(b))
+    }
+
+    #[test]
+    fn test_equivalent() {
+        assert!(equiv("[a:x = 1 AND a:y = 2]", "[a:y = 2 AND a:x = 1]"));
+        assert!(equiv(
+            "[a:x = 1 AND (a:y = 2 AND a:z = 3)]",
+            "[(a:z = 3 AND a:x = 1) AND a:y = 2]"
+        ));
+        assert!(equiv("[a:x NOT = 1]", "[a:x != 1]"));
+        assert!(equiv("[a:x IN (2, 1)]", "[a:x IN (1, 2)]"));
+        assert!(equiv(
+            "([a:x = 1] OR [b:y = 2]) WITHIN 5 SECONDS",
+            "([b:y = 2] OR [a:x = 1]) WITHIN 5 SECONDS"
+        ));
+        assert!(equiv(
+            "[a:x = 1] FOLLOWEDBY ([b:y = 2] FOLLOWEDBY [c:z = 3])",
+            "[a:x = 1] FOLLOWEDBY [b:y = 2] FOLLOWEDBY [c:z = 3]"
+        ));
+
+        assert!(!equiv(
+            "[a:x = 1] FOLLOWEDBY [b:y = 2]",
+            "[b:y = 2] FOLLOWEDBY [a:x = 1]"
+        ));
+        assert!(!equiv("[a:x = 1 AND a:y = 2]", "[a:x = 1 OR a:y = 2]"));
+        assert!(!equiv(
+            "[a:x = 1 AND (a:y = 2 OR a:z = 3)]",
+            "[(a:x = 1 AND a:y = 2) OR a:z = 3]"
+        ));
+        assert!(!equiv("[a:x = 1]", "[a:x = 1.0]"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let changes =
+            |a: &str, b: &str| diff(&parse_pattern(a).unwrap(), &parse_pattern(b).unwrap());
+        assert!(changes("[a:x = 1 AND a:y = 2]", "[a:y = 2 AND a:x = 1]").is_empty());
+        assert_eq!(
+            changes("[file:name = 'a']", "[file:name != 'a']"),
+            ["operator changed = → != at file:name"]
+        );
+        assert_eq!(
+            changes(
+                "[file:name = 'a' AND file:size > 10]",
+                "[file:size > 10 AND file:name = 'b' AND file:size < 100]"
+            ),
+            [
+                "value changed 'a' → 'b' at file:name",
+                "added comparison 'file:size < 100'"
+            ]
+        );
+        assert_eq!(
+            changes("[a:x = 1 OR a:y = 2]", "[a:x = 1 AND a:z IN (1, 2)]"),
+            [
+                "boolean operator changed OR → AND",
+                "removed comparison 'a:y = 2'",
+                "added comparison 'a:z IN (1, 2)'"
+            ]
+        );
+        assert_eq!(
+            changes(
+                "([a:x = 1] AND [b:y = 2]) WITHIN 5 SECONDS",
+                "[a:x = 1] FOLLOWEDBY [b:y = 2] REPEATS 2 TIMES"
+            ),
+            [
+                "removed qualifier 'WITHIN 5 SECONDS'",
+                "observation operator changed AND → FOLLOWEDBY",
+                "added qualifier 'REPEATS 2 TIMES'"
+            ]
+        );
+
+        let options = ParseOptions {
+            allow_grouped_negation: true,
+            ..Default::default()
+        };
+        let negated = parse_pattern_with_options("[NOT (a:x = 1 AND EXISTS a:y)]", &options);
+        assert_eq!(
+            diff(
+                &negated.unwrap(),
+                &parse_pattern("[a:x = 2 AND EXISTS a:y]").unwrap()
+            ),
+            [
+                "removed negation of '(EXISTS a:y AND a:x = 1)'",
+                "value changed 1 → 2 at a:x"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let expr = parse_pattern(
+            "[file:name = 'a.exe' AND file:hashes.MD5 IN ('x', 'y')] FOLLOWEDBY \
+             [file:size > 10 AND file:magic = h'ff' AND artifact:payload_bin = b'aGk='] \
+             WITHIN 5 SECONDS",
+        )
+        .unwrap();
+        assert_eq!(
+            redact(expr, "REDACTED").to_string(),
+            "[file:name = 'REDACTED' AND file:hashes.MD5 IN ('REDACTED', 'REDACTED')] FOLLOWEDBY \
+             [file:size > 10 AND file:magic = h'5245444143544544' AND \
+             artifact:payload_bin = b'UkVEQUNURUQ='] WITHIN 5 SECONDS"
+        );
+        assert_eq!(base64_encode(b"hi"), "aGk=");
+        assert_eq!(base64_encode(b"h"), "aA==");
+        assert_eq!(base64_encode(b""), "");
+    }
+
+    #[test]
+    fn test_strip_qualifiers() {
+        let strip = |input| strip_qualifiers(parse_pattern(input).unwrap()).to_string();
+        assert_eq!(
+            strip("([a:x = 1] REPEATS 2 TIMES AND [b:y = 2]) WITHIN 5 SECONDS"),
+            "[a:x = 1] AND [b:y = 2]"
+        );
+        assert_eq!(
+            strip(
+                "[a:x = 1] FOLLOWEDBY ([b:y = 2] OR [c:z = 3]) START t'2020-01-01T00:00:00Z' \
+                   STOP t'2021-01-01T00:00:00Z' WITHIN 1 SECONDS"
+            ),
+            "[a:x = 1] FOLLOWEDBY ([b:y = 2] OR [c:z = 3])"
+        );
+        assert_eq!(strip("[a:x = 1]"), "[a:x = 1]");
+    }
+
+    #[test]
+    fn test_simplify() {
+        let simple = |input| simplify(parse_pattern(input).unwrap()).to_string();
+        assert_eq!(
+            simple("[a:x NOT != 1 AND (a:y = 2 AND a:x NOT != 1)]"),
+            "[a:x = 1 AND a:y = 2]"
+        );
+        assert_eq!(simple("[(a:x = 1 OR a:x = 1)]"), "[a:x = 1]");
+        assert_eq!(
+            simple("[a:x = 1 OR (a:y > 2 AND (a:z = 3 AND a:y > 2))]"),
+            "[a:x = 1 OR (a:y > 2 AND a:z = 3)]"
+        );
+        assert_eq!(
+            simple("([a:x = 1] OR ([b:y = 2] OR [a:x = 1])) WITHIN 5 SECONDS"),
+            "([a:x = 1] OR [b:y = 2]) WITHIN 5 SECONDS"
+        );
+        assert_eq!(
+            simple("[a:x = 1] AND ([a:x = 1] FOLLOWEDBY [a:x = 1])"),
+            "[a:x = 1] AND ([a:x = 1] FOLLOWEDBY [a:x = 1])"
+        );
+        assert_eq!(simple("[a:x NOT = 1]"), "[a:x NOT = 1]");
+
+        let input =
+            parse_pattern("[a:x = 1 AND (a:y = 2 AND a:x = 1)] OR [b:z NOT != 'c']").unwrap();
+        let simplified = simplify(input.clone());
+        let reparsed = parse_pattern(&simplified.to_string()).unwrap();
+        assert_eq!(reparsed.to_string(), simplified.to_string());
+        assert!(simplified.comparisons().len() < input.comparisons().len());
+    }
+
+    #[test]
+    fn test_operators_without_complement_stay_negated() {
+        assert_eq!(
+            push("[a:x NOT LIKE 'a%' OR a:y NOT IN (1, 2)]"),
+            "[a:x NOT LIKE 'a%' OR a:y NOT IN (1, 2)]"
+        );
+    }
+}
@@ -1,9 +1,12 @@
-from typing import Iterator, Union
+import asyncio
+from concurrent.futures import Executor
+from typing import Any, Iterator, List, Optional, Union
 
 from stix_patterns_parser import stix_patterns_parser
 
 BooleanOp = stix_patterns_parser.BooleanOp
 Comparison = stix_patterns_parser.Comparison
+PatternNode = stix_patterns_parser.PatternNode
 ComparisonOp = stix_patterns_parser.ComparisonOp
 CompositeComparison = stix_patterns_parser.CompositeComparison
 CompositePattern = stix_patterns_parser.CompositePattern
@@ -12,12 +15,261 @@ QualifiedPattern = stix_patterns_parser.QualifiedPattern
 UnaryOp = stix_patterns_parser.UnaryOp
 ObjectPath = stix_patterns_parser.ObjectPath
 PathComponent = stix_patterns_parser.PathComponent
+PropertyKind = stix_patterns_parser.PropertyKind
+ParseWarning = stix_patterns_parser.ParseWarning
+ParseOptions = stix_patterns_parser.ParseOptions
+PatternSummary = stix_patterns_parser.PatternSummary
+ParseStats = stix_patterns_parser.ParseStats
+PatternComment = stix_patterns_parser.PatternComment
+ParserCache = stix_patterns_parser.ParserCache
 
 ComparisonExpression = Union[Comparison, CompositeComparison]
 PatternExpression = Union[ComparisonExpression, CompositePattern, QualifiedPattern]
 
 
 parse = stix_patterns_parser.parse
+parse_comparison_expr = stix_patterns_parser.parse_comparison_expr
+parse_indicator = stix_patterns_parser.parse_indicator
+parse_with_diagnostics = stix_patterns_parser.parse_with_diagnostics
+parse_with_stats = stix_patterns_parser.parse_with_stats
+parse_recover = stix_patterns_parser.parse_recover
+parse_file = stix_patterns_parser.parse_file
+find_comments = stix_patterns_parser.find_comments
+clear_intern_pool = stix_patterns_parser.clear_intern_pool
+format_pattern = stix_patterns_parser.format_pattern
+like_to_regex = stix_patterns_parser.like_to_regex
+like_shape = stix_patterns_parser.like_shape
+summarize = stix_patterns_parser.summarize
+push_negations = stix_patterns_parser.push_negations
+equivalent = stix_patterns_parser.equivalent
+diff = stix_patterns_parser.diff
+flatten = stix_patterns_parser.flatten
+flatten_pattern = stix_patterns_parser.flatten_pattern
+contains_operator = stix_patterns_parser.contains_operator
+operators_used = stix_patterns_parser.operators_used
+operator_table = stix_patterns_parser.operator_table
+qualifiers = stix_patterns_parser.qualifiers
+required_objects = stix_patterns_parser.required_objects
+find_comparisons = stix_patterns_parser.find_comparisons
+locate = stix_patterns_parser.locate
+observation_groups = stix_patterns_parser.observation_groups
+constants = stix_patterns_parser.constants
+depth = stix_patterns_parser.depth
+observation_count = stix_patterns_parser.observation_count
+is_trivial = stix_patterns_parser.is_trivial
+matches_object_type = stix_patterns_parser.matches_object_type
+find_contradictions = stix_patterns_parser.find_contradictions
+lint = stix_patterns_parser.lint
+extract_iocs = stix_patterns_parser.extract_iocs
+redact = stix_patterns_parser.redact
+strip_qualifiers = stix_patterns_parser.strip_qualifiers
+simplify = stix_patterns_parser.simplify
+combine = stix_patterns_parser.combine
+matches_data = stix_patterns_parser.matches_data
+resolve_path = stix_patterns_parser.resolve_path
+apply_op = stix_patterns_parser.apply_op
+to_sql = stix_patterns_parser.to_sql
+to_es_query = stix_patterns_parser.to_es_query
+to_dot = stix_patterns_parser.to_dot
+to_stix_string = stix_patterns_parser.to_stix_string
+to_dict = stix_patterns_parser.to_dict
+to_json = stix_patterns_parser.to_json
+from_dict = stix_patterns_parser.from_dict
+
+
+async def parse_async(
+    pattern: str,
+    options: Optional[ParseOptions] = None,
+    executor: Optional[Executor] = None,
+) -> PatternExpression:
+    """
+    Parse in `executor` (the loop's default one if None) without blocking
+    the event loop. `parse` releases the GIL, so parses run in parallel.
+    """
+    loop = asyncio.get_running_loop()
+    return await loop.run_in_executor(executor, parse, pattern, options)
+
+
+_STIX2_COMPARISONS = {
+    ComparisonOp.GT: "GreaterThanComparisonExpression",
+    ComparisonOp.LT: "LessThanComparisonExpression",
+    ComparisonOp.GE: "GreaterThanEqualComparisonExpression",
+    ComparisonOp.LE: "LessThanEqualComparisonExpression",
+    ComparisonOp.IN: "InComparisonExpression",
+    ComparisonOp.LIKE: "LikeComparisonExpression",
+    ComparisonOp.MATCHES: "MatchesComparisonExpression",
+    ComparisonOp.ISSUBSET: "IsSubsetComparisonExpression",
+    ComparisonOp.ISSUPERSET: "IsSupersetComparisonExpression",
+}
+
+_STIX2_BOOLEANS = {
+    BooleanOp.AND: "AndBooleanExpression",
+    BooleanOp.OR: "OrBooleanExpression",
+}
+
+_STIX2_OBSERVATIONS = {
+    ObservationOp.AND: "AndObservationExpression",
+    ObservationOp.OR: "OrObservationExpression",
+    ObservationOp.FOLLOWEDBY: "FollowedByObservationExpression",
+}
+
+_STIX2_CONSTANTS = {
+    "string": "StringConstant",
+    "int": "IntegerConstant",
+    "float": "FloatConstant",
+    "bool": "BooleanConstant",
+    "timestamp": "TimestampConstant",
+    "hex": "HexConstant",
+    "binary": "BinaryConstant",
+}
+
+
+def to_stix2(pattern: Union[str, PatternExpression]) -> Any:
+    """
+    Build the equivalent `stix2.patterns` object tree, for code written
+    against the `stix2` package. Requires `stix2` to be installed.
+    """
+    from stix2 import patterns
+
+    node = parse(pattern) if isinstance(pattern, str) else pattern
+    return _Stix2Converter(patterns).pattern(node)
+
+
+class _Stix2Converter(object):
+    def __init__(self, patterns: Any) -> None:
+        self.patterns = patterns
+
+    def pattern(self, node: PatternExpression) -> Any:
+        p = self.patterns
+        match node:
+            case QualifiedPattern(pattern=inner):
+                # stix2 holds one qualifier per expression, so several nest
+                # in the order they are written.
+                expr = self.pattern(inner)
+                for qualifier in self.qualifiers(node):
+                    expr = p.QualifiedObservationExpression(expr, qualifier)
+                return expr
+
+            case CompositePattern(op=op, parenthesized=parenthesized):
+                operands = [
+                    self.grouped(self.pattern(n), n, op, CompositePattern)
+                    for n in self.chain(node, CompositePattern)
+                ]
+                expr = getattr(p, _STIX2_OBSERVATIONS[op])(operands)
+                return p.ParentheticalExpression(expr) if parenthesized else expr
+
+            case _:
+                return p.ObservationExpression(self.comparison(node))
+
+    def qualifiers(self, node: QualifiedPattern) -> List[Any]:
+        p = self.patterns
+        qualifiers = []
+        if node.repeat is not None:
+            qualifiers.append(p.RepeatQualifier(node.repeat))
+        if node.within is not None:
+            if not node.within.is_integer():
+                raise ValueError("stix2 only takes whole WITHIN seconds")
+            qualifiers.append(p.WithinQualifier(int(node.within)))
+        if node.start is not None:
+            qualifiers.append(
+                p.StartStopQualifier(
+                    p.TimestampConstant(node.start), p.TimestampConstant(node.stop)
+                )
+            )
+        return qualifiers
+
+    def comparison(self, node: ComparisonExpression) -> Any:
+        p = self.patterns
+        node = self.without_negation(node)
+        if isinstance(node, CompositeComparison):
+            operands = [
+                self.grouped(self.comparison(n), n, node.op, CompositeComparison)
+                for n in map(
+                    self.without_negation, self.chain(node, CompositeComparison)
+                )
+            ]
+            expr = getattr(p, _STIX2_BOOLEANS[node.op])(operands)
+            return p.ParentheticalExpression(expr) if node.parenthesized else expr
+
+        lhs = self.object_path(node.object_path)
+        if node.op == UnaryOp.EXISTS:
+            return p.ExistsExpression(lhs)
+        rhs = self.constant(node.constant, node.constant_type)
+        if node.op in (ComparisonOp.EQ, ComparisonOp.NEQ):
+            # stix2 spells `!=` as a negated equality.
+            negated = node.negated != (node.op == ComparisonOp.NEQ)
+            return p.EqualityComparisonExpression(lhs, rhs, negated)
+        cls = getattr(p, _STIX2_COMPARISONS[node.op])
+        return cls(lhs, rhs, node.negated)
+
+    def object_path(self, path: ObjectPath) -> Any:
+        p = self.patterns
+        components = []
+        for c in path.property_path:
+            if isinstance(c.index, list):
+                raise ValueError("stix2 has no chained list indices")
+            if c.index is not None:
+                components.append(p.ListObjectPathComponent(c.property, c.index))
+            elif c.is_reference:
+                components.append(p.ReferenceObjectPathComponent(c.property))
+            else:
+                components.append(
+                    p.BasicObjectPathComponent(c.property, c.kind == PropertyKind.QUOTED)
+                )
+        return p.ObjectPath(path.object_type, components)
+
+    def constant(self, value: Any, type_name: Optional[Union[str, List[str]]]) -> Any:
+        p = self.patterns
+        if isinstance(value, ObjectPath):
+            return self.object_path(value)
+        if isinstance(value, list):
+            return p.ListConstant(
+                [self.constant(v, t) for v, t in zip(value, type_name or [])]
+            )
+        if type_name == "null":
+            raise ValueError("stix2 has no null constant")
+        return getattr(p, _STIX2_CONSTANTS[type_name])(value)
+
+    @staticmethod
+    def without_negation(node: ComparisonExpression) -> ComparisonExpression:
+        """
+        `node` with the negation of a group pushed down, as stix2 only
+        negates comparisons.
+        """
+        if isinstance(node, CompositeComparison) and node.negated:
+            node = push_negations(node)
+            if isinstance(node, CompositeComparison) and node.negated:
+                raise ValueError("stix2 cannot negate a group holding EXISTS")
+        return node
+
+    def grouped(self, converted: Any, node: Any, op: Any, cls: type) -> Any:
+        """
+        `converted` in parentheses when `node` joins its operands with
+        another operator than `op` without its own, since stix2 writes
+        operands as they are.
+        """
+        if isinstance(node, cls) and node.op != op and not node.parenthesized:
+            return self.patterns.ParentheticalExpression(converted)
+        return converted
+
+    @staticmethod
+    def chain(node: Any, cls: type) -> List[Any]:
+        """
+        Operands of an unparenthesized chain of one operator, as stix2
+        represents `a AND b AND c` with a single node.
+        """
+        operands = []
+        for child in (node.left, node.right):
+            if (
+                isinstance(child, cls)
+                and child.op == node.op
+                and not child.parenthesized
+                and not getattr(child, "negated", False)
+            ):
+                operands.extend(_Stix2Converter.chain(child, cls))
+            else:
+                operands.append(child)
+        return operands
 
 
 class StixPattern(object):
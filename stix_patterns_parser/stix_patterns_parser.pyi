@@ -1,7 +1,7 @@
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 from datetime import datetime
 from enum import Enum
-from typing import List, Literal, Optional, TypeAlias, Union
+from typing import Any, Callable, Dict, List, Literal, Optional, Set, Tuple, TypeAlias, Union
 
 class ComparisonOp(Enum):
     EQ = "="
@@ -15,30 +15,68 @@ class ComparisonOp(Enum):
     MATCHES = "MATCHES"
     ISSUBSET = "ISSUBSET"
     ISSUPERSET = "ISSUPERSET"
+    # Stable across versions, for compact encodings.
+    @property
+    def code(self) -> int: ...
+    @staticmethod
+    def from_code(code: int) -> "ComparisonOp": ...
 
 class UnaryOp(Enum):
     EXISTS = "EXISTS"
+    @property
+    def code(self) -> int: ...
+    @staticmethod
+    def from_code(code: int) -> "UnaryOp": ...
 
 class BooleanOp(Enum):
     AND = "AND"
     OR = "OR"
+    @property
+    def code(self) -> int: ...
+    @staticmethod
+    def from_code(code: int) -> "BooleanOp": ...
 
 class ObservationOp(Enum):
     AND = "AND"
     OR = "OR"
     FOLLOWEDBY = "FOLLOWEDBY"
+    @property
+    def code(self) -> int: ...
+    @staticmethod
+    def from_code(code: int) -> "ObservationOp": ...
+
+class PropertyKind(Enum):
+    PLAIN = 0
+    QUOTED = 1
 
 StixConstant = Union[str, int, float, bool, datetime]
+Operator: TypeAlias = Union[ComparisonOp, UnaryOp]
 
 @dataclass(frozen=True)
 class PathComponent:
     property: str
-    index: Optional[Union[int, Literal["*"]]] = None
+    # A list when indices are chained, as in `b[0][*]`.
+    index: Optional[
+        Union[int, Literal["*"], List[Union[int, Literal["*"]]]]
+    ] = None
+    kind: PropertyKind = PropertyKind.PLAIN
+    @property
+    def is_reference(self) -> bool: ...
 
 @dataclass(frozen=True)
 class ObjectPath:
     object_type: str
     property_path: List[PathComponent]
+    @property
+    def is_custom(self) -> bool: ...
+    @property
+    def start_offset(self) -> Optional[int]: ...
+    @property
+    def end_offset(self) -> Optional[int]: ...
+
+class PatternNode:
+    # Base of the node classes; not instantiable.
+    ...
 
 ComparisonExpression: TypeAlias = Union["Comparison", "CompositeComparison"]
 PatternExpression: TypeAlias = Union[
@@ -46,30 +84,257 @@ PatternExpression: TypeAlias = Union[
 ]
 
 @dataclass(frozen=True)
-class Comparison:
+class Comparison(PatternNode):
     object_path: ObjectPath
-    op: Union[ComparisonOp, UnaryOp]
-    constant: Optional[Union[StixConstant, List[StixConstant]]] = None
+    op: Operator
+    constant: Optional[Union[StixConstant, List[StixConstant], ObjectPath]] = None
     negated: bool = False
+    # One of "string", "int", "float", "bool", "timestamp", "hex", "binary"
+    # or "null", or one per list element. Inferred from the value when omitted.
+    constant_type: Optional[Union[str, List[str]]] = None
+    @property
+    def raw(self) -> Optional[str]: ...
+    # The list was written with ranges, parsed with ParseOptions(allow_ranges=True).
+    @property
+    def has_ranges(self) -> bool: ...
+    @property
+    def start_offset(self) -> Optional[int]: ...
+    @property
+    def end_offset(self) -> Optional[int]: ...
+    @property
+    def is_single_observation(self) -> bool: ...
+    @property
+    def is_composite(self) -> bool: ...
+    @property
+    def is_qualified(self) -> bool: ...
 
 @dataclass(frozen=True)
-class CompositeComparison:
+class CompositeComparison(PatternNode):
     left: ComparisonExpression
     op: BooleanOp
     right: ComparisonExpression
+    # Source grouping, ignored by ==.
+    parenthesized: bool = field(default=False, compare=False)
+    # `NOT (...)`, only parsed with ParseOptions(allow_grouped_negation=True).
+    negated: bool = False
+    @property
+    def start_offset(self) -> Optional[int]: ...
+    @property
+    def end_offset(self) -> Optional[int]: ...
+    @property
+    def is_single_observation(self) -> bool: ...
+    @property
+    def is_composite(self) -> bool: ...
+    @property
+    def is_qualified(self) -> bool: ...
 
 @dataclass(frozen=True)
-class CompositePattern:
+class CompositePattern(PatternNode):
     left: PatternExpression
     op: ObservationOp
     right: PatternExpression
+    # Source grouping, ignored by ==.
+    parenthesized: bool = field(default=False, compare=False)
+    @property
+    def start_offset(self) -> Optional[int]: ...
+    @property
+    def end_offset(self) -> Optional[int]: ...
+    @property
+    def is_single_observation(self) -> bool: ...
+    @property
+    def is_composite(self) -> bool: ...
+    @property
+    def is_qualified(self) -> bool: ...
 
 @dataclass(frozen=True)
-class QualifiedPattern:
+class QualifiedPattern(PatternNode):
     pattern: PatternExpression
     repeat: Optional[int] = None
     within: Optional[float] = None
     start: Optional[datetime] = None
     stop: Optional[datetime] = None
+    @property
+    def within_raw(self) -> Optional[str]: ...
+    @property
+    def start_offset(self) -> Optional[int]: ...
+    @property
+    def end_offset(self) -> Optional[int]: ...
+    @property
+    def is_single_observation(self) -> bool: ...
+    @property
+    def is_composite(self) -> bool: ...
+    @property
+    def is_qualified(self) -> bool: ...
+
+@dataclass(frozen=True)
+class ParseWarning:
+    message: str
+    start: int
+    end: int
+
+class ParseOptions:
+    allow_empty_lists: bool
+    allow_mixed_lists: bool
+    validate_regex: bool
+    case_insensitive_keywords: bool
+    allow_path_rhs: bool
+    max_operands: Optional[int]
+    timeout_ms: Optional[int]
+    allowed_operators: Optional[Set[ComparisonOp]]
+    allow_null: bool
+    normalize_object_types: bool
+    normalize_hash_names: bool
+    allow_bare_observation: bool
+    allow_reversed_comparison: bool
+    allow_comments: bool
+    intern_strings: bool
+    allow_double_quotes: bool
+    allow_between: bool
+    allow_ranges: bool
+    allow_grouped_negation: bool
+    allow_exponent: bool
+    strict: bool
+    def __init__(
+        self,
+        *,
+        allow_empty_lists: bool = False,
+        allow_mixed_lists: bool = False,
+        validate_regex: bool = False,
+        case_insensitive_keywords: bool = False,
+        allow_path_rhs: bool = False,
+        max_operands: Optional[int] = None,
+        timeout_ms: Optional[int] = None,
+        allowed_operators: Optional[Set[ComparisonOp]] = None,
+        allow_null: bool = False,
+        normalize_object_types: bool = False,
+        normalize_hash_names: bool = False,
+        allow_bare_observation: bool = False,
+        allow_reversed_comparison: bool = False,
+        allow_comments: bool = False,
+        intern_strings: bool = False,
+        allow_double_quotes: bool = False,
+        allow_between: bool = False,
+        allow_ranges: bool = False,
+        allow_grouped_negation: bool = False,
+        allow_exponent: bool = False,
+        strict: bool = False,
+    ) -> None: ...
+
+@dataclass(frozen=True)
+class PatternSummary:
+    object_types: List[str]
+    operators_used: List[Operator]
+    has_qualifiers: bool
+    observation_count: int
+
+@dataclass(frozen=True)
+class ParseStats:
+    parse_duration_us: int
+    node_count: int
+    input_bytes: int
+
+@dataclass(frozen=True)
+class PatternComment:
+    text: str
+    start: int
+    end: int
+
+class ParserCache:
+    # Least-recently-used cache of parsed patterns, keyed on the input. A hit
+    # returns the object parsed for the input the first time.
+    maxsize: int
+    hits: int
+    misses: int
+    def __init__(
+        self, maxsize: int = 128, options: Optional[ParseOptions] = None
+    ) -> None: ...
+    def parse(self, pattern: str) -> PatternExpression: ...
+    def clear(self) -> None: ...
+    def __len__(self) -> int: ...
 
-def parse(pattern: str) -> PatternExpression: ...
+def parse(
+    pattern: str, options: Optional[ParseOptions] = None
+) -> PatternExpression: ...
+def parse_comparison_expr(
+    expression: str, options: Optional[ParseOptions] = None
+) -> ComparisonExpression: ...
+def parse_indicator(
+    indicator: Dict[str, Any], options: Optional[ParseOptions] = None
+) -> PatternExpression: ...
+def parse_with_diagnostics(
+    pattern: str, options: Optional[ParseOptions] = None
+) -> Tuple[PatternExpression, List[ParseWarning]]: ...
+def parse_with_stats(
+    pattern: str, options: Optional[ParseOptions] = None
+) -> Tuple[PatternExpression, ParseStats]: ...
+def parse_recover(
+    pattern: str, options: Optional[ParseOptions] = None
+) -> Tuple[Optional[PatternExpression], List[str]]: ...
+def parse_file(
+    path: str, options: Optional[ParseOptions] = None
+) -> List[Tuple[int, Optional[PatternExpression], Optional[str]]]: ...
+def find_comments(pattern: str) -> List[PatternComment]: ...
+def clear_intern_pool() -> None: ...
+def format_pattern(pattern: str, indent: int = 2) -> str: ...
+def like_to_regex(pattern: str) -> str: ...
+def like_shape(
+    comparison: Comparison,
+) -> Literal["exact", "prefix", "suffix", "contains", "complex"]: ...
+def summarize(pattern: str) -> PatternSummary: ...
+def push_negations(expr: ComparisonExpression) -> ComparisonExpression: ...
+def equivalent(p1: str, p2: str) -> bool: ...
+def diff(p1: str, p2: str) -> List[str]: ...
+def flatten(
+    expr: ComparisonExpression,
+) -> Tuple[Optional[BooleanOp], List[ComparisonExpression]]: ...
+def flatten_pattern(
+    expr: PatternExpression,
+) -> Tuple[Optional[ObservationOp], List[PatternExpression]]: ...
+def contains_operator(pattern: str, op: Operator) -> bool: ...
+def operators_used(pattern: str) -> Set[Operator]: ...
+def operator_table() -> List[Dict[str, Any]]: ...
+def qualifiers(pattern: str) -> List[Dict[str, Any]]: ...
+# "any_of" is None when an observation has too many alternatives to list.
+def required_objects(pattern: str) -> List[Dict[str, Optional[List[Any]]]]: ...
+def find_comparisons(
+    pattern: str, object_type: str, property: str, ignore_indices: bool = False
+) -> List[Comparison]: ...
+def locate(
+    pattern: str, predicate: Callable[[PatternNode], bool]
+) -> Optional[Tuple[int, int]]: ...
+def observation_groups(pattern: str) -> List[List[Comparison]]: ...
+def constants(pattern: str) -> List[StixConstant]: ...
+def depth(pattern: str) -> int: ...
+def observation_count(pattern: str) -> int: ...
+def is_trivial(pattern: str) -> bool: ...
+def matches_object_type(pattern: str, glob: str) -> bool: ...
+def find_contradictions(pattern: str) -> List[str]: ...
+def lint(pattern: str) -> List[str]: ...
+def extract_iocs(pattern: str) -> Dict[str, List[str]]: ...
+def redact(pattern: str, placeholder: str = "REDACTED") -> str: ...
+def strip_qualifiers(pattern: str) -> str: ...
+def simplify(pattern: str) -> str: ...
+def combine(patterns: List[str], op: ObservationOp) -> str: ...
+def matches_data(pattern: str, data: Dict[str, Any]) -> bool: ...
+def resolve_path(data: Dict[str, Any], object_path: ObjectPath) -> List[Any]: ...
+def apply_op(
+    op: ComparisonOp,
+    lhs: Any,
+    rhs: Union[StixConstant, List[StixConstant]],
+    rhs_type: Optional[Union[str, List[str]]] = None,
+) -> bool: ...
+def to_sql(
+    pattern: str, table_map: Dict[str, str]
+) -> Tuple[str, List[StixConstant]]: ...
+def to_es_query(pattern: str, field_map: Dict[str, str]) -> Dict[str, Any]: ...
+def to_dot(pattern: str) -> str: ...
+def to_stix_string(
+    expr: PatternExpression,
+    preserve_grouping: bool = False,
+    *,
+    min_decimals: int = 1,
+    scientific_threshold: Optional[int] = None,
+) -> str: ...
+def to_dict(expr: PatternExpression) -> Dict[str, Any]: ...
+def to_json(expr: PatternExpression) -> str: ...
+def from_dict(data: Dict[str, Any]) -> PatternExpression: ...
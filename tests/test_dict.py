@@ -0,0 +1,65 @@
+"""
+Tests of the dict conversion of patterns. Run with `python -m unittest`
+once the module is built.
+"""
+
+import unittest
+
+from stix_patterns_parser import ParseOptions, from_dict, parse, to_dict
+
+# One pattern per kind of node and constant.
+PATTERNS = [
+    "[file:name = 'a']",
+    "[file:hashes.'SHA-256' = 'ab' AND file:x[0][*] IN (1, 2.5)]",
+    "[file:size NOT > 1 OR EXISTS file:name]",
+    "[a:t = t'2023-01-01T00:00:00.5Z' AND a:h = h'ff' AND a:b = b'YWI=']",
+    "[file:parent_directory_ref.path = file:name]",
+    "[a:x = null]",
+    "[NOT (a:x = 1 AND a:y = 2)]",
+    "[a:x = 1] AND ([a:y = 2] OR [a:z = 3]) FOLLOWEDBY [a:w = 4]",
+    "[a:x = 1] REPEATS 2 TIMES WITHIN 5.5 SECONDS"
+    " START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'",
+]
+
+OPTIONS = ParseOptions(
+    allow_path_rhs=True, allow_null=True, allow_grouped_negation=True
+)
+
+
+class DictTest(unittest.TestCase):
+    def test_round_trip(self) -> None:
+        for pattern in PATTERNS:
+            node = parse(pattern, OPTIONS)
+            with self.subTest(pattern=pattern):
+                self.assertEqual(from_dict(to_dict(node)), node)
+
+    def test_errors(self) -> None:
+        data = to_dict(parse("[a:x = 1] AND [a:y = 2]"))
+
+        bad_type = dict(data, type="Pattern")
+        with self.assertRaisesRegex(
+            ValueError,
+            r"^invalid pattern dict at pattern: expected type Comparison or "
+            r"CompositeComparison or CompositePattern or QualifiedPattern, "
+            r'found "Pattern"$',
+        ):
+            from_dict(bad_type)
+
+        missing = dict(data)
+        del missing["right"]
+        with self.assertRaisesRegex(
+            ValueError, r"^invalid pattern dict at pattern: missing key \"right\"$"
+        ):
+            from_dict(missing)
+
+        left = dict(data["left"])
+        del left["object_path"]
+        with self.assertRaisesRegex(
+            ValueError,
+            r"^invalid pattern dict at pattern\.left: missing key \"object_path\"$",
+        ):
+            from_dict(dict(data, left=left))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,45 @@
+"""
+Tests of the Python AST classes. Run with `python -m unittest` once the
+module is built.
+"""
+
+import pickle
+import unittest
+
+from stix_patterns_parser import ParseOptions, parse
+
+
+class NodesTest(unittest.TestCase):
+    def test_property_path(self) -> None:
+        path = parse("[file:parent_directory_ref.path = 'a']").object_path
+        components = path.property_path
+        # Built once, each access getting its own list of them.
+        self.assertIs(path.property_path[0], components[0])
+        components.clear()
+        self.assertEqual(
+            [c.property for c in path.property_path], ["parent_directory_ref", "path"]
+        )
+
+    def test_pickle(self) -> None:
+        options = ParseOptions(allow_path_rhs=True, allow_grouped_negation=True)
+        for pattern in [
+            "[file:hashes.'SHA-256' = 'ab' AND file:x[0][*] IN (1, 2.5)]",
+            "[a:t = t'2023-01-01T00:00:00.5Z' OR a:h != h'ff' OR EXISTS a:b]",
+            "[file:parent_directory_ref.path = file:name]",
+            "[NOT (a:x = 1 AND a:y = 2)]",
+            "[a:x = 1] AND [a:y = 2] FOLLOWEDBY [a:w = 4]",
+            "[a:x = 1] REPEATS 2 TIMES WITHIN 5.5 SECONDS"
+            " START t'2023-01-01T00:00:00Z' STOP t'2023-01-02T00:00:00Z'",
+        ]:
+            node = parse(pattern, options)
+            with self.subTest(pattern=pattern):
+                self.assertEqual(pickle.loads(pickle.dumps(node)), node)
+
+        path = parse("[file:x[0].'y' = 1]").object_path
+        self.assertEqual(pickle.loads(pickle.dumps(path)), path)
+        component = path.property_path[1]
+        self.assertEqual(pickle.loads(pickle.dumps(component)), component)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,38 @@
+"""
+Tests of parsing from several threads at once, which `parse` allows by
+releasing the GIL. Run with `python -m unittest` once the module is built.
+"""
+
+import asyncio
+import unittest
+from concurrent.futures import ThreadPoolExecutor
+from typing import List
+
+from stix_patterns_parser import PatternExpression, parse, parse_async
+
+PATTERNS = [
+    f"[file:size = {i}] FOLLOWEDBY [file:name = 'f{i}']" for i in range(64)
+]
+
+
+class ThreadsTest(unittest.TestCase):
+    def setUp(self) -> None:
+        self.expected = [parse(pattern) for pattern in PATTERNS]
+
+    def test_parse(self) -> None:
+        with ThreadPoolExecutor(max_workers=8) as executor:
+            results = list(executor.map(parse, PATTERNS))
+        self.assertEqual(results, self.expected)
+
+    def test_parse_async(self) -> None:
+        async def parse_all() -> List[PatternExpression]:
+            with ThreadPoolExecutor(max_workers=8) as executor:
+                return await asyncio.gather(
+                    *(parse_async(pattern, executor=executor) for pattern in PATTERNS)
+                )
+
+        self.assertEqual(asyncio.run(parse_all()), self.expected)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,136 @@
+"""
+Tests of the stix2 conversion against a stand-in for `stix2.patterns`, so
+they run without the `stix2` package: each class builds a tuple of its name
+and arguments. Run with `python -m unittest` once the module is built.
+"""
+
+import unittest
+from typing import Any
+
+from stix_patterns_parser import (
+    ParseOptions,
+    QualifiedPattern,
+    _Stix2Converter,
+    parse,
+    to_stix2,
+)
+
+
+class _Patterns(object):
+    def __getattr__(self, name: str) -> Any:
+        return lambda *args: (name, *args)
+
+
+def convert(pattern: str, options: Any = None) -> Any:
+    return _Stix2Converter(_Patterns()).pattern(parse(pattern, options))
+
+
+def path(object_type: str, prop: str) -> Any:
+    return ("ObjectPath", object_type, [("BasicObjectPathComponent", prop, False)])
+
+
+class ToStix2Test(unittest.TestCase):
+    def test_comparison(self) -> None:
+        self.assertEqual(
+            convert("[file:name != 'a']"),
+            (
+                "ObservationExpression",
+                (
+                    "EqualityComparisonExpression",
+                    path("file", "name"),
+                    ("StringConstant", "a"),
+                    True,
+                ),
+            ),
+        )
+
+    def test_negated_group(self) -> None:
+        options = ParseOptions(allow_grouped_negation=True)
+        self.assertEqual(
+            convert("[NOT (file:name = 'a' AND file:size > 1)]", options),
+            convert("[(file:name != 'a' OR file:size <= 1)]"),
+        )
+        with self.assertRaises(ValueError):
+            convert("[NOT (EXISTS file:name AND file:size > 1)]", options)
+
+    def test_grouping(self) -> None:
+        # De Morgan turns the group into an OR under the AND, which has no
+        # parentheses of its own.
+        options = ParseOptions(allow_grouped_negation=True)
+        converted = convert(
+            "[file:size > 1 AND NOT (file:name = 'a' AND file:name = 'b')]", options
+        )
+        pushed = convert("[file:name != 'a' OR file:name != 'b']")[1]
+        self.assertEqual(converted[1][1][1], ("ParentheticalExpression", pushed))
+        converted = convert(
+            "[file:name = 'a'] AND ([file:name = 'b'] OR [file:name = 'c'])"
+        )
+        self.assertEqual(converted[1][1][0], "ParentheticalExpression")
+
+    def test_qualifiers(self) -> None:
+        observation = convert("[file:name = 'a']")
+        self.assertEqual(
+            convert("[file:name = 'a'] REPEATS 2 TIMES WITHIN 10 SECONDS"),
+            (
+                "QualifiedObservationExpression",
+                (
+                    "QualifiedObservationExpression",
+                    observation,
+                    ("RepeatQualifier", 2),
+                ),
+                ("WithinQualifier", 10),
+            ),
+        )
+        within = convert("[file:name = 'a'] WITHIN 10 SECONDS")[2][1]
+        self.assertIs(type(within), int)
+        with self.assertRaises(ValueError):
+            convert("[file:name = 'a'] WITHIN 1.5 SECONDS")
+
+        # A node built with several qualifiers nests them in written order.
+        node = QualifiedPattern(
+            parse("[file:name = 'a']"),
+            repeat=2,
+            within=10.0,
+            start="2023-01-01T00:00:00Z",
+            stop="2023-01-02T00:00:00Z",
+        )
+        converted = _Stix2Converter(_Patterns()).pattern(node)
+        self.assertEqual(
+            [q[0] for q in (converted[2], converted[1][2], converted[1][1][2])],
+            ["StartStopQualifier", "WithinQualifier", "RepeatQualifier"],
+        )
+        self.assertEqual(converted[1][1][1], observation)
+
+
+try:
+    import stix2  # noqa: F401
+
+    HAVE_STIX2 = True
+except ImportError:
+    HAVE_STIX2 = False
+
+
+@unittest.skipUnless(HAVE_STIX2, "stix2 is not installed")
+class RealStix2Test(unittest.TestCase):
+    def test_round_trip(self) -> None:
+        options = ParseOptions(allow_grouped_negation=True)
+        for pattern, expected in [
+            (
+                "[file:name = 'a'] REPEATS 2 TIMES WITHIN 10 SECONDS",
+                "[file:name = 'a'] REPEATS 2 TIMES WITHIN 10 SECONDS",
+            ),
+            (
+                "[file:size > 1 AND NOT (file:name = 'a' AND file:name = 'b')]",
+                "[file:size > 1 AND (file:name != 'a' OR file:name != 'b')]",
+            ),
+            (
+                "([file:name = 'a'] OR [file:name = 'b']) FOLLOWEDBY [file:name = 'c']",
+                "([file:name = 'a'] OR [file:name = 'b']) FOLLOWEDBY [file:name = 'c']",
+            ),
+        ]:
+            converted = to_stix2(parse(pattern, options))
+            self.assertEqual(parse(str(converted)), parse(expected))
+
+
+if __name__ == "__main__":
+    unittest.main()